@@ -1,116 +1,439 @@
-use key::Key;
+use aesni;
+use bitslice;
+use key::{Key, KeySchedule};
+use buffer::{Buffer, LockError, ROUND_KEYS};
+#[cfg(feature = "std")]
+use secret;
 use state::State;
+use ttable;
+use vperm;
+
+// The trace goes to stdout, so without std it's only type checked
+#[cfg(feature = "std")]
+macro_rules! trace {
+	($debug:expr, $($arg:tt)*) => { if $debug { println!($($arg)*); } }
+}
+
+#[cfg(not(feature = "std"))]
+macro_rules! trace {
+	($debug:expr, $($arg:tt)*) => { if false && $debug { let _ = format_args!($($arg)*); } }
+}
+
+// Which implementation of the cipher to run.  They all give the same results;
+// the reference one follows FIPS-197 step by step and is the only one that can
+// print a trace of the intermediate values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+	Reference,
+	TTable,
+	AesNi,
+	// Constant time, for keys that must not leak through cache timing
+	Bitsliced,
+	// Constant time with SSSE3, for x86 machines without AES-NI
+	VectorPermute
+}
+
+impl Backend {
+	// The fastest backend the CPU we're running on supports
+	pub fn detect() -> Backend {
+		if aesni::is_available() {
+			Backend::AesNi
+		} else if vperm::is_available() {
+			Backend::VectorPermute
+		} else {
+			Backend::TTable
+		}
+	}
+
+	// Hardware backends fall back to software when the CPU doesn't have
+	// the instructions they need, and a constant time one only falls back to
+	// another constant time one
+	fn or_fallback(self) -> Backend {
+		match self {
+			Backend::AesNi if !aesni::is_available() => Backend::detect(),
+			Backend::VectorPermute if !vperm::is_available() => Backend::Bitsliced,
+			backend => backend
+		}
+	}
+}
+
+// The constant time backends expand the key without the S-box tables too
+fn create_schedule(key: &Key, backend: Backend) -> Result<KeySchedule, LockError> {
+	let key_schedule = match backend {
+		Backend::Bitsliced | Backend::VectorPermute => bitslice::create_schedule(key),
+		_ => key.expand_unlocked()
+	};
+
+	key_schedule.try_like(key)
+}
+
+// The constructors that can't return an error panic when a SecretKey's
+// derived keys can't be locked too
+fn or_panic<T>(result: Result<T, LockError>) -> T {
+	result.unwrap_or_else(|error| panic!("Couldn't lock memory for key material: {}", error))
+}
+
+// How many blocks the multi-buffer functions run through the rounds at once,
+// enough to cover the latency of aesenc or of a round of table lookups
+#[cfg(feature = "std")]
+const LANES: usize = 8;
+
+// Splits jobs into groups of at most LANES that share a backend and number of
+// rounds, so that each group can go through the rounds in lockstep.  The
+// groups hold the indices of their jobs
+#[cfg(feature = "std")]
+fn lanes<I: Iterator<Item = (Backend, usize)>>(jobs: I) -> Vec<Vec<usize>> {
+	let mut open: Vec<((Backend, usize), Vec<usize>)> = Vec::new();
+	let mut groups = Vec::new();
+
+	for (i, kind) in jobs.enumerate() {
+		let pos = match open.iter().position(|(k, _)| *k == kind) {
+			Some(pos) => pos,
+			None => {
+				open.push((kind, Vec::with_capacity(LANES)));
+				open.len() - 1
+			}
+		};
+
+		open[pos].1.push(i);
+		if open[pos].1.len() == LANES {
+			groups.push(open.swap_remove(pos).1);
+		}
+	}
+
+	groups.extend(open.into_iter().map(|(_, group)| group));
+	groups
+}
+
+// An Encryptor for a key given in hex, with its schedule expanded when the
+// crate using it is compiled and kept in a static, so it ends up in flash
+// on an embedded target.  A malformed key is a compile error
+#[macro_export]
+macro_rules! encryptor {
+	($hex:expr) => {{
+		static KEY: $crate::key::Key = $crate::key::Key::from_hex($hex);
+		static SCHEDULE: $crate::key::KeySchedule = KEY.create_schedule();
+
+		$crate::aes::Encryptor::with_schedule(KEY.clone(), &SCHEDULE)
+	}};
+}
 
 pub struct Encryptor {
-	key: Key
+	key_schedule: KeySchedule,
+	backend: Backend,
+	simd_keys: Buffer<[u8; 16], ROUND_KEYS>,
+	bitsliced_keys: Buffer<[u64; 8], ROUND_KEYS>
 }
 
 pub struct Decryptor {
-	key: Key
+	key_schedule: KeySchedule,
+	backend: Backend,
+	inverse_schedule: Buffer<u32, 60>,
+	simd_keys: Buffer<[u8; 16], ROUND_KEYS>,
+	bitsliced_keys: Buffer<[u64; 8], ROUND_KEYS>
 }
 
 impl Encryptor {
 	pub fn using(key: Key) -> Encryptor {
-		Encryptor{key}
+		Encryptor::with_backend(key, Backend::detect())
+	}
+
+	pub fn with_backend(key: Key, backend: Backend) -> Encryptor {
+		or_panic(Encryptor::create(key, backend))
+	}
+
+	// For a SecretKey's key, with the error if the round keys worked out from
+	// it can't be locked as well
+	#[cfg(feature = "std")]
+	pub fn try_using(key: Key) -> Result<Encryptor, secret::Error> {
+		Encryptor::try_with_backend(key, Backend::detect())
+	}
+
+	#[cfg(feature = "std")]
+	pub fn try_with_backend(key: Key, backend: Backend) -> Result<Encryptor, secret::Error> {
+		Encryptor::create(key, backend)
+	}
+
+	fn create(key: Key, backend: Backend) -> Result<Encryptor, LockError> {
+		let backend = backend.or_fallback();
+		let key_schedule = create_schedule(&key, backend)?;
+		Encryptor::with_parts(key, key_schedule, backend)
+	}
+
+	// Skips the key expansion, for a schedule that was worked out at compile
+	// time, whichever backend is picked.  See encryptor!
+	pub fn with_schedule(key: Key, key_schedule: &KeySchedule) -> Encryptor {
+		if !key_schedule.is_for(&key) { panic!("Key schedule isn't for this key!"); }
+
+		let key_schedule = key_schedule.clone().like(&key);
+		or_panic(Encryptor::with_parts(key, key_schedule, Backend::detect()))
+	}
+
+	fn with_parts(key: Key, key_schedule: KeySchedule, backend: Backend) -> Result<Encryptor, LockError> {
+		let simd_keys = match backend {
+			Backend::AesNi => aesni::round_keys(&key_schedule),
+			Backend::VectorPermute => vperm::encryption_keys(&key_schedule),
+			_ => Buffer::new()
+		};
+		let bitsliced_keys = match backend {
+			Backend::Bitsliced => bitslice::round_keys(&key_schedule),
+			_ => Buffer::new()
+		};
+
+		// Derived keys are locked along with a SecretKey's
+		let locked = key.is_locked();
+		let simd_keys = simd_keys.try_like(locked)?;
+		let bitsliced_keys = bitsliced_keys.try_like(locked)?;
+
+		Ok(Encryptor{key_schedule, backend, simd_keys, bitsliced_keys})
+	}
+
+	pub fn backend(&self) -> Backend {
+		self.backend
 	}
 
 	pub fn encrypt(&self, input: &[u8], debug: bool) -> [u8; 16] {
 		if input.len() != 16 { panic!("Can only encrypt 16 byte blocks!"); }
 
-		let key_schedule = self.key.create_schedule();
+		match self.backend {
+			Backend::TTable if !debug => ttable::encrypt(&self.key_schedule, input),
+			Backend::AesNi if !debug => aesni::encrypt(&self.simd_keys, input),
+			Backend::Bitsliced if !debug => bitslice::encrypt(&self.bitsliced_keys, input),
+			Backend::VectorPermute if !debug => vperm::encrypt(&self.simd_keys, input),
+			_ => self.encrypt_reference(input, debug)
+		}
+	}
+
+	// Encrypts a run of independent blocks, which lets the bitsliced backend
+	// work on eight of them at a time
+	#[cfg(feature = "std")]
+	pub fn encrypt_blocks(&self, input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.encrypt_blocks_in_place(&mut output);
+		output
+	}
+
+	pub fn encrypt_blocks_in_place(&self, data: &mut [u8]) {
+		if !data.len().is_multiple_of(16) { panic!("Can only encrypt 16 byte blocks!"); }
+
+		match self.backend {
+			Backend::Bitsliced => bitslice::encrypt_blocks(&self.bitsliced_keys, data),
+			_ => for block in data.chunks_mut(16) {
+				let output = self.encrypt(block, false);
+				block.copy_from_slice(&output);
+			}
+		}
+	}
+
+	#[cfg(feature = "std")]
+	// Encrypts one block with each encryptor, which can all have different
+	// keys.  Blocks are run through the rounds together where the backend
+	// allows it, rather than each one waiting for the one before
+	pub fn encrypt_many(jobs: &[(&Encryptor, &[u8])]) -> Vec<[u8; 16]> {
+		if jobs.iter().any(|&(_, input)| input.len() != 16) { panic!("Can only encrypt 16 byte blocks!"); }
+
+		let mut output = vec![[0; 16]; jobs.len()];
+		for group in lanes(jobs.iter().map(|&(encryptor, _)| (encryptor.backend, encryptor.key_schedule.rounds()))) {
+			let results = match jobs[group[0]].0.backend {
+				Backend::TTable => ttable::encrypt_lanes(
+					&group.iter().map(|&i| (&jobs[i].0.key_schedule, jobs[i].1)).collect::<Vec<_>>()
+				),
+				Backend::AesNi => aesni::encrypt_lanes(
+					&group.iter().map(|&i| (&jobs[i].0.simd_keys[..], jobs[i].1)).collect::<Vec<_>>()
+				),
+				_ => group.iter().map(|&i| jobs[i].0.encrypt(jobs[i].1, false)).collect()
+			};
+
+			for (&i, result) in group.iter().zip(results) {
+				output[i] = result;
+			}
+		}
+
+		output
+	}
+
+	fn encrypt_reference(&self, input: &[u8], debug: bool) -> [u8; 16] {
+		let key_schedule = &self.key_schedule;
 
 		let mut state = State::from_slice(input);
-		if debug { println!("round[ 0].input    {}", state); }
+		trace!(debug, "round[ 0].input    {}", state);
 
 		let ks0 = &key_schedule[0];
-		if debug { println!("round[ 0].k_sch    {:0>8x}{:0>8x}{:0>8x}{:0>8x}", ks0[0], ks0[1], ks0[2], ks0[3])}
+		trace!(debug, "round[ 0].k_sch    {:0>8x}{:0>8x}{:0>8x}{:0>8x}", ks0[0], ks0[1], ks0[2], ks0[3]);
 		state = state.add_round_key(ks0);
 
-		let nr = match self.key.get_size_bits() {
-			128 => {10},
-			192 => {12},
-			256 => {14},
-			_ => {panic!("Invalid key size!")}
-		};
+		let nr = key_schedule.rounds();
 
 		for round in 1..nr {
-			if debug { println!("round[{: >2}].start    {}", round, state); }
+			trace!(debug, "round[{: >2}].start    {}", round, state);
 			state = state.sub_bytes();
-			if debug { println!("round[{: >2}].s_box    {}", round, state); }
+			trace!(debug, "round[{: >2}].s_box    {}", round, state);
 			state = state.shift_rows();
-			if debug { println!("round[{: >2}].s_row    {}", round, state); }
+			trace!(debug, "round[{: >2}].s_row    {}", round, state);
 			state = state.mix_columns();
-			if debug { println!("round[{: >2}].m_col    {}", round, state); }
+			trace!(debug, "round[{: >2}].m_col    {}", round, state);
 
 			let ks = &key_schedule[round];
-			if debug { println!("round[{: >2}].k_sch    {:0>8x}{:0>8x}{:0>8x}{:0>8x}", round, ks[0], ks[1], ks[2], ks[3])}
+			trace!(debug, "round[{: >2}].k_sch    {:0>8x}{:0>8x}{:0>8x}{:0>8x}", round, ks[0], ks[1], ks[2], ks[3]);
 			state = state.add_round_key(ks);
 		}
 
-		if debug { println!("round[{}].start    {}", nr, state); }
+		trace!(debug, "round[{}].start    {}", nr, state);
 		state = state.sub_bytes();
-		if debug { println!("round[{}].s_box    {}", nr, state); }
+		trace!(debug, "round[{}].s_box    {}", nr, state);
 		state = state.shift_rows();
-		if debug { println!("round[{}].s_row    {}", nr, state); }
+		trace!(debug, "round[{}].s_row    {}", nr, state);
 
 		let ks = &key_schedule[nr];
-		if debug { println!("round[{}].k_sch    {:0>8x}{:0>8x}{:0>8x}{:0>8x}", nr, ks[0], ks[1], ks[2], ks[3]); }
+		trace!(debug, "round[{}].k_sch    {:0>8x}{:0>8x}{:0>8x}{:0>8x}", nr, ks[0], ks[1], ks[2], ks[3]);
 		state = state.add_round_key(&key_schedule[nr]);
-		if debug { println!("round[{}].output   {}", nr, state); }
+		trace!(debug, "round[{}].output   {}", nr, state);
 
 		state.to_byte_array()
 	}
 }
 
-
 impl Decryptor {
 	pub fn using(key: Key) -> Decryptor {
-		Decryptor{key}
+		Decryptor::with_backend(key, Backend::detect())
+	}
+
+	pub fn with_backend(key: Key, backend: Backend) -> Decryptor {
+		or_panic(Decryptor::create(key, backend))
+	}
+
+	// For a SecretKey's key, with the error if the round keys worked out from
+	// it can't be locked as well
+	#[cfg(feature = "std")]
+	pub fn try_using(key: Key) -> Result<Decryptor, secret::Error> {
+		Decryptor::try_with_backend(key, Backend::detect())
+	}
+
+	#[cfg(feature = "std")]
+	pub fn try_with_backend(key: Key, backend: Backend) -> Result<Decryptor, secret::Error> {
+		Decryptor::create(key, backend)
+	}
+
+	fn create(key: Key, backend: Backend) -> Result<Decryptor, LockError> {
+		let backend = backend.or_fallback();
+		let key_schedule = create_schedule(&key, backend)?;
+		let inverse_schedule = match backend {
+			Backend::TTable => ttable::inverse_schedule(&key_schedule),
+			_ => Buffer::new()
+		};
+		let simd_keys = match backend {
+			Backend::AesNi => aesni::inverse_keys(&aesni::expand_key(&key)),
+			Backend::VectorPermute => vperm::decryption_keys(&key_schedule),
+			_ => Buffer::new()
+		};
+		let bitsliced_keys = match backend {
+			Backend::Bitsliced => bitslice::round_keys(&key_schedule),
+			_ => Buffer::new()
+		};
+
+		let locked = key.is_locked();
+		let inverse_schedule = inverse_schedule.try_like(locked)?;
+		let simd_keys = simd_keys.try_like(locked)?;
+		let bitsliced_keys = bitsliced_keys.try_like(locked)?;
+
+		Ok(Decryptor{key_schedule, backend, inverse_schedule, simd_keys, bitsliced_keys})
+	}
+
+	pub fn backend(&self) -> Backend {
+		self.backend
 	}
 
 	pub fn decrypt(&self, input: &[u8], debug: bool) -> [u8; 16] {
 		if input.len() != 16 { panic!("Can only decrypt 16 byte blocks!"); }
 
-		let key_schedule = self.key.create_schedule();
+		match self.backend {
+			Backend::TTable if !debug => ttable::decrypt(&self.inverse_schedule, input),
+			Backend::AesNi if !debug => aesni::decrypt(&self.simd_keys, input),
+			Backend::Bitsliced if !debug => bitslice::decrypt(&self.bitsliced_keys, input),
+			Backend::VectorPermute if !debug => vperm::decrypt(&self.simd_keys, input),
+			_ => self.decrypt_reference(input, debug)
+		}
+	}
+
+	#[cfg(feature = "std")]
+	pub fn decrypt_blocks(&self, input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.decrypt_blocks_in_place(&mut output);
+		output
+	}
+
+	pub fn decrypt_blocks_in_place(&self, data: &mut [u8]) {
+		if !data.len().is_multiple_of(16) { panic!("Can only decrypt 16 byte blocks!"); }
+
+		match self.backend {
+			Backend::Bitsliced => bitslice::decrypt_blocks(&self.bitsliced_keys, data),
+			_ => for block in data.chunks_mut(16) {
+				let output = self.decrypt(block, false);
+				block.copy_from_slice(&output);
+			}
+		}
+	}
+
+	#[cfg(feature = "std")]
+	pub fn decrypt_many(jobs: &[(&Decryptor, &[u8])]) -> Vec<[u8; 16]> {
+		if jobs.iter().any(|&(_, input)| input.len() != 16) { panic!("Can only decrypt 16 byte blocks!"); }
+
+		let mut output = vec![[0; 16]; jobs.len()];
+		for group in lanes(jobs.iter().map(|&(decryptor, _)| (decryptor.backend, decryptor.key_schedule.rounds()))) {
+			let results = match jobs[group[0]].0.backend {
+				Backend::TTable => ttable::decrypt_lanes(
+					&group.iter().map(|&i| (&jobs[i].0.inverse_schedule[..], jobs[i].1)).collect::<Vec<_>>()
+				),
+				Backend::AesNi => aesni::decrypt_lanes(
+					&group.iter().map(|&i| (&jobs[i].0.simd_keys[..], jobs[i].1)).collect::<Vec<_>>()
+				),
+				_ => group.iter().map(|&i| jobs[i].0.decrypt(jobs[i].1, false)).collect()
+			};
+
+			for (&i, result) in group.iter().zip(results) {
+				output[i] = result;
+			}
+		}
+
+		output
+	}
+
+	fn decrypt_reference(&self, input: &[u8], debug: bool) -> [u8; 16] {
+		let key_schedule = &self.key_schedule;
 
 		let mut state = State::from_slice(input);
-		if debug { println!("round[ 0].iinput   {}", state); }
+		trace!(debug, "round[ 0].iinput   {}", state);
 
-		let nr = match self.key.get_size_bits() {
-			128 => {10},
-			192 => {12},
-			256 => {14},
-			_ => {panic!("Invalid key size!")}
-		};
+		let nr = key_schedule.rounds();
 
 		let ks = &key_schedule[nr];
-		if debug { println!("round[ 0].ik_sch   {:0>8x}{:0>8x}{:0>8x}{:0>8x}", ks[0], ks[1], ks[2], ks[3])}
+		trace!(debug, "round[ 0].ik_sch   {:0>8x}{:0>8x}{:0>8x}{:0>8x}", ks[0], ks[1], ks[2], ks[3]);
 		state = state.add_round_key(ks);
 
 		for round in (1..nr).rev() {
-			if debug { println!("round[{: >2}].istart   {}", nr-round, state); }
+			trace!(debug, "round[{: >2}].istart   {}", nr-round, state);
 			state = state.inv_shift_rows();
-			if debug { println!("round[{: >2}].is_row   {}", nr-round, state); }
+			trace!(debug, "round[{: >2}].is_row   {}", nr-round, state);
 			state = state.inv_sub_bytes();
-			if debug { println!("round[{: >2}].is_box   {}", nr-round, state); }
+			trace!(debug, "round[{: >2}].is_box   {}", nr-round, state);
 
 			let ks = &key_schedule[round];
-			if debug { println!("round[{: >2}].ik_sch   {:0>8x}{:0>8x}{:0>8x}{:0>8x}", nr-round, ks[0], ks[1], ks[2], ks[3])}
+			trace!(debug, "round[{: >2}].ik_sch   {:0>8x}{:0>8x}{:0>8x}{:0>8x}", nr-round, ks[0], ks[1], ks[2], ks[3]);
 			state = state.add_round_key(ks);
-			if debug { println!("round[{: >2}].ik_add   {}", nr-round, state); }
+			trace!(debug, "round[{: >2}].ik_add   {}", nr-round, state);
 			state = state.inv_mix_columns();
 		}
 
-		if debug { println!("round[{}].istart   {}", nr, state); }
+		trace!(debug, "round[{}].istart   {}", nr, state);
 		state = state.inv_shift_rows();
-		if debug { println!("round[{}].is_row   {}", nr, state); }
+		trace!(debug, "round[{}].is_row   {}", nr, state);
 		state = state.inv_sub_bytes();
-		if debug { println!("round[{}].is_box   {}", nr, state); }
+		trace!(debug, "round[{}].is_box   {}", nr, state);
 
 		let ks = &key_schedule[0];
-		if debug { println!("round[{}].ik_sch   {:0>8x}{:0>8x}{:0>8x}{:0>8x}", nr, ks[0], ks[1], ks[2], ks[3]); }
+		trace!(debug, "round[{}].ik_sch   {:0>8x}{:0>8x}{:0>8x}{:0>8x}", nr, ks[0], ks[1], ks[2], ks[3]);
 		state = state.add_round_key(ks);
-		if debug { println!("round[{}].ioutput  {}", nr, state); }
+		trace!(debug, "round[{}].ioutput  {}", nr, state);
 
 		state.to_byte_array()
 	}
@@ -181,6 +504,152 @@ mod tests {
 		assert_eq!(expected, encryptor.encrypt(&input, false));
 	}
 
+	// FIPS-197 Appendix C, run through every backend
+	fn check_backend(backend: Backend) {
+		let keys: [&[u32]; 3] = [
+			&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f],
+			&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f, 0x10111213, 0x14151617],
+			&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f, 0x10111213, 0x14151617, 0x18191a1b, 0x1c1d1e1f]
+		];
+		let outputs = [
+			[0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4, 0xc5, 0x5a],
+			[0xdd, 0xa9, 0x7c, 0xa4, 0x86, 0x4c, 0xdf, 0xe0, 0x6e, 0xaf, 0x70, 0xa0, 0xec, 0x0d, 0x71, 0x91],
+			[0x8e, 0xa2, 0xb7, 0xca, 0x51, 0x67, 0x45, 0xbf, 0xea, 0xfc, 0x49, 0x90, 0x4b, 0x49, 0x60, 0x89]
+		];
+		let input = [
+			0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77,
+			0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff,
+		];
+
+		for (key, expected) in keys.iter().zip(outputs.iter()) {
+			let encryptor = Encryptor::with_backend(Key::new(key), backend);
+			let decryptor = Decryptor::with_backend(Key::new(key), backend);
+
+			assert_eq!(*expected, encryptor.encrypt(&input, false));
+			assert_eq!(input, decryptor.decrypt(expected, false));
+		}
+	}
+
+	#[test]
+	fn test_encryptor_macro() {
+		let encryptor = encryptor!("000102030405060708090a0b0c0d0e0f1011121314151617");
+
+		let input = [
+			0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77,
+			0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff,
+		];
+		let expected = [
+			0xdd, 0xa9, 0x7c, 0xa4, 0x86, 0x4c, 0xdf, 0xe0,
+			0x6e, 0xaf, 0x70, 0xa0, 0xec, 0x0d, 0x71, 0x91
+		];
+		assert_eq!(expected, encryptor.encrypt(&input, false));
+	}
+
+	#[test]
+	#[should_panic]
+	fn test_wrong_schedule() {
+		let schedule = Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f]).create_schedule();
+		Encryptor::with_schedule(Key::new(&[0x2b7e1516, 0x28aed2a6, 0xabf71588, 0x09cf4f3c]), &schedule);
+	}
+
+	#[test]
+	fn test_reference_backend() {
+		check_backend(Backend::Reference);
+	}
+
+	#[test]
+	fn test_ttable_backend() {
+		check_backend(Backend::TTable);
+	}
+
+	#[test]
+	fn test_aesni_backend() {
+		check_backend(Backend::AesNi);
+
+		let encryptor = Encryptor::with_backend(Key::new(&[0; 4]), Backend::AesNi);
+		if aesni::is_available() {
+			assert_eq!(Backend::AesNi, encryptor.backend());
+		} else {
+			assert_eq!(Backend::detect(), encryptor.backend());
+		}
+	}
+
+	#[test]
+	fn test_vector_permute_backend() {
+		check_backend(Backend::VectorPermute);
+
+		let encryptor = Encryptor::with_backend(Key::new(&[0; 4]), Backend::VectorPermute);
+		if vperm::is_available() {
+			assert_eq!(Backend::VectorPermute, encryptor.backend());
+		} else {
+			assert_eq!(Backend::Bitsliced, encryptor.backend());
+		}
+	}
+
+	#[test]
+	fn test_bitsliced_backend() {
+		check_backend(Backend::Bitsliced);
+
+		// Nine blocks is more than one batch
+		let key = Key::new(&[0x2b7e1516, 0x28aed2a6, 0xabf71588, 0x09cf4f3c]);
+		let encryptor = Encryptor::with_backend(key.clone(), Backend::Bitsliced);
+		let decryptor = Decryptor::with_backend(key.clone(), Backend::Bitsliced);
+		let reference = Encryptor::with_backend(key, Backend::Reference);
+
+		let mut input = [0; 144];
+		for i in 0..144 { input[i] = (i * 7) as u8; }
+		let mut output = input;
+		encryptor.encrypt_blocks_in_place(&mut output);
+		let mut expected = input;
+		reference.encrypt_blocks_in_place(&mut expected);
+		assert_eq!(expected, output);
+
+		decryptor.decrypt_blocks_in_place(&mut output);
+		assert_eq!(input, output);
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_lanes() {
+		let kinds = (0..30).map(|i| if i % 3 == 0 { (Backend::AesNi, 10) } else { (Backend::TTable, 10 + 2 * (i % 2)) });
+		let groups = lanes(kinds);
+
+		let mut seen: Vec<usize> = groups.iter().flatten().cloned().collect();
+		seen.sort();
+		assert_eq!((0..30).collect::<Vec<_>>(), seen);
+
+		// Groups are given out as they fill up, then the partial ones
+		assert_eq!(6, groups.len());
+		assert_eq!(vec![0, 3, 6, 9, 12, 15, 18, 21], groups[0]);
+		assert_eq!(vec![2, 4, 8, 10, 14, 16, 20, 22], groups[1]);
+		assert_eq!(vec![24, 27], groups[3]);
+	}
+
+	// Sessions with different keys, sizes and backends, mixed together
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_many() {
+		let backends = [Backend::TTable, Backend::AesNi, Backend::Reference, Backend::Bitsliced];
+		let mut encryptors = Vec::new();
+		let mut decryptors = Vec::new();
+		for i in 0..40u32 {
+			let words: Vec<u32> = (0..4 + 2 * (i % 3)).map(|j| i.wrapping_mul(0x9e3779b9) ^ j).collect();
+			let backend = backends[(i % 7 % 4) as usize];
+			encryptors.push(Encryptor::with_backend(Key::new(&words), backend));
+			decryptors.push(Decryptor::with_backend(Key::new(&words), backend));
+		}
+		let inputs: Vec<[u8; 16]> = (0..40).map(|i| [i as u8 * 3; 16]).collect();
+
+		let jobs: Vec<_> = encryptors.iter().zip(inputs.iter()).map(|(e, input)| (e, &input[..])).collect();
+		let outputs = Encryptor::encrypt_many(&jobs);
+		for i in 0..40 {
+			assert_eq!(encryptors[i].encrypt(&inputs[i], false), outputs[i]);
+		}
+
+		let jobs: Vec<_> = decryptors.iter().zip(outputs.iter()).map(|(d, output)| (d, &output[..])).collect();
+		assert_eq!(inputs, Decryptor::decrypt_many(&jobs));
+	}
+
 	#[test]
 	fn test_decryptor128() {
 		let key = Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f]);
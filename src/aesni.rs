@@ -0,0 +1,368 @@
+// Hardware AES using the x86_64 AES-NI instructions.  Everything here must
+// only be called once is_available() has returned true
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+
+use buffer::{Buffer, ROUND_KEYS};
+use key::{Key, KeySchedule};
+#[cfg(target_arch = "x86_64")]
+use zeroize::zeroize;
+
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+pub fn is_available() -> bool {
+	is_x86_feature_detected!("aes") && is_x86_feature_detected!("sse2")
+}
+
+// Without std it has to be known when compiling
+#[cfg(all(target_arch = "x86_64", not(feature = "std")))]
+pub fn is_available() -> bool {
+	cfg!(target_feature = "aes") && cfg!(target_feature = "sse2")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn is_available() -> bool {
+	false
+}
+
+// Expands the key with aeskeygenassist, giving one 16 byte round key per round
+#[cfg(target_arch = "x86_64")]
+pub fn expand_key(key: &Key) -> Buffer<[u8; 16], ROUND_KEYS> {
+	assert!(is_available());
+
+	let mut bytes = [0; 32];
+	for (i, word) in key.words().iter().enumerate() {
+		bytes[i*4..(i+1)*4].copy_from_slice(&word.to_be_bytes());
+	}
+
+	let keys = unsafe {
+		match key.words().len() {
+			4 => expand_key128(&bytes[..16]),
+			6 => expand_key192(&bytes[..24]),
+			8 => expand_key256(&bytes),
+			_ => panic!("Invalid key size!")
+		}
+	};
+
+	zeroize(&mut bytes);
+	keys
+}
+
+// The same round keys taken from a schedule that's already been worked out,
+// like the one encryptor! bakes in
+pub fn round_keys(schedule: &KeySchedule) -> Buffer<[u8; 16], ROUND_KEYS> {
+	(0..schedule.rounds() + 1).map(|round| {
+		let mut key = [0; 16];
+		for c in 0..4 {
+			key[c*4..(c+1)*4].copy_from_slice(&schedule[round][c].to_be_bytes());
+		}
+		key
+	}).collect()
+}
+
+// Turns the encryption round keys into the ones for the equivalent inverse
+// cipher, in the order aesdec uses them
+#[cfg(target_arch = "x86_64")]
+pub fn inverse_keys(keys: &[[u8; 16]]) -> Buffer<[u8; 16], ROUND_KEYS> {
+	assert!(is_available());
+	unsafe { inverse_keys_impl(keys) }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn encrypt(keys: &[[u8; 16]], input: &[u8]) -> [u8; 16] {
+	unsafe { encrypt_impl(keys, input) }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn decrypt(keys: &[[u8; 16]], input: &[u8]) -> [u8; 16] {
+	unsafe { decrypt_impl(keys, input) }
+}
+
+// Several blocks each under their own keys, with the same number of rounds.
+// aesenc takes several cycles but a new one can start every cycle, so
+// interleaving independent blocks keeps the unit busy
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+pub fn encrypt_lanes(jobs: &[(&[[u8; 16]], &[u8])]) -> Vec<[u8; 16]> {
+	unsafe { encrypt_lanes_impl(jobs) }
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+pub fn decrypt_lanes(jobs: &[(&[[u8; 16]], &[u8])]) -> Vec<[u8; 16]> {
+	unsafe { decrypt_lanes_impl(jobs) }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn expand_key(_key: &Key) -> Buffer<[u8; 16], ROUND_KEYS> {
+	unreachable!("AES-NI is not available on this architecture")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn inverse_keys(_keys: &[[u8; 16]]) -> Buffer<[u8; 16], ROUND_KEYS> {
+	unreachable!("AES-NI is not available on this architecture")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn encrypt(_keys: &[[u8; 16]], _input: &[u8]) -> [u8; 16] {
+	unreachable!("AES-NI is not available on this architecture")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn decrypt(_keys: &[[u8; 16]], _input: &[u8]) -> [u8; 16] {
+	unreachable!("AES-NI is not available on this architecture")
+}
+
+#[cfg(all(not(target_arch = "x86_64"), feature = "std"))]
+pub fn encrypt_lanes(_jobs: &[(&[[u8; 16]], &[u8])]) -> Vec<[u8; 16]> {
+	unreachable!("AES-NI is not available on this architecture")
+}
+
+#[cfg(all(not(target_arch = "x86_64"), feature = "std"))]
+pub fn decrypt_lanes(_jobs: &[(&[[u8; 16]], &[u8])]) -> Vec<[u8; 16]> {
+	unreachable!("AES-NI is not available on this architecture")
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn load(bytes: &[u8]) -> __m128i {
+	_mm_loadu_si128(bytes.as_ptr() as *const __m128i)
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn store(block: __m128i) -> [u8; 16] {
+	let mut ret = [0; 16];
+	_mm_storeu_si128(ret.as_mut_ptr() as *mut __m128i, block);
+	ret
+}
+
+// Xors each word of the block with all of the words before it, which is the
+// chain of xors the key schedule does within one block of Nk words
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn prefix_xor(mut x: __m128i) -> __m128i {
+	x = _mm_xor_si128(x, _mm_slli_si128(x, 4));
+	x = _mm_xor_si128(x, _mm_slli_si128(x, 4));
+	_mm_xor_si128(x, _mm_slli_si128(x, 4))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "aes")]
+unsafe fn expand_key128(key: &[u8]) -> Buffer<[u8; 16], ROUND_KEYS> {
+	// aeskeygenassist needs its round constant as an immediate
+	macro_rules! round {
+		($prev:expr, $rcon:expr) => {{
+			let assist = _mm_shuffle_epi32(_mm_aeskeygenassist_si128($prev, $rcon), 0xff);
+			_mm_xor_si128(prefix_xor($prev), assist)
+		}}
+	}
+
+	let mut keys = Buffer::new();
+	keys.push(store(load(key)));
+	keys.push(store(round!(load(&keys[0]), 0x01)));
+	keys.push(store(round!(load(&keys[1]), 0x02)));
+	keys.push(store(round!(load(&keys[2]), 0x04)));
+	keys.push(store(round!(load(&keys[3]), 0x08)));
+	keys.push(store(round!(load(&keys[4]), 0x10)));
+	keys.push(store(round!(load(&keys[5]), 0x20)));
+	keys.push(store(round!(load(&keys[6]), 0x40)));
+	keys.push(store(round!(load(&keys[7]), 0x80)));
+	keys.push(store(round!(load(&keys[8]), 0x1b)));
+	keys.push(store(round!(load(&keys[9]), 0x36)));
+	keys
+}
+
+// With 6 word keys each step produces one and a half round keys, so the
+// output is assembled from 64 bit halves
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "aes")]
+unsafe fn expand_key192(key: &[u8]) -> Buffer<[u8; 16], ROUND_KEYS> {
+	let mut high = [0; 16];
+	high[..8].copy_from_slice(&key[16..]);
+
+	let mut t1 = load(key);
+	let mut t3 = load(&high);
+
+	// Updates t1 with the next four words and the low half of t3 with the
+	// two after that
+	macro_rules! step {
+		($rcon:expr) => {{
+			let assist = _mm_shuffle_epi32(_mm_aeskeygenassist_si128(t3, $rcon), 0x55);
+			t1 = _mm_xor_si128(prefix_xor(t1), assist);
+			let last = _mm_shuffle_epi32(t1, 0xff);
+			t3 = _mm_xor_si128(_mm_xor_si128(t3, _mm_slli_si128(t3, 4)), last);
+		}}
+	}
+
+	// The two words left over in t3 from the previous step and the first two
+	// of t1 make one round key, and the rest of t1 and t3 make the next
+	macro_rules! one_and_a_half {
+		($keys:expr, $pending:expr) => {{
+			$keys.push(store(_mm_unpacklo_epi64($pending, t1)));
+			$keys.push(store(_mm_castpd_si128(_mm_shuffle_pd(_mm_castsi128_pd(t1), _mm_castsi128_pd(t3), 1))));
+		}}
+	}
+
+	let mut keys = Buffer::new();
+	keys.push(store(t1));
+	let pending = t3;
+	step!(0x01);
+	one_and_a_half!(keys, pending);
+	step!(0x02);
+	keys.push(store(t1));
+	let pending = t3;
+	step!(0x04);
+	one_and_a_half!(keys, pending);
+	step!(0x08);
+	keys.push(store(t1));
+	let pending = t3;
+	step!(0x10);
+	one_and_a_half!(keys, pending);
+	step!(0x20);
+	keys.push(store(t1));
+	let pending = t3;
+	step!(0x40);
+	one_and_a_half!(keys, pending);
+
+	// Only four more words are needed for the last round key
+	let assist = _mm_shuffle_epi32(_mm_aeskeygenassist_si128(t3, 0x80), 0x55);
+	keys.push(store(_mm_xor_si128(prefix_xor(t1), assist)));
+	keys
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "aes")]
+unsafe fn expand_key256(key: &[u8]) -> Buffer<[u8; 16], ROUND_KEYS> {
+	macro_rules! even {
+		($prev2:expr, $prev:expr, $rcon:expr) => {{
+			let assist = _mm_shuffle_epi32(_mm_aeskeygenassist_si128($prev, $rcon), 0xff);
+			_mm_xor_si128(prefix_xor($prev2), assist)
+		}}
+	}
+
+	// The odd blocks use SubWord without RotWord or Rcon
+	macro_rules! odd {
+		($prev2:expr, $prev:expr) => {{
+			let assist = _mm_shuffle_epi32(_mm_aeskeygenassist_si128($prev, 0x00), 0xaa);
+			_mm_xor_si128(prefix_xor($prev2), assist)
+		}}
+	}
+
+	let mut keys = Buffer::new();
+	keys.push(store(load(&key[..16])));
+	keys.push(store(load(&key[16..])));
+	keys.push(store(even!(load(&keys[0]), load(&keys[1]), 0x01)));
+	keys.push(store(odd!(load(&keys[1]), load(&keys[2]))));
+	keys.push(store(even!(load(&keys[2]), load(&keys[3]), 0x02)));
+	keys.push(store(odd!(load(&keys[3]), load(&keys[4]))));
+	keys.push(store(even!(load(&keys[4]), load(&keys[5]), 0x04)));
+	keys.push(store(odd!(load(&keys[5]), load(&keys[6]))));
+	keys.push(store(even!(load(&keys[6]), load(&keys[7]), 0x08)));
+	keys.push(store(odd!(load(&keys[7]), load(&keys[8]))));
+	keys.push(store(even!(load(&keys[8]), load(&keys[9]), 0x10)));
+	keys.push(store(odd!(load(&keys[9]), load(&keys[10]))));
+	keys.push(store(even!(load(&keys[10]), load(&keys[11]), 0x20)));
+	keys.push(store(odd!(load(&keys[11]), load(&keys[12]))));
+	keys.push(store(even!(load(&keys[12]), load(&keys[13]), 0x40)));
+	keys
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "aes")]
+unsafe fn inverse_keys_impl(keys: &[[u8; 16]]) -> Buffer<[u8; 16], ROUND_KEYS> {
+	let nr = keys.len() - 1;
+
+	(0..nr+1).rev().map(|round| {
+		if round == 0 || round == nr {
+			keys[round]
+		} else {
+			store(_mm_aesimc_si128(load(&keys[round])))
+		}
+	}).collect()
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "aes")]
+unsafe fn encrypt_impl(keys: &[[u8; 16]], input: &[u8]) -> [u8; 16] {
+	let nr = keys.len() - 1;
+
+	let mut state = _mm_xor_si128(load(input), load(&keys[0]));
+	for key in &keys[1..nr] {
+		state = _mm_aesenc_si128(state, load(key));
+	}
+
+	store(_mm_aesenclast_si128(state, load(&keys[nr])))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "aes")]
+unsafe fn decrypt_impl(keys: &[[u8; 16]], input: &[u8]) -> [u8; 16] {
+	let nr = keys.len() - 1;
+
+	let mut state = _mm_xor_si128(load(input), load(&keys[0]));
+	for key in &keys[1..nr] {
+		state = _mm_aesdec_si128(state, load(key));
+	}
+
+	store(_mm_aesdeclast_si128(state, load(&keys[nr])))
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+#[target_feature(enable = "aes")]
+unsafe fn encrypt_lanes_impl(jobs: &[(&[[u8; 16]], &[u8])]) -> Vec<[u8; 16]> {
+	let nr = jobs[0].0.len() - 1;
+
+	let mut states: Vec<__m128i> = jobs.iter().map(|&(keys, input)| _mm_xor_si128(load(input), load(&keys[0]))).collect();
+	for round in 1..nr {
+		for (state, &(keys, _)) in states.iter_mut().zip(jobs) {
+			*state = _mm_aesenc_si128(*state, load(&keys[round]));
+		}
+	}
+
+	states.iter().zip(jobs).map(|(&state, &(keys, _))| store(_mm_aesenclast_si128(state, load(&keys[nr])))).collect()
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+#[target_feature(enable = "aes")]
+unsafe fn decrypt_lanes_impl(jobs: &[(&[[u8; 16]], &[u8])]) -> Vec<[u8; 16]> {
+	let nr = jobs[0].0.len() - 1;
+
+	let mut states: Vec<__m128i> = jobs.iter().map(|&(keys, input)| _mm_xor_si128(load(input), load(&keys[0]))).collect();
+	for round in 1..nr {
+		for (state, &(keys, _)) in states.iter_mut().zip(jobs) {
+			*state = _mm_aesdec_si128(*state, load(&keys[round]));
+		}
+	}
+
+	states.iter().zip(jobs).map(|(&state, &(keys, _))| store(_mm_aesdeclast_si128(state, load(&keys[nr])))).collect()
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+	use aesni::*;
+	use key::Key;
+
+	// The hardware key expansion should agree with the software one
+	#[test]
+	fn test_expand_key() {
+		if !is_available() { return; }
+
+		let keys: [&[u32]; 3] = [
+			&[0x2b7e1516, 0x28aed2a6, 0xabf71588, 0x09cf4f3c],
+			&[0x8e73b0f7, 0xda0e6452, 0xc810f32b, 0x809079e5, 0x62f8ead2, 0x522c6b7b],
+			&[0x603deb10, 0x15ca71be, 0x2b73aef0, 0x857d7781, 0x1f352c07, 0x3b6108d7, 0x2d9810a3, 0x0914dff4]
+		];
+
+		for words in keys.iter() {
+			let key = Key::new(words);
+			let schedule = key.create_schedule();
+			let expanded = expand_key(&key);
+
+			assert_eq!(schedule.rounds() + 1, expanded.len());
+			for round in 0..expanded.len() {
+				let mut expected = [0; 16];
+				for c in 0..4 {
+					expected[c*4..(c+1)*4].copy_from_slice(&schedule[round][c].to_be_bytes());
+				}
+				assert_eq!(expected, expanded[round]);
+			}
+			assert!(expanded == round_keys(&schedule));
+		}
+	}
+}
@@ -0,0 +1,492 @@
+use buffer::{Buffer, ROUND_KEYS};
+use key::{Key, KeySchedule};
+
+// Constant time AES that never indexes memory or branches on secret data.  The
+// state is bitsliced: each of the eight words of a group holds one bit position
+// of every byte of four blocks, with bit r*16 + c*4 + n holding byte (r, c) of
+// block n.  SubBytes is then a boolean circuit run on all 64 bytes at once,
+// ShiftRows rotates the nibbles within each row and MixColumns rotates whole
+// rows.  Two groups are run together so each batch is eight blocks
+type Planes = [u64; 8];
+
+pub const BATCH_BLOCKS: usize = 8;
+
+// The usual key expansion, but with SubWord computed by the S-box circuit so
+// that expanding a secret key doesn't touch the tables either
+pub fn create_schedule(key: &Key) -> KeySchedule {
+	let nk = key.words().len();
+	let nr = nk + 6;
+
+	let mut words: Buffer<u32, 60> = Buffer::from_slice(key.words());
+
+	// The round constants don't depend on the key so they can be computed
+	// any way we like
+	let mut rcon: u8 = 1;
+	for i in nk..4*(nr+1) {
+		let mut temp = words[i-1];
+		if i % nk == 0 {
+			temp = sub_word(temp.rotate_left(8)) ^ (rcon as u32) << 24;
+			rcon = (rcon << 1) ^ ((rcon >> 7) * 0x1b);
+		} else if nk > 6 && i % nk == 4 {
+			temp = sub_word(temp);
+		}
+		words.push(words[i-nk] ^ temp);
+	}
+
+	KeySchedule::for_key(key, words)
+}
+
+// Each round key is bitsliced with a copy in every block's position so it can
+// be xored straight into a group
+pub fn round_keys(schedule: &KeySchedule) -> Buffer<Planes, ROUND_KEYS> {
+	let mut keys = Buffer::new();
+
+	for round in 0..schedule.rounds() + 1 {
+		let mut bytes = [0; 16];
+		for c in 0..4 {
+			bytes[c*4..(c+1)*4].copy_from_slice(&schedule[round][c].to_be_bytes());
+		}
+
+		let mut q = [0; 8];
+		for n in 0..4 {
+			pack_block(&bytes, n, &mut q);
+		}
+		keys.push(q);
+	}
+
+	keys
+}
+
+pub fn encrypt(keys: &[Planes], input: &[u8]) -> [u8; 16] {
+	let mut block = [0; 16];
+	block.copy_from_slice(input);
+	encrypt_blocks(keys, &mut block);
+	block
+}
+
+pub fn decrypt(keys: &[Planes], input: &[u8]) -> [u8; 16] {
+	let mut block = [0; 16];
+	block.copy_from_slice(input);
+	decrypt_blocks(keys, &mut block);
+	block
+}
+
+// Encrypts any number of whole blocks in place, eight at a time
+pub fn encrypt_blocks(keys: &[Planes], data: &mut [u8]) {
+	let nr = keys.len() - 1;
+
+	for batch in data.chunks_mut(16 * BATCH_BLOCKS) {
+		let mut groups = pack(batch);
+
+		for q in groups.iter_mut() {
+			add_round_key(q, &keys[0]);
+		}
+		for key in &keys[1..nr] {
+			for q in groups.iter_mut() {
+				sub_bytes(q);
+				shift_rows(q);
+				mix_columns(q);
+				add_round_key(q, key);
+			}
+		}
+		for q in groups.iter_mut() {
+			sub_bytes(q);
+			shift_rows(q);
+			add_round_key(q, &keys[nr]);
+		}
+
+		unpack(&groups, batch);
+	}
+}
+
+pub fn decrypt_blocks(keys: &[Planes], data: &mut [u8]) {
+	let nr = keys.len() - 1;
+
+	for batch in data.chunks_mut(16 * BATCH_BLOCKS) {
+		let mut groups = pack(batch);
+
+		for q in groups.iter_mut() {
+			add_round_key(q, &keys[nr]);
+		}
+		for key in keys[1..nr].iter().rev() {
+			for q in groups.iter_mut() {
+				inv_shift_rows(q);
+				inv_sub_bytes(q);
+				add_round_key(q, key);
+				inv_mix_columns(q);
+			}
+		}
+		for q in groups.iter_mut() {
+			inv_shift_rows(q);
+			inv_sub_bytes(q);
+			add_round_key(q, &keys[0]);
+		}
+
+		unpack(&groups, batch);
+	}
+}
+
+fn sub_word(word: u32) -> u32 {
+	let bytes = word.to_be_bytes();
+	let mut q = [0; 8];
+	for n in 0..4 {
+		spread(bytes[n], n, &mut q);
+	}
+
+	sub_bytes(&mut q);
+
+	u32::from_be_bytes([gather(&q, 0), gather(&q, 1), gather(&q, 2), gather(&q, 3)])
+}
+
+fn spread(byte: u8, pos: usize, q: &mut Planes) {
+	for b in 0..8 {
+		q[b] |= (((byte >> b) & 1) as u64) << pos;
+	}
+}
+
+fn gather(q: &Planes, pos: usize) -> u8 {
+	let mut byte = 0;
+	for b in 0..8 {
+		byte |= (((q[b] >> pos) & 1) as u8) << b;
+	}
+	byte
+}
+
+fn pack_block(block: &[u8], n: usize, q: &mut Planes) {
+	for (i, &byte) in block.iter().enumerate() {
+		spread(byte, (i % 4) * 16 + (i / 4) * 4 + n, q);
+	}
+}
+
+// A short batch leaves the unused block positions zero
+fn pack(batch: &[u8]) -> [Planes; 2] {
+	let mut groups = [[0; 8]; 2];
+	for (i, block) in batch.chunks(16).enumerate() {
+		pack_block(block, i % 4, &mut groups[i / 4]);
+	}
+	groups
+}
+
+fn unpack(groups: &[Planes; 2], batch: &mut [u8]) {
+	for (i, block) in batch.chunks_mut(16).enumerate() {
+		for (j, byte) in block.iter_mut().enumerate() {
+			*byte = gather(&groups[i / 4], (j % 4) * 16 + (j / 4) * 4 + i % 4);
+		}
+	}
+}
+
+fn add_round_key(q: &mut Planes, key: &Planes) {
+	for b in 0..8 {
+		q[b] ^= key[b];
+	}
+}
+
+// Boyar and Peralta's 113 gate circuit for the S-box, "A depth-16 circuit for
+// the AES S-box".  x0 is the most significant bit
+#[allow(clippy::many_single_char_names)]
+fn sub_bytes(q: &mut Planes) {
+	let x0 = q[7];
+	let x1 = q[6];
+	let x2 = q[5];
+	let x3 = q[4];
+	let x4 = q[3];
+	let x5 = q[2];
+	let x6 = q[1];
+	let x7 = q[0];
+
+	// Top linear transformation
+	let y14 = x3 ^ x5;
+	let y13 = x0 ^ x6;
+	let y9 = x0 ^ x3;
+	let y8 = x0 ^ x5;
+	let t0 = x1 ^ x2;
+	let y1 = t0 ^ x7;
+	let y4 = y1 ^ x3;
+	let y12 = y13 ^ y14;
+	let y2 = y1 ^ x0;
+	let y5 = y1 ^ x6;
+	let y3 = y5 ^ y8;
+	let t1 = x4 ^ y12;
+	let y15 = t1 ^ x5;
+	let y20 = t1 ^ x1;
+	let y6 = y15 ^ x7;
+	let y10 = y15 ^ t0;
+	let y11 = y20 ^ y9;
+	let y7 = x7 ^ y11;
+	let y17 = y10 ^ y11;
+	let y19 = y10 ^ y8;
+	let y16 = t0 ^ y11;
+	let y21 = y13 ^ y16;
+	let y18 = x0 ^ y16;
+
+	// Non-linear section, the inversion in GF(2^8)
+	let t2 = y12 & y15;
+	let t3 = y3 & y6;
+	let t4 = t3 ^ t2;
+	let t5 = y4 & x7;
+	let t6 = t5 ^ t2;
+	let t7 = y13 & y16;
+	let t8 = y5 & y1;
+	let t9 = t8 ^ t7;
+	let t10 = y2 & y7;
+	let t11 = t10 ^ t7;
+	let t12 = y9 & y11;
+	let t13 = y14 & y17;
+	let t14 = t13 ^ t12;
+	let t15 = y8 & y10;
+	let t16 = t15 ^ t12;
+	let t17 = t4 ^ t14;
+	let t18 = t6 ^ t16;
+	let t19 = t9 ^ t14;
+	let t20 = t11 ^ t16;
+	let t21 = t17 ^ y20;
+	let t22 = t18 ^ y19;
+	let t23 = t19 ^ y21;
+	let t24 = t20 ^ y18;
+
+	let t25 = t21 ^ t22;
+	let t26 = t21 & t23;
+	let t27 = t24 ^ t26;
+	let t28 = t25 & t27;
+	let t29 = t28 ^ t22;
+	let t30 = t23 ^ t24;
+	let t31 = t22 ^ t26;
+	let t32 = t31 & t30;
+	let t33 = t32 ^ t24;
+	let t34 = t23 ^ t33;
+	let t35 = t27 ^ t33;
+	let t36 = t24 & t35;
+	let t37 = t36 ^ t34;
+	let t38 = t27 ^ t36;
+	let t39 = t29 & t38;
+	let t40 = t25 ^ t39;
+
+	let t41 = t40 ^ t37;
+	let t42 = t29 ^ t33;
+	let t43 = t29 ^ t40;
+	let t44 = t33 ^ t37;
+	let t45 = t42 ^ t41;
+	let z0 = t44 & y15;
+	let z1 = t37 & y6;
+	let z2 = t33 & x7;
+	let z3 = t43 & y16;
+	let z4 = t40 & y1;
+	let z5 = t29 & y7;
+	let z6 = t42 & y11;
+	let z7 = t45 & y17;
+	let z8 = t41 & y10;
+	let z9 = t44 & y12;
+	let z10 = t37 & y3;
+	let z11 = t33 & y4;
+	let z12 = t43 & y13;
+	let z13 = t40 & y5;
+	let z14 = t29 & y2;
+	let z15 = t42 & y9;
+	let z16 = t45 & y14;
+	let z17 = t41 & y8;
+
+	// Bottom linear transformation, which includes the affine map
+	let t46 = z15 ^ z16;
+	let t47 = z10 ^ z11;
+	let t48 = z5 ^ z13;
+	let t49 = z9 ^ z10;
+	let t50 = z2 ^ z12;
+	let t51 = z2 ^ z5;
+	let t52 = z7 ^ z8;
+	let t53 = z0 ^ z3;
+	let t54 = z6 ^ z7;
+	let t55 = z16 ^ z17;
+	let t56 = z12 ^ t48;
+	let t57 = t50 ^ t53;
+	let t58 = z4 ^ t46;
+	let t59 = z3 ^ t54;
+	let t60 = t46 ^ t57;
+	let t61 = z14 ^ t57;
+	let t62 = t52 ^ t58;
+	let t63 = t49 ^ t58;
+	let t64 = z4 ^ t59;
+	let t65 = t61 ^ t62;
+	let t66 = z1 ^ t63;
+	let s0 = t59 ^ t63;
+	let s6 = t56 ^ !t62;
+	let s7 = t48 ^ !t60;
+	let t67 = t64 ^ t65;
+	let s3 = t53 ^ t66;
+	let s4 = t51 ^ t66;
+	let s5 = t47 ^ t65;
+	let s1 = t64 ^ !s3;
+	let s2 = t55 ^ !t67;
+
+	*q = [s7, s6, s5, s4, s3, s2, s1, s0];
+}
+
+// The inverse S-box is the inverse affine map, then the forward S-box (which
+// inverts and applies the affine map), then the inverse affine map again
+fn inv_sub_bytes(q: &mut Planes) {
+	inv_affine(q);
+	sub_bytes(q);
+	inv_affine(q);
+}
+
+// b'[i] = b[i+2] ^ b[i+5] ^ b[i+7] ^ 0x05[i] undoes the S-box's affine map,
+// including the xor with 0x63
+fn inv_affine(q: &mut Planes) {
+	let mut x = *q;
+	for &b in [0, 1, 5, 6].iter() {
+		x[b] = !x[b];
+	}
+	for i in 0..8 {
+		q[i] = x[(i + 2) % 8] ^ x[(i + 5) % 8] ^ x[(i + 7) % 8];
+	}
+}
+
+// Row r moves left by r columns, which is a rotation by 4*r bits within the
+// row's 16 bits
+fn shift_rows(q: &mut Planes) {
+	for x in q.iter_mut() {
+		*x = rotate_rows(*x, |row, r| (row >> (4 * r)) | (row << (16 - 4 * r)));
+	}
+}
+
+fn inv_shift_rows(q: &mut Planes) {
+	for x in q.iter_mut() {
+		*x = rotate_rows(*x, |row, r| (row << (4 * r)) | (row >> (16 - 4 * r)));
+	}
+}
+
+fn rotate_rows<F: Fn(u64, usize) -> u64>(x: u64, rotate: F) -> u64 {
+	let mut ret = 0;
+	for r in 0..4 {
+		let row = (x >> (16 * r)) & 0xffff;
+		ret |= (rotate(row, r) & 0xffff) << (16 * r);
+	}
+	ret
+}
+
+// Multiplication by x of every byte at once
+fn xtime(t: &Planes) -> Planes {
+	[t[7], t[0] ^ t[7], t[1], t[2] ^ t[7], t[3] ^ t[7], t[4], t[5], t[6]]
+}
+
+// Rotating a word by 16 bits moves row r+1 into row r, so with a = q and
+// a(k) = q rotated by 16*k each output row is 2*(a ^ a(1)) ^ a(1) ^ a(2) ^ a(3)
+fn mix_columns(q: &mut Planes) {
+	let mut t = [0; 8];
+	for b in 0..8 {
+		t[b] = q[b] ^ q[b].rotate_right(16);
+	}
+
+	let doubled = xtime(&t);
+	for b in 0..8 {
+		q[b] = doubled[b] ^ q[b].rotate_right(16) ^ q[b].rotate_right(32) ^ q[b].rotate_right(48);
+	}
+}
+
+// InvMixColumns is MixColumns after multiplying each column by 04*x^2 + 05,
+// that is a ^ 4*(a ^ a(2))
+fn inv_mix_columns(q: &mut Planes) {
+	let mut t = [0; 8];
+	for b in 0..8 {
+		t[b] = q[b] ^ q[b].rotate_right(32);
+	}
+
+	let quadrupled = xtime(&xtime(&t));
+	for b in 0..8 {
+		q[b] ^= quadrupled[b];
+	}
+
+	mix_columns(q);
+}
+
+#[cfg(test)]
+mod tests {
+	use bitslice::*;
+	use sbox::{S_BOX, INV_S_BOX};
+	use state::State;
+
+	type Layer = (fn(&mut Planes), fn(&State) -> State);
+
+	// Runs a function on all 256 byte values at once, using bit positions
+	// 0-63 of four separate planes
+	fn all_bytes(f: fn(&mut Planes)) -> Vec<u8> {
+		let mut out = Vec::new();
+		for chunk in 0..4 {
+			let mut q = [0; 8];
+			for pos in 0..64 {
+				spread((chunk * 64 + pos) as u8, pos, &mut q);
+			}
+			f(&mut q);
+			for pos in 0..64 {
+				out.push(gather(&q, pos));
+			}
+		}
+		out
+	}
+
+	#[test]
+	fn test_sub_bytes() {
+		assert_eq!(&S_BOX[..], &all_bytes(sub_bytes)[..]);
+		assert_eq!(&INV_S_BOX[..], &all_bytes(inv_sub_bytes)[..]);
+	}
+
+	#[test]
+	fn test_create_schedule() {
+		let keys: [&[u32]; 3] = [
+			&[0x2b7e1516, 0x28aed2a6, 0xabf71588, 0x09cf4f3c],
+			&[0x8e73b0f7, 0xda0e6452, 0xc810f32b, 0x809079e5, 0x62f8ead2, 0x522c6b7b],
+			&[0x603deb10, 0x15ca71be, 0x2b73aef0, 0x857d7781, 0x1f352c07, 0x3b6108d7, 0x2d9810a3, 0x0914dff4]
+		];
+
+		for words in keys.iter() {
+			let key = Key::new(words);
+			assert_eq!(key.create_schedule(), create_schedule(&key));
+		}
+	}
+
+	// Each layer should agree with State's byte-wise version on every block
+	// position
+	#[test]
+	fn test_round_functions() {
+		let blocks: Vec<u8> = (0..128).map(|i| (i * 37 + 11) as u8).collect();
+
+		let layers: [Layer; 4] = [
+			(shift_rows, State::shift_rows),
+			(inv_shift_rows, State::inv_shift_rows),
+			(mix_columns, State::mix_columns),
+			(inv_mix_columns, State::inv_mix_columns)
+		];
+
+		for &(bitsliced, reference) in layers.iter() {
+			let mut groups = pack(&blocks);
+			for q in groups.iter_mut() {
+				bitsliced(q);
+			}
+			let mut output = vec![0; 128];
+			unpack(&groups, &mut output);
+
+			for (block, expected) in blocks.chunks(16).zip(output.chunks(16)) {
+				assert_eq!(reference(&State::from_slice(block)).to_byte_array(), expected);
+			}
+		}
+	}
+
+	#[test]
+	fn test_blocks() {
+		let key = Key::new(&[0x2b7e1516, 0x28aed2a6, 0xabf71588, 0x09cf4f3c]);
+		let keys = round_keys(&create_schedule(&key));
+		let schedule = key.create_schedule();
+
+		// Eleven blocks makes one full batch and one partial one
+		let input: Vec<u8> = (0..176).map(|i| i as u8).collect();
+		let mut data = input.clone();
+		encrypt_blocks(&keys, &mut data);
+
+		for (block, output) in input.chunks(16).zip(data.chunks(16)) {
+			assert_eq!(::ttable::encrypt(&schedule, block), output);
+			assert_eq!(encrypt(&keys, block), output);
+		}
+
+		decrypt_blocks(&keys, &mut data);
+		assert_eq!(input, data);
+	}
+}
@@ -0,0 +1,192 @@
+#[cfg(not(feature = "std"))]
+use core::convert;
+use core::iter;
+use core::ops;
+
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+#[cfg(feature = "std")]
+use locked::Locked;
+#[cfg(feature = "std")]
+use secret::Error;
+use zeroize::zeroize;
+
+// What locking key material can fail with.  Without std nothing is locked
+#[cfg(feature = "std")]
+pub type LockError = Error;
+#[cfg(not(feature = "std"))]
+pub type LockError = convert::Infallible;
+
+// Enough round keys for AES-256
+pub const ROUND_KEYS: usize = 15;
+
+// Where key material lives: an array big enough for the largest key size, or
+// with std, locked pages shared between the copies of a SecretKey's key.
+// Either way it's wiped when the last user is done with it
+pub enum Buffer<T: Copy + Default, const N: usize> {
+	Inline([T; N], usize),
+	#[cfg(feature = "std")]
+	Locked(Arc<Locked<T>>)
+}
+
+impl<T: Copy + Default, const N: usize> Buffer<T, N> {
+	pub fn new() -> Buffer<T, N> {
+		Buffer::Inline([T::default(); N], 0)
+	}
+
+	// For const fns, which can't call T::default()
+	pub const fn from_array(values: [T; N], len: usize) -> Buffer<T, N> {
+		if len > N { panic!("Too much key material!"); }
+
+		Buffer::Inline(values, len)
+	}
+
+	pub fn from_slice(values: &[T]) -> Buffer<T, N> {
+		values.iter().cloned().collect()
+	}
+
+	// Only for filling a buffer as it's made, before it's locked
+	pub const fn push(&mut self, value: T) {
+		match *self {
+			Buffer::Inline(ref mut values, ref mut len) => {
+				if *len == N { panic!("Too much key material!"); }
+
+				values[*len] = value;
+				*len += 1;
+			},
+			#[cfg(feature = "std")]
+			Buffer::Locked(..) => panic!("Locked key material can't be changed!")
+		}
+	}
+
+	// Moved into locked pages if the key this is derived from is in them.
+	// Nothing is locked for an empty buffer, which is what backends that
+	// don't use it get
+	#[cfg(feature = "std")]
+	pub fn try_like(self, locked: bool) -> Result<Buffer<T, N>, LockError> {
+		if !locked || self.is_empty() || self.is_locked() { return Ok(self); }
+
+		self.lock()
+	}
+
+	#[cfg(not(feature = "std"))]
+	pub fn try_like(self, _locked: bool) -> Result<Buffer<T, N>, LockError> {
+		Ok(self)
+	}
+
+	// For callers that can't hand the error back.  Once a key has been locked
+	// the limit should only run out if far too many keys are being kept, so
+	// there that's treated as a bug
+	pub fn like(self, locked: bool) -> Buffer<T, N> {
+		self.try_like(locked).unwrap_or_else(|error| panic!("Couldn't lock memory for key material: {}", error))
+	}
+
+	#[cfg(feature = "std")]
+	pub fn lock(&self) -> Result<Buffer<T, N>, Error> {
+		Ok(Buffer::Locked(Arc::new(Locked::new(self)?)))
+	}
+
+	pub const fn is_locked(&self) -> bool {
+		match *self {
+			Buffer::Inline(..) => false,
+			#[cfg(feature = "std")]
+			Buffer::Locked(..) => true
+		}
+	}
+
+	// For const fns, which can't go through the Arc.  The buffers they read
+	// are ones they made themselves, and those are never locked
+	pub const fn inline_slice(&self) -> &[T] {
+		match *self {
+			Buffer::Inline(ref values, len) => values.split_at(len).0,
+			#[cfg(feature = "std")]
+			Buffer::Locked(_) => panic!("Locked key material can't be read in a const fn!")
+		}
+	}
+}
+
+impl<T: Copy + Default, const N: usize> iter::FromIterator<T> for Buffer<T, N> {
+	fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Buffer<T, N> {
+		let mut buffer = Buffer::new();
+		for value in values {
+			buffer.push(value);
+		}
+
+		buffer
+	}
+}
+
+impl<T: Copy + Default, const N: usize> ops::Deref for Buffer<T, N> {
+	type Target = [T];
+
+	fn deref(&self) -> &[T] {
+		match *self {
+			Buffer::Inline(ref values, len) => &values[..len],
+			#[cfg(feature = "std")]
+			Buffer::Locked(ref locked) => locked
+		}
+	}
+}
+
+impl<T: Copy + Default, const N: usize> Clone for Buffer<T, N> {
+	fn clone(&self) -> Buffer<T, N> {
+		match *self {
+			Buffer::Inline(values, len) => Buffer::Inline(values, len),
+			#[cfg(feature = "std")]
+			Buffer::Locked(ref locked) => Buffer::Locked(locked.clone())
+		}
+	}
+}
+
+impl<T: Copy + Default + PartialEq, const N: usize> PartialEq for Buffer<T, N> {
+	fn eq(&self, other: &Buffer<T, N>) -> bool {
+		**self == **other
+	}
+}
+
+impl<T: Copy + Default, const N: usize> Drop for Buffer<T, N> {
+	fn drop(&mut self) {
+		match *self {
+			Buffer::Inline(ref mut values, _) => zeroize(values),
+			// Locked wipes its own pages
+			#[cfg(feature = "std")]
+			Buffer::Locked(..) => {}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use buffer::*;
+
+	#[test]
+	fn test_buffer() {
+		let mut buffer: Buffer<u32, 4> = Buffer::new();
+		assert!(buffer.is_empty());
+
+		buffer.push(1);
+		buffer.push(2);
+		assert_eq!(&[1, 2], &buffer[..]);
+		assert!(buffer == Buffer::from_slice(&[1, 2]));
+		assert!(!buffer.clone().like(false).is_locked());
+	}
+
+	#[test]
+	#[should_panic]
+	fn test_overflow() {
+		Buffer::<u8, 2>::from_slice(&[1, 2, 3]);
+	}
+
+	#[cfg(feature = "std")]
+	#[test]
+	fn test_lock() {
+		let buffer: Buffer<u64, 4> = Buffer::from_slice(&[1, 2, 3]);
+
+		if let Ok(locked) = buffer.lock() {
+			assert!(locked.is_locked());
+			assert!(locked == buffer);
+			assert!(locked.clone().is_locked());
+		}
+	}
+}
@@ -0,0 +1,180 @@
+use key::{self, Key};
+use state::State;
+use zeroize::zeroize;
+
+// AES with each round key worked out as it's needed instead of the whole
+// schedule being expanded up front, for devices where the 176 to 240 bytes
+// of a schedule matter.  Only Nk words are ever kept: encryption runs the key
+// schedule forwards from the key, and decryption runs it backwards from its
+// last Nk words, which are found once when the decryptor is made
+pub struct CompactEncryptor {
+	key: Key
+}
+
+pub struct CompactDecryptor {
+	last_words: Key
+}
+
+// Nk consecutive words of the key schedule, w[low] to w[low + Nk - 1], with
+// w[i] kept in words[i % Nk].  Sliding forwards or backwards one word is the
+// same xor into the slot that's being replaced
+struct Window {
+	words: [u32; 8],
+	nk: usize,
+	low: usize
+}
+
+impl Window {
+	fn new(words: &[u32], low: usize) -> Window {
+		let nk = words.len();
+		let mut window = Window{ words: [0; 8], nk, low };
+		for (i, &word) in words.iter().enumerate() {
+			window.words[(low + i) % nk] = word;
+		}
+
+		window
+	}
+
+	fn word(&mut self, i: usize) -> u32 {
+		while i >= self.low + self.nk {
+			self.slide(self.low + self.nk);
+			self.low += 1;
+		}
+		while i < self.low {
+			self.slide(self.low + self.nk - 1);
+			self.low -= 1;
+		}
+
+		self.words[i % self.nk]
+	}
+
+	// Turns w[i - Nk] into w[i] or back, using w[i - 1]
+	fn slide(&mut self, i: usize) {
+		let temp = self.words[(i - 1) % self.nk];
+		self.words[i % self.nk] ^= key::schedule_core(temp, i, self.nk);
+	}
+
+	fn round_key(&mut self, round: usize) -> [u32; 4] {
+		[self.word(4*round), self.word(4*round + 1), self.word(4*round + 2), self.word(4*round + 3)]
+	}
+}
+
+impl Drop for Window {
+	fn drop(&mut self) {
+		zeroize(&mut self.words);
+	}
+}
+
+impl CompactEncryptor {
+	pub fn using(key: Key) -> CompactEncryptor {
+		CompactEncryptor{ key }
+	}
+
+	pub fn encrypt(&self, input: &[u8]) -> [u8; 16] {
+		if input.len() != 16 { panic!("Can only encrypt 16 byte blocks!"); }
+
+		let nr = self.key.words().len() + 6;
+		let mut window = Window::new(self.key.words(), 0);
+
+		let mut state = State::from_slice(input).add_round_key(&window.round_key(0));
+		for round in 1..nr {
+			state = state.sub_bytes().shift_rows().mix_columns().add_round_key(&window.round_key(round));
+		}
+		state = state.sub_bytes().shift_rows().add_round_key(&window.round_key(nr));
+
+		state.to_byte_array()
+	}
+}
+
+impl CompactDecryptor {
+	pub fn using(key: Key) -> CompactDecryptor {
+		let nk = key.words().len();
+		let end = 4 * (nk + 7);
+
+		let mut window = Window::new(key.words(), 0);
+		let mut last_words = [0; 8];
+		for i in 0..nk {
+			last_words[i] = window.word(end - nk + i);
+		}
+		let last_words_key = key.derive(&last_words[..nk]);
+		zeroize(&mut last_words);
+
+		CompactDecryptor{ last_words: last_words_key }
+	}
+
+	pub fn decrypt(&self, input: &[u8]) -> [u8; 16] {
+		if input.len() != 16 { panic!("Can only decrypt 16 byte blocks!"); }
+
+		let nk = self.last_words.words().len();
+		let nr = nk + 6;
+		let mut window = Window::new(self.last_words.words(), 4 * (nr + 1) - nk);
+
+		let mut state = State::from_slice(input).add_round_key(&window.round_key(nr));
+		for round in (1..nr).rev() {
+			state = state.inv_shift_rows().inv_sub_bytes().add_round_key(&window.round_key(round)).inv_mix_columns();
+		}
+		state = state.inv_shift_rows().inv_sub_bytes().add_round_key(&window.round_key(0));
+
+		state.to_byte_array()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use compact::*;
+	use aes::{Encryptor, Decryptor};
+
+	fn keys() -> [Key; 3] {
+		[
+			Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f]),
+			Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f, 0x10111213, 0x14151617]),
+			Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f, 0x10111213, 0x14151617, 0x18191a1b, 0x1c1d1e1f])
+		]
+	}
+
+	#[test]
+	fn test_window() {
+		for key in keys().iter() {
+			let schedule = key.create_schedule();
+			let end = 4 * (schedule.rounds() + 1);
+
+			let mut window = Window::new(key.words(), 0);
+			for round in 0..schedule.rounds() + 1 {
+				assert_eq!(&schedule[round], &window.round_key(round)[..]);
+			}
+			for round in (0..schedule.rounds() + 1).rev() {
+				assert_eq!(&schedule[round], &window.round_key(round)[..]);
+			}
+			assert_eq!(&key.words()[..4], &[window.word(0), window.word(1), window.word(2), window.word(3)][..]);
+
+			// And from the end, as the decryptor does
+			let nk = key.words().len();
+			let mut last = [0; 8];
+			for i in 0..nk {
+				last[i] = window.word(end - nk + i);
+			}
+			let mut window = Window::new(&last[..nk], end - nk);
+			assert_eq!(&schedule[0], &window.round_key(0)[..]);
+		}
+	}
+
+	#[test]
+	fn test_encrypt() {
+		let input = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+
+		for key in keys().iter() {
+			let encryptor = CompactEncryptor::using(key.clone());
+			let ciphertext = encryptor.encrypt(&input);
+			assert_eq!(Encryptor::using(key.clone()).encrypt(&input, false), ciphertext);
+
+			let decryptor = CompactDecryptor::using(key.clone());
+			assert_eq!(Decryptor::using(key.clone()).decrypt(&ciphertext, false), decryptor.decrypt(&ciphertext));
+			assert_eq!(input, decryptor.decrypt(&ciphertext));
+		}
+
+		// FIPS-197 C.1
+		assert_eq!([
+			0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4, 0xc5, 0x5a
+		], CompactEncryptor::using(keys()[0].clone()).encrypt(&input));
+	}
+}
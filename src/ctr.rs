@@ -0,0 +1,127 @@
+use aes::Encryptor;
+use key::Key;
+use zeroize::zeroize;
+
+// Counter mode from NIST SP 800-38A.  The keystream is the encryption of
+// successive counter blocks, with the whole block incremented as a big endian
+// number, so encryption and decryption are the same operation.  Any block of
+// the keystream can be computed directly, which is what lets a message be
+// processed in independent pieces
+pub struct Ctr {
+	encryptor: Encryptor
+}
+
+impl Ctr {
+	pub fn using(key: Key) -> Ctr {
+		Ctr{ encryptor: Encryptor::using(key) }
+	}
+
+	#[cfg(feature = "std")]
+	pub fn apply(&self, iv: &[u8], input: &[u8]) -> Vec<u8> {
+		self.apply_from(iv, 0, input)
+	}
+
+	// Starts the given number of blocks into the keystream
+	#[cfg(feature = "std")]
+	pub fn apply_from(&self, iv: &[u8], block: u128, input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.apply_in_place(iv, block, &mut output);
+		output
+	}
+
+	// The keystream is made eight blocks at a time on the stack, so nothing
+	// is allocated
+	pub fn apply_in_place(&self, iv: &[u8], block: u128, data: &mut [u8]) {
+		if iv.len() != 16 { panic!("CTR initial counter must be 16 bytes!"); }
+
+		let mut bytes = [0; 16];
+		bytes.copy_from_slice(iv);
+		let start = u128::from_be_bytes(bytes).wrapping_add(block);
+
+		let mut keystream = [0; 128];
+		for (i, chunk) in data.chunks_mut(128).enumerate() {
+			let blocks = chunk.len().div_ceil(16);
+			for j in 0..blocks {
+				let counter = start.wrapping_add((i * 8 + j) as u128);
+				keystream[j*16..(j+1)*16].copy_from_slice(&counter.to_be_bytes());
+			}
+			self.encryptor.encrypt_blocks_in_place(&mut keystream[..blocks*16]);
+
+			for (a, b) in chunk.iter_mut().zip(keystream.iter()) {
+				*a ^= b;
+			}
+		}
+		zeroize(&mut keystream);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use ctr::*;
+
+	fn ctr() -> Ctr {
+		Ctr::using(Key::new(&[0x2b7e1516, 0x28aed2a6, 0xabf71588, 0x09cf4f3c]))
+	}
+
+	const IV: [u8; 16] = [
+		0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd, 0xfe, 0xff
+	];
+
+	const PLAINTEXT: [u8; 64] = [
+		0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17, 0x2a,
+		0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c, 0x9e, 0xb7, 0x6f, 0xac, 0x45, 0xaf, 0x8e, 0x51,
+		0x30, 0xc8, 0x1c, 0x46, 0xa3, 0x5c, 0xe4, 0x11, 0xe5, 0xfb, 0xc1, 0x19, 0x1a, 0x0a, 0x52, 0xef,
+		0xf6, 0x9f, 0x24, 0x45, 0xdf, 0x4f, 0x9b, 0x17, 0xad, 0x2b, 0x41, 0x7b, 0xe6, 0x6c, 0x37, 0x10
+	];
+
+	// F.5.1 CTR-AES128.Encrypt from SP 800-38A
+	const CIPHERTEXT: [u8; 64] = [
+		0x87, 0x4d, 0x61, 0x91, 0xb6, 0x20, 0xe3, 0x26, 0x1b, 0xef, 0x68, 0x64, 0x99, 0x0d, 0xb6, 0xce,
+		0x98, 0x06, 0xf6, 0x6b, 0x79, 0x70, 0xfd, 0xff, 0x86, 0x17, 0x18, 0x7b, 0xb9, 0xff, 0xfd, 0xff,
+		0x5a, 0xe4, 0xdf, 0x3e, 0xdb, 0xd5, 0xd3, 0x5e, 0x5b, 0x4f, 0x09, 0x02, 0x0d, 0xb0, 0x3e, 0xab,
+		0x1e, 0x03, 0x1d, 0xda, 0x2f, 0xbe, 0x03, 0xd1, 0x79, 0x21, 0x70, 0xa0, 0xf3, 0x00, 0x9c, 0xee
+	];
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_apply() {
+		let ctr = ctr();
+
+		assert_eq!(&CIPHERTEXT[..], &ctr.apply(&IV, &PLAINTEXT)[..]);
+		assert_eq!(&PLAINTEXT[..], &ctr.apply(&IV, &CIPHERTEXT)[..]);
+
+		// A partial last block uses the start of its keystream block
+		assert_eq!(&CIPHERTEXT[..37], &ctr.apply(&IV, &PLAINTEXT[..37])[..]);
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_apply_from() {
+		let ctr = ctr();
+
+		// The counter carries across the whole block, and wraps around
+		assert_eq!(&CIPHERTEXT[16..], &ctr.apply_from(&IV, 1, &PLAINTEXT[16..])[..]);
+		assert_eq!(&CIPHERTEXT[48..], &ctr.apply_from(&[0xff; 16], 0xf0f1f2f3f4f5f6f7f8f9fafbfcfdff03, &PLAINTEXT[48..])[..]);
+	}
+
+	#[test]
+	fn test_apply_in_place() {
+		let ctr = ctr();
+
+		// Longer than the eight blocks done at a time, with a partial block
+		let mut data = [0; 200];
+		ctr.apply_in_place(&IV, 0, &mut data[..195]);
+		let keystream = data;
+
+		data = [0; 200];
+		data[..64].copy_from_slice(&PLAINTEXT);
+		ctr.apply_in_place(&IV, 0, &mut data[..64]);
+		assert_eq!(&CIPHERTEXT[..], &data[..64]);
+
+		// The blocks past the first eight follow on from them
+		let mut tail = [0; 35];
+		ctr.apply_in_place(&IV, 10, &mut tail);
+		assert_eq!(&keystream[160..195], &tail[..]);
+		assert_eq!([0; 5], keystream[195..]);
+	}
+}
@@ -0,0 +1,277 @@
+use aes::{Encryptor, Decryptor};
+use key::Key;
+
+// The three ciphertext stealing variants from the addendum to NIST SP 800-38A.
+// They only differ in the order of the last two ciphertext blocks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variant {
+	// The partial penultimate block is kept in place
+	CS1,
+	// The last two blocks are swapped, but only when the last one is partial
+	CS2,
+	// The last two blocks are always swapped, as used by Kerberos (RFC 3962)
+	CS3
+}
+
+pub struct CbcCs {
+	variant: Variant,
+	encryptor: Encryptor,
+	decryptor: Decryptor
+}
+
+impl CbcCs {
+	pub fn using(key: Key, variant: Variant) -> CbcCs {
+		CbcCs{
+			variant,
+			encryptor: Encryptor::using(key.clone()),
+			decryptor: Decryptor::using(key)
+		}
+	}
+
+	#[cfg(feature = "std")]
+	pub fn encrypt(&self, iv: &[u8], input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.encrypt_in_place(iv, &mut output);
+		output
+	}
+
+	#[cfg(feature = "std")]
+	pub fn decrypt(&self, iv: &[u8], input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.decrypt_in_place(iv, &mut output);
+		output
+	}
+
+	pub fn encrypt_in_place(&self, iv: &[u8], data: &mut [u8]) {
+		if iv.len() != 16 { panic!("IV must be 16 bytes!"); }
+		if data.len() < 16 { panic!("Can only encrypt at least one full block!"); }
+
+		let n = data.len().div_ceil(16);
+		let d = data.len() - (n-1)*16;
+
+		// Ordinary CBC up to the last block, which is padded out with zeroes
+		let mut prev = [0; 16];
+		prev.copy_from_slice(iv);
+
+		for chunk in data[..(n-1)*16].chunks_mut(16) {
+			for i in 0..16 {
+				prev[i] ^= chunk[i];
+			}
+
+			prev = self.encryptor.encrypt(&prev, false);
+			chunk.copy_from_slice(&prev);
+		}
+
+		let tail = &mut data[(n-1)*16..];
+		for i in 0..d {
+			prev[i] ^= tail[i];
+		}
+		let last = self.encryptor.encrypt(&prev, false);
+
+		if n == 1 {
+			tail.copy_from_slice(&last);
+			return;
+		}
+
+		// Only the first d bytes of C[n-1] are kept, the rest can be
+		// recovered from C[n] during decryption
+		let tail = &mut data[(n-2)*16..];
+		if self.swaps(d) {
+			tail.copy_within(..d, 16);
+			tail[..16].copy_from_slice(&last);
+		} else {
+			tail[d..].copy_from_slice(&last);
+		}
+	}
+
+	pub fn decrypt_in_place(&self, iv: &[u8], data: &mut [u8]) {
+		if iv.len() != 16 { panic!("IV must be 16 bytes!"); }
+		if data.len() < 16 { panic!("Can only decrypt at least one full block!"); }
+
+		let n = data.len().div_ceil(16);
+		let d = data.len() - (n-1)*16;
+
+		if n == 1 { return self.cbc_decrypt_in_place(iv, data); }
+
+		let tail = &mut data[(n-2)*16..];
+		let mut stolen = [0; 16];
+		let mut last = [0; 16];
+		if self.swaps(d) {
+			stolen[..d].copy_from_slice(&tail[16..]);
+			last.copy_from_slice(&tail[..16]);
+		} else {
+			stolen[..d].copy_from_slice(&tail[..d]);
+			last.copy_from_slice(&tail[d..]);
+		}
+
+		// D(C[n]) = C[n-1] ^ (P[n]* || 0), so its last 16-d bytes are
+		// exactly the bytes that were stolen from C[n-1]
+		let z = self.decryptor.decrypt(&last, false);
+
+		tail[..d].copy_from_slice(&stolen[..d]);
+		tail[d..16].copy_from_slice(&z[d..]);
+		for i in 0..d {
+			tail[16 + i] = z[i] ^ stolen[i];
+		}
+
+		self.cbc_decrypt_in_place(iv, &mut data[..(n-1)*16]);
+	}
+
+	fn cbc_decrypt_in_place(&self, iv: &[u8], data: &mut [u8]) {
+		let mut prev = [0; 16];
+		prev.copy_from_slice(iv);
+
+		for chunk in data.chunks_mut(16) {
+			let block = self.decryptor.decrypt(chunk, false);
+			for i in 0..16 {
+				let c = chunk[i];
+				chunk[i] = block[i] ^ prev[i];
+				prev[i] = c;
+			}
+		}
+	}
+
+	fn swaps(&self, d: usize) -> bool {
+		match self.variant {
+			Variant::CS1 => false,
+			Variant::CS2 => d != 16,
+			Variant::CS3 => true
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use cts::*;
+
+	const INPUT: &[u8] = b"I would like the General Gau's Chicken, please, and wonton soup.";
+
+	fn key() -> Key {
+		Key::from_bytes(b"chicken teriyaki")
+	}
+
+	// Test vectors are from Appendix B of RFC 3962, which uses CS3 with an all zero IV
+	const CS3_VECTORS: [(usize, &[u8]); 6] = [
+		(17, &[
+			0xc6, 0x35, 0x35, 0x68, 0xf2, 0xbf, 0x8c, 0xb4, 0xd8, 0xa5, 0x80, 0x36, 0x2d, 0xa7, 0xff, 0x7f,
+			0x97
+		]),
+		(31, &[
+			0xfc, 0x00, 0x78, 0x3e, 0x0e, 0xfd, 0xb2, 0xc1, 0xd4, 0x45, 0xd4, 0xc8, 0xef, 0xf7, 0xed, 0x22,
+			0x97, 0x68, 0x72, 0x68, 0xd6, 0xec, 0xcc, 0xc0, 0xc0, 0x7b, 0x25, 0xe2, 0x5e, 0xcf, 0xe5
+		]),
+		(32, &[
+			0x39, 0x31, 0x25, 0x23, 0xa7, 0x86, 0x62, 0xd5, 0xbe, 0x7f, 0xcb, 0xcc, 0x98, 0xeb, 0xf5, 0xa8,
+			0x97, 0x68, 0x72, 0x68, 0xd6, 0xec, 0xcc, 0xc0, 0xc0, 0x7b, 0x25, 0xe2, 0x5e, 0xcf, 0xe5, 0x84
+		]),
+		(47, &[
+			0x97, 0x68, 0x72, 0x68, 0xd6, 0xec, 0xcc, 0xc0, 0xc0, 0x7b, 0x25, 0xe2, 0x5e, 0xcf, 0xe5, 0x84,
+			0xb3, 0xff, 0xfd, 0x94, 0x0c, 0x16, 0xa1, 0x8c, 0x1b, 0x55, 0x49, 0xd2, 0xf8, 0x38, 0x02, 0x9e,
+			0x39, 0x31, 0x25, 0x23, 0xa7, 0x86, 0x62, 0xd5, 0xbe, 0x7f, 0xcb, 0xcc, 0x98, 0xeb, 0xf5
+		]),
+		(48, &[
+			0x97, 0x68, 0x72, 0x68, 0xd6, 0xec, 0xcc, 0xc0, 0xc0, 0x7b, 0x25, 0xe2, 0x5e, 0xcf, 0xe5, 0x84,
+			0x9d, 0xad, 0x8b, 0xbb, 0x96, 0xc4, 0xcd, 0xc0, 0x3b, 0xc1, 0x03, 0xe1, 0xa1, 0x94, 0xbb, 0xd8,
+			0x39, 0x31, 0x25, 0x23, 0xa7, 0x86, 0x62, 0xd5, 0xbe, 0x7f, 0xcb, 0xcc, 0x98, 0xeb, 0xf5, 0xa8
+		]),
+		(64, &[
+			0x97, 0x68, 0x72, 0x68, 0xd6, 0xec, 0xcc, 0xc0, 0xc0, 0x7b, 0x25, 0xe2, 0x5e, 0xcf, 0xe5, 0x84,
+			0x39, 0x31, 0x25, 0x23, 0xa7, 0x86, 0x62, 0xd5, 0xbe, 0x7f, 0xcb, 0xcc, 0x98, 0xeb, 0xf5, 0xa8,
+			0x48, 0x07, 0xef, 0xe8, 0x36, 0xee, 0x89, 0xa5, 0x26, 0x73, 0x0d, 0xbc, 0x2f, 0x7b, 0xc8, 0x40,
+			0x9d, 0xad, 0x8b, 0xbb, 0x96, 0xc4, 0xcd, 0xc0, 0x3b, 0xc1, 0x03, 0xe1, 0xa1, 0x94, 0xbb, 0xd8
+		]),
+	];
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_cs3_encrypt() {
+		let cts = CbcCs::using(key(), Variant::CS3);
+
+		for &(len, expected) in CS3_VECTORS.iter() {
+			assert_eq!(expected, &cts.encrypt(&[0; 16], &INPUT[..len])[..]);
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_cs3_decrypt() {
+		let cts = CbcCs::using(key(), Variant::CS3);
+
+		for &(len, input) in CS3_VECTORS.iter() {
+			assert_eq!(&INPUT[..len], &cts.decrypt(&[0; 16], input)[..]);
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_variant_ordering() {
+		let cs1 = CbcCs::using(key(), Variant::CS1);
+		let cs2 = CbcCs::using(key(), Variant::CS2);
+
+		for &(len, cs3) in CS3_VECTORS.iter() {
+			let input = &INPUT[..len];
+			let n = cs3.len();
+			let d = n - (n.div_ceil(16) - 1)*16;
+
+			// CS1 and CS3 are the same but with the last two blocks swapped
+			let mut expected = cs3[..n-16-d].to_vec();
+			expected.extend_from_slice(&cs3[n-d..]);
+			expected.extend_from_slice(&cs3[n-16-d..n-d]);
+			assert_eq!(expected, cs1.encrypt(&[0; 16], input));
+
+			// CS2 only swaps when the last block is partial
+			if d == 16 {
+				assert_eq!(expected, cs2.encrypt(&[0; 16], input));
+			} else {
+				assert_eq!(cs3, &cs2.encrypt(&[0; 16], input)[..]);
+			}
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_round_trip() {
+		let iv = [
+			0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+			0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f
+		];
+
+		for &variant in [Variant::CS1, Variant::CS2, Variant::CS3].iter() {
+			let cts = CbcCs::using(key(), variant);
+
+			for len in 16..INPUT.len() {
+				let ciphertext = cts.encrypt(&iv, &INPUT[..len]);
+				assert_eq!(len, ciphertext.len());
+				assert_eq!(&INPUT[..len], &cts.decrypt(&iv, &ciphertext)[..]);
+			}
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_single_block() {
+		let cts = CbcCs::using(key(), Variant::CS3);
+		let encryptor = Encryptor::using(key());
+
+		assert_eq!(encryptor.encrypt(&INPUT[..16], false), &cts.encrypt(&[0; 16], &INPUT[..16])[..]);
+	}
+
+	#[test]
+	fn test_in_place() {
+		for &variant in [Variant::CS1, Variant::CS2, Variant::CS3].iter() {
+			let cts = CbcCs::using(key(), variant);
+
+			for &(len, cs3) in CS3_VECTORS.iter() {
+				let mut data = [0; 64];
+				data[..len].copy_from_slice(&INPUT[..len]);
+
+				cts.encrypt_in_place(&[0; 16], &mut data[..len]);
+				if variant == Variant::CS3 {
+					assert_eq!(cs3, &data[..len]);
+				}
+				cts.decrypt_in_place(&[0; 16], &mut data[..len]);
+				assert_eq!(&INPUT[..len], &data[..len]);
+			}
+		}
+	}
+}
@@ -0,0 +1,300 @@
+use core::slice;
+
+use aes::{Encryptor, Decryptor};
+use gf128::mul_alpha;
+use key::Key;
+use zeroize::zeroize;
+
+// EME2 from IEEE 1619.2, Halevi's EME* extension of the Encrypt-Mix-Encrypt
+// construction to arbitrary lengths and associated data (the tweak).  It is an
+// ECB pass with masks derived from K_ECB, a mixing layer that ties every block
+// to every other block and the tweak, and a second masked ECB pass.  A partial
+// last block bypasses both ECB passes and is encrypted with an extra mask
+pub struct Eme2 {
+	encryptor: Encryptor,
+	decryptor: Decryptor,
+	k_ad: u128,
+	k_ecb: u128
+}
+
+// The mixing masks are refreshed with an extra block cipher call this often
+const BLOCKS_PER_MASK: usize = 128;
+
+impl Eme2 {
+	pub fn using(k_ad: &[u8], k_ecb: &[u8], key: Key) -> Eme2 {
+		if k_ad.len() != 16 || k_ecb.len() != 16 { panic!("EME2 mask keys must be 16 bytes!"); }
+
+		Eme2{
+			encryptor: Encryptor::using(key.clone()),
+			decryptor: Decryptor::using(key),
+			k_ad: to_block(k_ad),
+			k_ecb: to_block(k_ecb)
+		}
+	}
+
+	#[cfg(feature = "std")]
+	pub fn encrypt(&self, tweak: &[u8], input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.encrypt_in_place(tweak, &mut output);
+		output
+	}
+
+	#[cfg(feature = "std")]
+	pub fn decrypt(&self, tweak: &[u8], input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.decrypt_in_place(tweak, &mut output);
+		output
+	}
+
+	pub fn encrypt_in_place(&self, tweak: &[u8], data: &mut [u8]) {
+		if data.len() < 16 { panic!("Can only encrypt at least one full block!"); }
+
+		self.transform(tweak, data, true)
+	}
+
+	pub fn decrypt_in_place(&self, tweak: &[u8], data: &mut [u8]) {
+		if data.len() < 16 { panic!("Can only decrypt at least one full block!"); }
+
+		self.transform(tweak, data, false)
+	}
+
+	// Decryption is the same sequence of steps as encryption with the block
+	// cipher inverted, so both directions share this.  The full blocks hold
+	// the intermediate values between the passes
+	fn transform(&self, tweak: &[u8], data: &mut [u8], encrypt: bool) {
+		let cipher = |block: u128| -> u128 {
+			let bytes = block.to_le_bytes();
+			to_block(&if encrypt {
+				self.encryptor.encrypt(&bytes, false)
+			} else {
+				self.decryptor.decrypt(&bytes, false)
+			})
+		};
+
+		let full = data.len() / 16;
+		let t_star = self.tweak_hash(tweak);
+		let (blocks, tail) = data.split_at_mut(full*16);
+
+		let mut mp = t_star;
+		let mut l = self.k_ecb;
+		for chunk in blocks.chunks_mut(16) {
+			let block = cipher(to_block(chunk) ^ l);
+			chunk.copy_from_slice(&block.to_le_bytes());
+			mp ^= block;
+			l = mul_alpha(l);
+		}
+		if !tail.is_empty() {
+			mp ^= pad(tail);
+		}
+
+		let (mc, mm) = if tail.is_empty() {
+			(cipher(mp), 0)
+		} else {
+			let mm = cipher(mp);
+			(cipher(mm), mm)
+		};
+
+		// Each refresh starts again from the first mask M_1, not from the
+		// mask it replaces
+		let m1 = mp ^ mc;
+		let mut m = m1;
+		let mut first = mc ^ t_star;
+		for (i, chunk) in blocks.chunks_mut(16).enumerate().skip(1) {
+			let mut block = to_block(chunk);
+			if i % BLOCKS_PER_MASK == 0 {
+				let mp = block ^ m1;
+				let mc = cipher(mp);
+				m = mp ^ mc;
+				block = mc ^ m1;
+			} else {
+				m = mul_alpha(m);
+				block ^= m;
+			}
+
+			chunk.copy_from_slice(&block.to_le_bytes());
+			first ^= block;
+		}
+
+		if !tail.is_empty() {
+			let mask = mm.to_le_bytes();
+			for i in 0..tail.len() {
+				tail[i] ^= mask[i];
+			}
+			first ^= pad(tail);
+		}
+
+		blocks[..16].copy_from_slice(&first.to_le_bytes());
+
+		let mut l = self.k_ecb;
+		for chunk in blocks.chunks_mut(16) {
+			chunk.copy_from_slice(&(cipher(to_block(chunk)) ^ l).to_le_bytes());
+			l = mul_alpha(l);
+		}
+	}
+
+	// Compresses the associated data into a single block.  An empty tweak
+	// still gets a key dependent value so it can't be confused with any other
+	fn tweak_hash(&self, tweak: &[u8]) -> u128 {
+		if tweak.is_empty() {
+			return to_block(&self.encryptor.encrypt(&self.k_ad.to_le_bytes(), false));
+		}
+
+		let mut k = self.k_ad;
+		let mut t_star = 0;
+		for chunk in tweak.chunks(16) {
+			k = mul_alpha(k);
+
+			let block = if chunk.len() == 16 {
+				to_block(chunk)
+			} else {
+				// Use a distinct mask for the padded block
+				k = mul_alpha(k);
+				pad(chunk)
+			};
+
+			t_star ^= to_block(&self.encryptor.encrypt(&(block ^ k).to_le_bytes(), false)) ^ k;
+		}
+
+		t_star
+	}
+}
+
+impl Drop for Eme2 {
+	fn drop(&mut self) {
+		zeroize(slice::from_mut(&mut self.k_ad));
+		zeroize(slice::from_mut(&mut self.k_ecb));
+	}
+}
+
+fn to_block(bytes: &[u8]) -> u128 {
+	let mut block = [0; 16];
+	block.copy_from_slice(bytes);
+	u128::from_le_bytes(block)
+}
+
+// Pads a partial block with a 1 bit followed by zeroes
+fn pad(bytes: &[u8]) -> u128 {
+	let mut block = [0; 16];
+	block[..bytes.len()].copy_from_slice(bytes);
+	block[bytes.len()] = 0x80;
+	u128::from_le_bytes(block)
+}
+
+#[cfg(test)]
+mod tests {
+	use eme2::*;
+
+	fn eme2() -> Eme2 {
+		Eme2::using(&[0x11; 16], &[0x22; 16], Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f]))
+	}
+
+	#[cfg(feature = "std")]
+	fn sequence(len: usize) -> Vec<u8> {
+		(0..len).map(|i| (i * 7) as u8).collect()
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_round_trip() {
+		let eme2 = eme2();
+
+		for &tweak in [&b""[..], &b"sector 42"[..], &[0x5a; 16][..], &[0xa5; 33][..]].iter() {
+			for len in 16..80 {
+				let input = sequence(len);
+				let ciphertext = eme2.encrypt(tweak, &input);
+				assert_eq!(len, ciphertext.len());
+				assert_ne!(input, ciphertext);
+				assert_eq!(input, eme2.decrypt(tweak, &ciphertext));
+			}
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_mask_refresh() {
+		// A 4096 byte sector passes the point where the mixing mask is
+		// refreshed with another block cipher call
+		let eme2 = eme2();
+		let input = sequence(4096 + 5);
+
+		let ciphertext = eme2.encrypt(b"sector", &input);
+		assert_eq!(input, eme2.decrypt(b"sector", &ciphertext));
+	}
+
+	// These come from a direct transcription of the IEEE 1619.2 pseudocode
+	// over an independent AES, not from the standard's own annex
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_known_answers() {
+		let eme2 = eme2();
+
+		let expected = [
+			0x36, 0x7c, 0x27, 0x20, 0x58, 0x7d, 0xa6, 0x34, 0x45, 0x15, 0x58, 0xe7, 0x80, 0xd0, 0x31, 0x77
+		];
+		assert_eq!(&expected[..], &eme2.encrypt(b"", &sequence(16))[..]);
+
+		let expected = [
+			0x18, 0x67, 0x14, 0x7c, 0xc1, 0x1b, 0xf1, 0x89, 0xe1, 0x1a, 0x33, 0xb1, 0xbc, 0x82, 0x66, 0x30,
+			0xa2, 0x98, 0x2c, 0x23, 0x53, 0x82, 0x3f, 0xc9, 0x3d, 0xbc, 0xbe, 0xc6, 0xef, 0xe6, 0x28, 0x44,
+			0xec, 0xa3, 0xeb, 0xd5, 0x1d
+		];
+		assert_eq!(&expected[..], &eme2.encrypt(b"sector 42", &sequence(37))[..]);
+		assert_eq!(sequence(37), eme2.decrypt(b"sector 42", &expected));
+
+		// Blocks 127 to 130 take in the refreshed mask, and the last 21 bytes
+		// are the partial block after 256 full ones
+		let ciphertext = eme2.encrypt(b"sector", &sequence(4096 + 5));
+		let expected = [
+			0x5b, 0x76, 0xa1, 0xa3, 0x81, 0xb6, 0x8e, 0x8d, 0x79, 0x0a, 0xb5, 0xcc, 0xeb, 0x1f, 0xa5, 0xdb,
+			0xe2, 0x39, 0xec, 0x52, 0xe4, 0x70, 0x4a, 0x87, 0xaf, 0x90, 0xad, 0x35, 0xe7, 0xd8, 0xeb, 0x1c,
+			0x5c, 0x5d, 0x3a, 0x47, 0xbb, 0x67, 0xb7, 0x8a, 0x51, 0x18, 0x12, 0xd0, 0x7c, 0x67, 0x4a, 0x43,
+			0x56, 0xf3, 0x18, 0x10, 0xb0, 0xe3, 0x31, 0x02, 0xc8, 0x66, 0x16, 0xd3, 0x40, 0xc4, 0x0b, 0xef
+		];
+		assert_eq!(&expected[..], &ciphertext[2032..2096]);
+		let expected = [
+			0x8a, 0xa1, 0x5f, 0x6c, 0x0a, 0x73, 0xd4, 0x78, 0x4f, 0x9b, 0xf7, 0x08, 0xe0, 0x19, 0xbc, 0xc6,
+			0x99, 0xdd, 0xb6, 0xa7, 0xac
+		];
+		assert_eq!(&expected[..], &ciphertext[4080..]);
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_diffusion() {
+		let eme2 = eme2();
+		let input = sequence(512);
+		let ciphertext = eme2.encrypt(b"sector", &input);
+
+		// Changing any single block changes every block of the output
+		let mut changed = input.clone();
+		changed[300] ^= 0x80;
+		let other = eme2.encrypt(b"sector", &changed);
+		for i in 0..32 {
+			assert_ne!(&ciphertext[i*16..(i+1)*16], &other[i*16..(i+1)*16]);
+		}
+
+		let other = eme2.encrypt(b"sectos", &input);
+		for i in 0..32 {
+			assert_ne!(&ciphertext[i*16..(i+1)*16], &other[i*16..(i+1)*16]);
+		}
+	}
+
+	#[test]
+	fn test_in_place() {
+		let eme2 = eme2();
+
+		let mut data = [0; 37];
+		for i in 0..37 { data[i] = (i * 7) as u8; }
+		let plaintext = data;
+
+		let expected = [
+			0x18, 0x67, 0x14, 0x7c, 0xc1, 0x1b, 0xf1, 0x89, 0xe1, 0x1a, 0x33, 0xb1, 0xbc, 0x82, 0x66, 0x30,
+			0xa2, 0x98, 0x2c, 0x23, 0x53, 0x82, 0x3f, 0xc9, 0x3d, 0xbc, 0xbe, 0xc6, 0xef, 0xe6, 0x28, 0x44,
+			0xec, 0xa3, 0xeb, 0xd5, 0x1d
+		];
+		eme2.encrypt_in_place(b"sector 42", &mut data);
+		assert_eq!(expected, data);
+		eme2.decrypt_in_place(b"sector 42", &mut data);
+		assert_eq!(plaintext, data);
+	}
+}
@@ -1,63 +1,205 @@
-use std::ops;
-use std::cmp;
+use core::ops;
+use core::cmp;
 
+use gf::Gf256;
+use tower;
+
+// An element of GF(2^8), bytes as polynomials over GF(2) modulo the AES
+// polynomial x^8 + x^4 + x^3 + x + 1.  This is gf::Gf256 with the extras the
+// cipher needs: a constant time inverse and log tables
 #[derive(Debug, Clone, Copy)]
 pub struct FF {
     val: u8
 }
 
+// 3 = x + 1 generates the multiplicative group, so every non-zero element is
+// 3^i for exactly one i in 0..255.  The tables are built from it at compile
+// time
+pub const GENERATOR: FF = FF{val: 0x03};
+const EXP: [u8; 255] = exp_table();
+const LOG: [u8; 256] = log_table();
+
 impl FF {
-    pub fn new(val: u8) -> FF {
+    pub const fn new(val: u8) -> FF {
         FF{val}
     }
 
-    pub fn value(self) -> u8 {
+    pub const fn value(self) -> u8 {
         self.val
     }
 
-    fn xtime(&self) -> FF {
-        let mut val = self.val;
-        let do_mod = if val >= 0b10000000 {true} else {false};
+    pub const fn is_zero(self) -> bool {
+        self.val == 0
+    }
+
+    // The multiplicative inverse, with 0 mapping to 0 as the S-box needs.
+    // It's computed in the tower field so it takes the same time for every
+    // value
+    pub const fn inverse(self) -> FF {
+        FF::new(tower::from_tower(tower::inverse(tower::to_tower(self.val))))
+    }
+
+    // By repeated squaring, with 0^0 = 1
+    pub fn pow(self, n: u32) -> FF {
+        let mut res = FF::new(1);
+        let mut square = self;
+        let mut n = n;
+
+        while n > 0 {
+            if n & 1 == 1 {
+                res *= square;
+            }
+            square *= square;
+            n >>= 1;
+        }
+
+        res
+    }
+
+    // GENERATOR^n
+    pub const fn exp(n: usize) -> FF {
+        FF::new(EXP[n % 255])
+    }
+
+    // The n with GENERATOR^n equal to this, or None for 0
+    pub const fn log(self) -> Option<usize> {
+        if self.is_zero() { return None; }
+
+        Some(LOG[self.val as usize] as usize)
+    }
+
+    // Multiplication by adding logs.  Faster than the bit-serial product, but
+    // which table entries are read depends on the values, so it shouldn't be
+    // used on secrets
+    pub const fn table_mul(self, rhs: FF) -> FF {
+        if self.is_zero() || rhs.is_zero() { return FF::new(0); }
+
+        FF::new(EXP[(LOG[self.val as usize] as usize + LOG[rhs.val as usize] as usize) % 255])
+    }
+
+    // The number of distinct powers of a non-zero element
+    pub fn order(self) -> usize {
+        if self.is_zero() { panic!("0 has no multiplicative order!"); }
+
+        255 / gcd(self.log().unwrap(), 255)
+    }
+
+    pub fn is_generator(self) -> bool {
+        !self.is_zero() && self.order() == 255
+    }
+
+    // Every generator of the multiplicative group, in increasing order
+    pub fn generators() -> impl Iterator<Item = FF> {
+        (1..=255).map(FF::new).filter(|x| x.is_generator())
+    }
+
+    // Multiplication by x, in constant time
+    pub const fn xtime(&self) -> FF {
+        FF::new(Gf256::new(self.val as u128).xtime().value() as u8)
+    }
+}
+
+// Bit-serial multiplication in gf, which masks rather than branches on the
+// bits of the multiplier, so it takes the same time for any operands
+const fn multiply(a: FF, b: FF) -> FF {
+    FF::new(Gf256::new(a.val as u128).product(Gf256::new(b.val as u128)).value() as u8)
+}
+
+const fn exp_table() -> [u8; 255] {
+    let mut table = [1; 255];
+    let mut i = 1;
+
+    while i < 255 {
+        table[i] = multiply(FF::new(table[i-1]), GENERATOR).val;
+        i += 1;
+    }
+
+    table
+}
 
-        val <<= 1;
+const fn log_table() -> [u8; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
 
-        FF::new(if do_mod {val ^ 0x1b} else {val})
+    while i < 255 {
+        table[EXP[i] as usize] = i as u8;
+        i += 1;
     }
+
+    table
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
 }
 
 impl ops::Add for FF {
     type Output = FF;
 
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn add(self, rhs: FF) -> FF {
         FF::new(self.val ^ rhs.val)
     }
 }
 
-// TODO figure out how to make this work
-/*impl<'a> ops::AddAssign for &'a FF {
-    fn add_assign(&mut self, rhs: &'a FF) {
-        *self = &(*self + rhs);
+// Every element is its own additive inverse, so subtracting is adding
+impl ops::Sub for FF {
+    type Output = FF;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn sub(self, rhs: FF) -> FF {
+        self + rhs
     }
-}*/
+}
+
+impl ops::Neg for FF {
+    type Output = FF;
+
+    fn neg(self) -> FF {
+        self
+    }
+}
 
 impl ops::Mul for FF {
     type Output = FF;
 
     fn mul(self, rhs: FF) -> FF {
-        if self.val == 1 { return FF::new(rhs.val); }
-        if rhs.val == 1 { return FF::new(self.val); }
-
-        let mut a = FF::new(self.val);
-        let mut res = FF::new(0);
-        
-        for i in 0..8 {
-            if rhs.val & (1 << i) > 0 {
-                res = res + a;
-            }
-            a = a.xtime();
-        }
+        multiply(self, rhs)
+    }
+}
 
-        res
+impl ops::Div for FF {
+    type Output = FF;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, rhs: FF) -> FF {
+        if rhs.is_zero() { panic!("Division by zero!"); }
+
+        self * rhs.inverse()
+    }
+}
+
+impl ops::AddAssign for FF {
+    fn add_assign(&mut self, rhs: FF) {
+        *self = *self + rhs;
+    }
+}
+
+impl ops::SubAssign for FF {
+    fn sub_assign(&mut self, rhs: FF) {
+        *self = *self - rhs;
+    }
+}
+
+impl ops::MulAssign for FF {
+    fn mul_assign(&mut self, rhs: FF) {
+        *self = *self * rhs;
+    }
+}
+
+impl ops::DivAssign for FF {
+    fn div_assign(&mut self, rhs: FF) {
+        *self = *self / rhs;
     }
 }
 
@@ -71,7 +213,7 @@ impl cmp::Eq for FF {}
 
 #[cfg(test)]
 mod tests {
-    use ff::FF;
+    use ff::*;
 
     #[test]
     fn add() {
@@ -91,4 +233,114 @@ mod tests {
     fn mult() {
         assert_eq!(FF::new(0xfe), FF::new(0x57) * FF::new(0x13));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn inverse() {
+        assert_eq!(FF::new(0x00), FF::new(0x00).inverse());
+        assert_eq!(FF::new(0xca), FF::new(0x53).inverse());
+
+        for x in 1..=255 {
+            assert_eq!(FF::new(1), FF::new(x) * FF::new(x).inverse());
+        }
+    }
+
+    #[test]
+    fn sub_div() {
+        assert_eq!(FF::new(0x57), FF::new(0xd4) - FF::new(0x83));
+        assert_eq!(FF::new(0x57), -FF::new(0x57));
+        assert_eq!(FF::new(0x57), FF::new(0xfe) / FF::new(0x13));
+        assert_eq!(FF::new(0x53).inverse(), FF::new(1) / FF::new(0x53));
+    }
+
+    #[test]
+    #[should_panic]
+    fn div_by_zero() {
+        let _ = FF::new(0x57) / FF::new(0);
+    }
+
+    #[test]
+    fn assign() {
+        let mut x = FF::new(0x57);
+        x += FF::new(0x83);
+        assert_eq!(FF::new(0xd4), x);
+        x -= FF::new(0x83);
+        assert_eq!(FF::new(0x57), x);
+        x *= FF::new(0x13);
+        assert_eq!(FF::new(0xfe), x);
+        x /= FF::new(0x13);
+        assert_eq!(FF::new(0x57), x);
+    }
+
+    #[test]
+    fn pow() {
+        assert_eq!(FF::new(1), FF::new(0).pow(0));
+        assert_eq!(FF::new(0), FF::new(0).pow(5));
+
+        for x in 0..=255 {
+            let x = FF::new(x);
+            let mut power = FF::new(1);
+            for n in 0..20 {
+                assert_eq!(power, x.pow(n));
+                power *= x;
+            }
+
+            // Fermat: x^256 = x, so x^254 is the inverse
+            assert_eq!(x, x.pow(256));
+            assert_eq!(x.inverse(), x.pow(254));
+        }
+    }
+
+    #[test]
+    fn log_exp() {
+        assert_eq!(None, FF::new(0).log());
+        assert_eq!(FF::new(1), FF::exp(0));
+        assert_eq!(FF::new(1), FF::exp(255));
+        assert_eq!(GENERATOR, FF::exp(1));
+
+        for x in 1..=255 {
+            let x = FF::new(x);
+            assert_eq!(x, FF::exp(x.log().unwrap()));
+            assert_eq!(x, GENERATOR.pow(x.log().unwrap() as u32));
+        }
+    }
+
+    #[test]
+    fn generators() {
+        // There are phi(255) = 128 of them.  2 = x isn't one, it has order 51
+        assert_eq!(128, FF::generators().count());
+        assert_eq!(Some(GENERATOR), FF::generators().next());
+        assert!(!FF::new(2).is_generator());
+        assert_eq!(51, FF::new(2).order());
+        assert_eq!(1, FF::new(1).order());
+
+        for g in FF::generators() {
+            let mut seen = [false; 256];
+            let mut x = FF::new(1);
+            for _ in 0..255 {
+                assert!(!seen[x.value() as usize]);
+                seen[x.value() as usize] = true;
+                x *= g;
+            }
+        }
+    }
+
+    // Every product both ways, checked against the table and tower field
+    // multiplications and undone by division
+    #[test]
+    fn all_products() {
+        for a in 0..=255 {
+            for b in 0..=255 {
+                let (x, y) = (FF::new(a), FF::new(b));
+                let product = x * y;
+
+                assert_eq!(product, y * x);
+                assert_eq!(product, x.table_mul(y));
+                assert_eq!(product.value(), tower::from_tower(tower::mul(tower::to_tower(a), tower::to_tower(b))));
+                if b != 0 {
+                    assert_eq!(x, product / y);
+                }
+                assert_eq!(x, x - y + y);
+            }
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use core::slice;
+
+use aes::Encryptor;
+use gf128;
+use key::Key;
+use zeroize::zeroize;
+
+// GCM from NIST SP 800-38D: CTR mode with a 32 bit counter, authenticated by
+// GHASH over the associated data and the ciphertext.  The steps are public so
+// that the keystream and the hash of the ciphertext can be worked out in
+// pieces and put back together
+pub struct Gcm {
+	encryptor: Encryptor,
+	h: [u8; 16]
+}
+
+impl Gcm {
+	pub fn using(key: Key) -> Gcm {
+		let encryptor = Encryptor::using(key);
+		let h = encryptor.encrypt(&[0; 16], false);
+
+		Gcm{ encryptor, h }
+	}
+
+	#[cfg(feature = "std")]
+	pub fn encrypt(&self, iv: &[u8], aad: &[u8], plaintext: &[u8]) -> (Vec<u8>, [u8; 16]) {
+		let mut ciphertext = plaintext.to_vec();
+		let tag = self.encrypt_in_place(iv, aad, &mut ciphertext);
+
+		(ciphertext, tag)
+	}
+
+	// Returns None if the tag doesn't match, without decrypting anything
+	#[cfg(feature = "std")]
+	pub fn decrypt(&self, iv: &[u8], aad: &[u8], ciphertext: &[u8], tag: &[u8]) -> Option<Vec<u8>> {
+		let mut plaintext = ciphertext.to_vec();
+		if !self.decrypt_in_place(iv, aad, &mut plaintext, tag) {
+			return None;
+		}
+
+		Some(plaintext)
+	}
+
+	pub fn encrypt_in_place(&self, iv: &[u8], aad: &[u8], data: &mut [u8]) -> [u8; 16] {
+		let j0 = self.initial_counter(iv);
+		self.apply_keystream_in_place(&j0, 0, data);
+
+		let mut ghash = self.ghash();
+		ghash.update(data);
+		self.tag(&j0, aad, &ghash, data.len())
+	}
+
+	// Leaves the data alone and returns false if the tag doesn't match
+	pub fn decrypt_in_place(&self, iv: &[u8], aad: &[u8], data: &mut [u8], tag: &[u8]) -> bool {
+		let j0 = self.initial_counter(iv);
+
+		let mut ghash = self.ghash();
+		ghash.update(data);
+		if !self.verify(&j0, aad, &ghash, data.len(), tag) {
+			return false;
+		}
+
+		self.apply_keystream_in_place(&j0, 0, data);
+		true
+	}
+
+	// J0.  A 96 bit IV is used directly, anything else is hashed
+	pub fn initial_counter(&self, iv: &[u8]) -> [u8; 16] {
+		if iv.is_empty() { panic!("GCM IV can't be empty!"); }
+
+		if iv.len() == 12 {
+			let mut j0 = [0; 16];
+			j0[..12].copy_from_slice(iv);
+			j0[15] = 1;
+			return j0;
+		}
+
+		let mut ghash = self.ghash();
+		ghash.update(iv);
+		ghash.update(&(8 * iv.len() as u128).to_be_bytes());
+		ghash.finish()
+	}
+
+	// Encrypts or decrypts starting the given number of blocks into the
+	// message.  Only the last 32 bits of the counter are incremented
+	#[cfg(feature = "std")]
+	pub fn apply_keystream(&self, j0: &[u8; 16], block: u32, input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.apply_keystream_in_place(j0, block, &mut output);
+		output
+	}
+
+	pub fn apply_keystream_in_place(&self, j0: &[u8; 16], block: u32, data: &mut [u8]) {
+		let start = u32::from_be_bytes([j0[12], j0[13], j0[14], j0[15]]).wrapping_add(1).wrapping_add(block);
+
+		// Eight blocks of keystream at a time, on the stack
+		let mut keystream = [0; 128];
+		for (i, chunk) in data.chunks_mut(128).enumerate() {
+			let blocks = chunk.len().div_ceil(16);
+			for j in 0..blocks {
+				keystream[j*16..j*16+12].copy_from_slice(&j0[..12]);
+				keystream[j*16+12..(j+1)*16].copy_from_slice(&start.wrapping_add((i * 8 + j) as u32).to_be_bytes());
+			}
+			self.encryptor.encrypt_blocks_in_place(&mut keystream[..blocks*16]);
+
+			for (a, b) in chunk.iter_mut().zip(keystream.iter()) {
+				*a ^= b;
+			}
+		}
+		zeroize(&mut keystream);
+	}
+
+	pub fn ghash(&self) -> Ghash {
+		Ghash::new(&self.h)
+	}
+
+	// The tag from the hash of the ciphertext, which is put after the hash of
+	// the associated data and followed by the lengths
+	pub fn tag(&self, j0: &[u8; 16], aad: &[u8], ciphertext_hash: &Ghash, ciphertext_len: usize) -> [u8; 16] {
+		let mut ghash = self.ghash();
+		ghash.update(aad);
+		ghash.append(ciphertext_hash);
+		ghash.update(&(((8 * aad.len() as u128) << 64) | (8 * ciphertext_len as u128)).to_be_bytes());
+
+		let s = ghash.finish();
+		let mask = self.encryptor.encrypt(j0, false);
+
+		let mut tag = [0; 16];
+		for i in 0..16 {
+			tag[i] = s[i] ^ mask[i];
+		}
+		tag
+	}
+
+	pub fn verify(&self, j0: &[u8; 16], aad: &[u8], ciphertext_hash: &Ghash, ciphertext_len: usize, tag: &[u8]) -> bool {
+		if tag.len() != 16 { return false; }
+
+		let expected = self.tag(j0, aad, ciphertext_hash, ciphertext_len);
+
+		// Don't short circuit so that the comparison takes the same time
+		// regardless of where the first difference is
+		let mut diff = 0;
+		for i in 0..16 {
+			diff |= expected[i] ^ tag[i];
+		}
+
+		diff == 0
+	}
+}
+
+impl Drop for Gcm {
+	fn drop(&mut self) {
+		zeroize(&mut self.h);
+	}
+}
+
+// GHASH, a polynomial evaluated at H in GF(2^128).  GCM numbers the bits of
+// a block from the most significant bit of the first byte, so a block is
+// loaded big endian and bit reversed to get bit i as the coefficient of x^i
+#[derive(Clone)]
+pub struct Ghash {
+	h: u128,
+	y: u128,
+	blocks: u64
+}
+
+impl Ghash {
+	pub fn new(h: &[u8]) -> Ghash {
+		if h.len() != 16 { panic!("GHASH key must be 16 bytes!"); }
+
+		Ghash{ h: to_field(h), y: 0, blocks: 0 }
+	}
+
+	// A trailing partial block is padded with zeroes, so the input should be
+	// split at 16 byte boundaries if more is going to follow
+	pub fn update(&mut self, input: &[u8]) {
+		for chunk in input.chunks(16) {
+			let mut bytes = [0; 16];
+			bytes[..chunk.len()].copy_from_slice(chunk);
+
+			self.y = gf128::mul(self.y ^ to_field(&bytes), self.h);
+			self.blocks += 1;
+		}
+	}
+
+	// Carries on as though the blocks other hashed had been passed to update.
+	// Each block is multiplied by one more power of H for every block that
+	// follows it, so this hash needs H^n for the n blocks of other
+	pub fn append(&mut self, other: &Ghash) {
+		self.y = gf128::mul(self.y, gf128::pow(self.h, other.blocks)) ^ other.y;
+		self.blocks += other.blocks;
+	}
+
+	pub fn finish(&self) -> [u8; 16] {
+		self.y.reverse_bits().to_be_bytes()
+	}
+}
+
+impl Drop for Ghash {
+	fn drop(&mut self) {
+		zeroize(slice::from_mut(&mut self.h));
+		zeroize(slice::from_mut(&mut self.y));
+	}
+}
+
+fn to_field(bytes: &[u8]) -> u128 {
+	let mut block = [0; 16];
+	block.copy_from_slice(bytes);
+	u128::from_be_bytes(block).reverse_bits()
+}
+
+#[cfg(test)]
+mod tests {
+	use gcm::*;
+
+	fn gcm() -> Gcm {
+		Gcm::using(Key::new(&[0xfeffe992, 0x8665731c, 0x6d6a8f94, 0x67308308]))
+	}
+
+	const PLAINTEXT: [u8; 60] = [
+		0xd9, 0x31, 0x32, 0x25, 0xf8, 0x84, 0x06, 0xe5, 0xa5, 0x59, 0x09, 0xc5, 0xaf, 0xf5, 0x26, 0x9a,
+		0x86, 0xa7, 0xa9, 0x53, 0x15, 0x34, 0xf7, 0xda, 0x2e, 0x4c, 0x30, 0x3d, 0x8a, 0x31, 0x8a, 0x72,
+		0x1c, 0x3c, 0x0c, 0x95, 0x95, 0x68, 0x09, 0x53, 0x2f, 0xcf, 0x0e, 0x24, 0x49, 0xa6, 0xb5, 0x25,
+		0xb1, 0x6a, 0xed, 0xf5, 0xaa, 0x0d, 0xe6, 0x57, 0xba, 0x63, 0x7b, 0x39
+	];
+
+	const AAD: [u8; 20] = [
+		0xfe, 0xed, 0xfa, 0xce, 0xde, 0xad, 0xbe, 0xef, 0xfe, 0xed, 0xfa, 0xce, 0xde, 0xad, 0xbe, 0xef,
+		0xab, 0xad, 0xda, 0xd2
+	];
+
+	// Test Case 4 from the GCM specification
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_encrypt() {
+		let iv = [0xca, 0xfe, 0xba, 0xbe, 0xfa, 0xce, 0xdb, 0xad, 0xde, 0xca, 0xf8, 0x88];
+		let (ciphertext, tag) = gcm().encrypt(&iv, &AAD, &PLAINTEXT);
+
+		assert_eq!(&[
+			0x42, 0x83, 0x1e, 0xc2, 0x21, 0x77, 0x74, 0x24, 0x4b, 0x72, 0x21, 0xb7, 0x84, 0xd0, 0xd4, 0x9c,
+			0xe3, 0xaa, 0x21, 0x2f, 0x2c, 0x02, 0xa4, 0xe0, 0x35, 0xc1, 0x7e, 0x23, 0x29, 0xac, 0xa1, 0x2e,
+			0x21, 0xd5, 0x14, 0xb2, 0x54, 0x66, 0x93, 0x1c, 0x7d, 0x8f, 0x6a, 0x5a, 0xac, 0x84, 0xaa, 0x05,
+			0x1b, 0xa3, 0x0b, 0x39, 0x6a, 0x0a, 0xac, 0x97, 0x3d, 0x58, 0xe0, 0x91
+		][..], &ciphertext[..]);
+		assert_eq!([
+			0x5b, 0xc9, 0x4f, 0xbc, 0x32, 0x21, 0xa5, 0xdb, 0x94, 0xfa, 0xe9, 0x5a, 0xe7, 0x12, 0x1a, 0x47
+		], tag);
+
+		assert_eq!(Some(PLAINTEXT.to_vec()), gcm().decrypt(&iv, &AAD, &ciphertext, &tag));
+
+		let mut forged = tag;
+		forged[15] ^= 1;
+		assert_eq!(None, gcm().decrypt(&iv, &AAD, &ciphertext, &forged));
+	}
+
+	// Test Case 6, with a 60 byte IV that has to be hashed
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_long_iv() {
+		let iv = [
+			0x93, 0x13, 0x22, 0x5d, 0xf8, 0x84, 0x06, 0xe5, 0x55, 0x90, 0x9c, 0x5a, 0xff, 0x52, 0x69, 0xaa,
+			0x6a, 0x7a, 0x95, 0x38, 0x53, 0x4f, 0x7d, 0xa1, 0xe4, 0xc3, 0x03, 0xd2, 0xa3, 0x18, 0xa7, 0x28,
+			0xc3, 0xc0, 0xc9, 0x51, 0x56, 0x80, 0x95, 0x39, 0xfc, 0xf0, 0xe2, 0x42, 0x9a, 0x6b, 0x52, 0x54,
+			0x16, 0xae, 0xdb, 0xf5, 0xa0, 0xde, 0x6a, 0x57, 0xa6, 0x37, 0xb3, 0x9b
+		];
+		let (ciphertext, tag) = gcm().encrypt(&iv, &AAD, &PLAINTEXT);
+
+		assert_eq!(&[0x8c, 0xe2, 0x49, 0x98, 0x62, 0x56, 0x15, 0xb6][..], &ciphertext[..8]);
+		assert_eq!([
+			0x61, 0x9c, 0xc5, 0xae, 0xff, 0xfe, 0x0b, 0xfa, 0x46, 0x2a, 0xf4, 0x3c, 0x16, 0x99, 0xd0, 0x50
+		], tag);
+	}
+
+	// Test Cases 1 and 2, with an all zero key
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_zero_key() {
+		let gcm = Gcm::using(Key::new(&[0; 4]));
+
+		assert_eq!([
+			0x58, 0xe2, 0xfc, 0xce, 0xfa, 0x7e, 0x30, 0x61, 0x36, 0x7f, 0x1d, 0x57, 0xa4, 0xe7, 0x45, 0x5a
+		], gcm.encrypt(&[0; 12], &[], &[]).1);
+
+		let (ciphertext, tag) = gcm.encrypt(&[0; 12], &[], &[0; 16]);
+		assert_eq!(&[
+			0x03, 0x88, 0xda, 0xce, 0x60, 0xb6, 0xa3, 0x92, 0xf3, 0x28, 0xc2, 0xb9, 0x71, 0xb2, 0xfe, 0x78
+		][..], &ciphertext[..]);
+		assert_eq!([
+			0xab, 0x6e, 0x47, 0xd4, 0x2c, 0xec, 0x13, 0xbd, 0xf5, 0x3a, 0x67, 0xb2, 0x12, 0x57, 0xbd, 0xdf
+		], tag);
+	}
+
+	#[test]
+	fn test_in_place() {
+		let iv = [0xca, 0xfe, 0xba, 0xbe, 0xfa, 0xce, 0xdb, 0xad, 0xde, 0xca, 0xf8, 0x88];
+		let mut data = PLAINTEXT;
+		let tag = gcm().encrypt_in_place(&iv, &AAD, &mut data);
+
+		assert_eq!([0x42, 0x83, 0x1e, 0xc2], data[..4]);
+		assert_eq!([0x3d, 0x58, 0xe0, 0x91], data[56..]);
+		assert_eq!([
+			0x5b, 0xc9, 0x4f, 0xbc, 0x32, 0x21, 0xa5, 0xdb, 0x94, 0xfa, 0xe9, 0x5a, 0xe7, 0x12, 0x1a, 0x47
+		], tag);
+
+		// A bad tag leaves the ciphertext as it was
+		let ciphertext = data;
+		assert!(!gcm().decrypt_in_place(&iv, b"", &mut data, &tag));
+		assert_eq!(ciphertext, data);
+
+		assert!(gcm().decrypt_in_place(&iv, &AAD, &mut data, &tag));
+		assert_eq!(PLAINTEXT, data);
+	}
+
+	#[test]
+	fn test_ghash_append() {
+		let h = [0x66, 0xe9, 0x4b, 0xd4, 0xef, 0x8a, 0x2c, 0x3b, 0x88, 0x4c, 0xfa, 0x59, 0xca, 0x34, 0x2b, 0x2e];
+		let data: Vec<u8> = (0..100).map(|i| i as u8).collect();
+
+		let mut whole = Ghash::new(&h);
+		whole.update(&data);
+
+		let mut first = Ghash::new(&h);
+		first.update(&data[..48]);
+		let mut second = Ghash::new(&h);
+		second.update(&data[48..]);
+		first.append(&second);
+
+		assert_eq!(whole.finish(), first.finish());
+	}
+}
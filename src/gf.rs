@@ -0,0 +1,403 @@
+use core::cmp;
+use core::fmt;
+use core::ops;
+
+// An element of GF(2^N), a polynomial over GF(2) of degree below N held with
+// bit i as the coefficient of x^i, multiplied modulo x^N + POLY.  POLY is the
+// reduction polynomial without its leading term so that N = 128 fits in a
+// u128; it has to be irreducible for this to be a field, which new() checks.
+//
+// Some standards number the bits the other way, with the coefficient of x^0
+// in the top bit (GCM's GHASH does), which is what the reflected conversions
+// are for
+#[derive(Clone, Copy)]
+pub struct Gf<const N: u32, const POLY: u128> {
+	val: u128
+}
+
+// GF(2^4) as used by the tower field
+pub type Gf16 = Gf<4, 0x03>;
+
+// The AES field, the same one as ff::FF
+pub type Gf256 = Gf<8, 0x1b>;
+
+// The field of the IEEE 1619 tweaks and GHASH
+pub type Gf128 = Gf<128, 0x87>;
+
+impl<const N: u32, const POLY: u128> Gf<N, POLY> {
+	// Worked out once for each field, when it's compiled
+	const IRREDUCIBLE: bool = is_irreducible(N, POLY);
+
+	pub const fn new(val: u128) -> Gf<N, POLY> {
+		if !Self::IRREDUCIBLE { panic!("Reduction polynomial isn't irreducible!"); }
+		if val & !mask(N) != 0 { panic!("Value doesn't fit in the field!"); }
+
+		Gf{ val }
+	}
+
+	pub const fn zero() -> Gf<N, POLY> {
+		Gf::new(0)
+	}
+
+	pub const fn one() -> Gf<N, POLY> {
+		Gf::new(1)
+	}
+
+	// With the coefficient of x^0 in bit N - 1
+	pub const fn from_reflected(val: u128) -> Gf<N, POLY> {
+		if val & !mask(N) != 0 { panic!("Value doesn't fit in the field!"); }
+
+		Gf::new(val.reverse_bits() >> (128 - N))
+	}
+
+	pub const fn value(self) -> u128 {
+		self.val
+	}
+
+	pub const fn to_reflected(self) -> u128 {
+		self.val.reverse_bits() >> (128 - N)
+	}
+
+	pub const fn is_zero(self) -> bool {
+		self.val == 0
+	}
+
+	// Multiplication by x
+	pub const fn xtime(self) -> Gf<N, POLY> {
+		Gf{ val: xtime(self.val, N, POLY) }
+	}
+
+	// The same as *, but usable in constants
+	pub const fn product(self, rhs: Gf<N, POLY>) -> Gf<N, POLY> {
+		Gf{ val: mul(self.val, rhs.val, N, POLY) }
+	}
+
+	// By repeated squaring, with 0^0 = 1
+	pub const fn pow(self, n: u128) -> Gf<N, POLY> {
+		Gf{ val: pow(self.val, n, N, POLY) }
+	}
+
+	// x^(2^N - 2), which is 1/x for everything but 0, and 0 for 0
+	pub const fn inverse(self) -> Gf<N, POLY> {
+		self.pow(mask(N) - 1)
+	}
+}
+
+impl<const N: u32, const POLY: u128> ops::Add for Gf<N, POLY> {
+	type Output = Gf<N, POLY>;
+
+	#[allow(clippy::suspicious_arithmetic_impl)]
+	fn add(self, rhs: Gf<N, POLY>) -> Gf<N, POLY> {
+		Gf{ val: self.val ^ rhs.val }
+	}
+}
+
+impl<const N: u32, const POLY: u128> ops::Sub for Gf<N, POLY> {
+	type Output = Gf<N, POLY>;
+
+	#[allow(clippy::suspicious_arithmetic_impl)]
+	fn sub(self, rhs: Gf<N, POLY>) -> Gf<N, POLY> {
+		self + rhs
+	}
+}
+
+impl<const N: u32, const POLY: u128> ops::Neg for Gf<N, POLY> {
+	type Output = Gf<N, POLY>;
+
+	fn neg(self) -> Gf<N, POLY> {
+		self
+	}
+}
+
+impl<const N: u32, const POLY: u128> ops::Mul for Gf<N, POLY> {
+	type Output = Gf<N, POLY>;
+
+	fn mul(self, rhs: Gf<N, POLY>) -> Gf<N, POLY> {
+		self.product(rhs)
+	}
+}
+
+impl<const N: u32, const POLY: u128> ops::Div for Gf<N, POLY> {
+	type Output = Gf<N, POLY>;
+
+	#[allow(clippy::suspicious_arithmetic_impl)]
+	fn div(self, rhs: Gf<N, POLY>) -> Gf<N, POLY> {
+		if rhs.is_zero() { panic!("Division by zero!"); }
+
+		self * rhs.inverse()
+	}
+}
+
+impl<const N: u32, const POLY: u128> ops::AddAssign for Gf<N, POLY> {
+	fn add_assign(&mut self, rhs: Gf<N, POLY>) {
+		*self = *self + rhs;
+	}
+}
+
+impl<const N: u32, const POLY: u128> ops::SubAssign for Gf<N, POLY> {
+	fn sub_assign(&mut self, rhs: Gf<N, POLY>) {
+		*self = *self - rhs;
+	}
+}
+
+impl<const N: u32, const POLY: u128> ops::MulAssign for Gf<N, POLY> {
+	fn mul_assign(&mut self, rhs: Gf<N, POLY>) {
+		*self = *self * rhs;
+	}
+}
+
+impl<const N: u32, const POLY: u128> ops::DivAssign for Gf<N, POLY> {
+	fn div_assign(&mut self, rhs: Gf<N, POLY>) {
+		*self = *self / rhs;
+	}
+}
+
+impl<const N: u32, const POLY: u128> cmp::PartialEq for Gf<N, POLY> {
+	fn eq(&self, other: &Gf<N, POLY>) -> bool {
+		self.val == other.val
+	}
+}
+
+impl<const N: u32, const POLY: u128> cmp::Eq for Gf<N, POLY> {}
+
+impl<const N: u32, const POLY: u128> fmt::Debug for Gf<N, POLY> {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(formatter, "Gf<{}>({:#x})", N, self.val)
+	}
+}
+
+// The low n bits
+const fn mask(n: u32) -> u128 {
+	if n == 0 || n > 128 { panic!("Field degree must be from 1 to 128!"); }
+
+	u128::MAX >> (128 - n)
+}
+
+// The arithmetic is in the ring of polynomials modulo x^n + poly, which is
+// only a field when that's irreducible.  None of it branches on the values
+pub const fn xtime(a: u128, n: u32, poly: u128) -> u128 {
+	let top = 0u128.wrapping_sub((a >> (n - 1)) & 1);
+	((a << 1) & mask(n)) ^ (top & poly)
+}
+
+pub const fn mul(a: u128, b: u128, n: u32, poly: u128) -> u128 {
+	let mut a = a;
+	let mut res = 0;
+	let mut i = 0;
+
+	while i < n {
+		res ^= a & 0u128.wrapping_sub((b >> i) & 1);
+		a = xtime(a, n, poly);
+		i += 1;
+	}
+
+	res
+}
+
+const fn pow(a: u128, e: u128, n: u32, poly: u128) -> u128 {
+	let mut res = 1;
+	let mut square = a;
+	let mut e = e;
+
+	while e > 0 {
+		if e & 1 == 1 {
+			res = mul(res, square, n, poly);
+		}
+		square = mul(square, square, n, poly);
+		e >>= 1;
+	}
+
+	res
+}
+
+// Rabin's test: x^n + poly is irreducible if and only if x^(2^n) = x modulo
+// it, and x^(2^(n/p)) - x shares no factor with it for each prime p dividing n
+pub const fn is_irreducible(n: u32, poly: u128) -> bool {
+	// x + 1 and x are the only polynomials of degree 1, and both irreducible
+	if n == 1 { return true; }
+
+	let x = 2;
+	let mut p = 2;
+	while p <= n {
+		if n.is_multiple_of(p) && is_prime(p) {
+			let h = square_times(x, n / p, n, poly);
+			if gcd_with_modulus(h ^ x, n, poly) != 1 {
+				return false;
+			}
+		}
+		p += 1;
+	}
+
+	square_times(x, n, n, poly) == x
+}
+
+// a^(2^k)
+const fn square_times(a: u128, k: u32, n: u32, poly: u128) -> u128 {
+	let mut a = a;
+	let mut i = 0;
+
+	while i < k {
+		a = mul(a, a, n, poly);
+		i += 1;
+	}
+
+	a
+}
+
+const fn is_prime(p: u32) -> bool {
+	let mut d = 2;
+	while d * d <= p {
+		if p.is_multiple_of(d) { return false; }
+		d += 1;
+	}
+
+	true
+}
+
+const fn degree(a: u128) -> u32 {
+	127 - a.leading_zeros()
+}
+
+// a modulo b as polynomials, b non-zero
+const fn poly_mod(a: u128, b: u128) -> u128 {
+	let mut a = a;
+	while a != 0 && degree(a) >= degree(b) {
+		a ^= b << (degree(a) - degree(b));
+	}
+
+	a
+}
+
+// The gcd of x^n + poly and a, which has a lower degree.  The modulus doesn't
+// fit in a u128 when n is 128, so the first step of Euclid's algorithm works
+// out x^n mod a one power at a time
+const fn gcd_with_modulus(a: u128, n: u32, poly: u128) -> u128 {
+	if a == 0 { return 0; }
+	if a == 1 { return 1; }
+
+	let mut r = 1;
+	let mut i = 0;
+	while i < n {
+		r <<= 1;
+		if r != 0 && degree(r) == degree(a) {
+			r ^= a;
+		}
+		i += 1;
+	}
+	let r = r ^ poly_mod(poly, a);
+
+	let (mut a, mut b) = (a, r);
+	while b != 0 {
+		let t = poly_mod(a, b);
+		a = b;
+		b = t;
+	}
+
+	a
+}
+
+#[cfg(test)]
+mod tests {
+	use gf::*;
+	use ff::FF;
+	use gcm::Ghash;
+	use gf128;
+	use tower;
+
+	#[test]
+	fn test_irreducible() {
+		// x^4 + x + 1, the AES polynomial and the GF(2^128) one
+		assert!(is_irreducible(4, 0x03));
+		assert!(is_irreducible(8, 0x1b));
+		assert!(is_irreducible(128, 0x87));
+
+		// x^4 + x^3 + x^2 + x + 1 is irreducible even though x isn't a
+		// generator, but x^4 + 1 = (x + 1)^4 and (x^4 + x + 1)(x^4 + x^3 + 1)
+		// = x^8 + x^7 + x^5 + x^4 + x^3 + x + 1 aren't
+		assert!(is_irreducible(4, 0x0f));
+		assert!(!is_irreducible(4, 0x01));
+		assert!(!is_irreducible(8, 0xbb));
+		assert!(!is_irreducible(8, 0x00));
+
+		// There are 30 irreducible polynomials of degree 8, and 6 of degree
+		// 5.  A polynomial without a constant term is divisible by x
+		assert_eq!(30, (0..256).filter(|&poly| is_irreducible(8, poly)).count());
+		assert_eq!(6, (0..32).filter(|&poly| is_irreducible(5, poly)).count());
+		assert!(!is_irreducible(8, 0x1a));
+	}
+
+	#[test]
+	#[should_panic]
+	fn test_reducible_field() {
+		Gf::<8, 0x00>::new(1);
+	}
+
+	#[test]
+	#[should_panic]
+	fn test_too_big() {
+		Gf16::new(0x10);
+	}
+
+	// The AES instance agrees with FF for every pair
+	#[test]
+	fn test_gf256() {
+		for a in 0..=255 {
+			let x = Gf256::new(a as u128);
+			assert_eq!(FF::new(a).inverse().value() as u128, x.inverse().value());
+
+			for b in 0..=255 {
+				let y = Gf256::new(b as u128);
+				assert_eq!((FF::new(a) * FF::new(b)).value() as u128, (x * y).value());
+				if b != 0 {
+					assert_eq!(x, x * y / y);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn test_gf16() {
+		for a in 0..16 {
+			for b in 0..16 {
+				assert_eq!(tower::gf16_mul(a, b) as u128, (Gf16::new(a as u128) * Gf16::new(b as u128)).value());
+			}
+		}
+
+		assert_eq!(Gf16::new(0x9), Gf16::new(0x2).inverse());
+	}
+
+	#[test]
+	fn test_gf128() {
+		let a = 0x0123456789abcdeffedcba9876543210;
+		let b = 0x66e94bd4ef8a2c3b884cfa59ca342b2e;
+
+		assert_eq!(gf128::mul(a, b), (Gf128::new(a) * Gf128::new(b)).value());
+		assert_eq!(gf128::mul_alpha(a), Gf128::new(a).xtime().value());
+		assert_eq!(Gf128::one(), Gf128::new(a) * Gf128::new(a).inverse());
+
+		let mut x = Gf128::new(a);
+		x *= Gf128::new(b);
+		x /= Gf128::new(b);
+		x += Gf128::one();
+		x -= Gf128::one();
+		assert_eq!(Gf128::new(a), x);
+	}
+
+	// GHASH of one block is the block times H, in the reflected order
+	#[test]
+	fn test_reflected() {
+		let h = [0x66, 0xe9, 0x4b, 0xd4, 0xef, 0x8a, 0x2c, 0x3b, 0x88, 0x4c, 0xfa, 0x59, 0xca, 0x34, 0x2b, 0x2e];
+		let block = [0x03, 0x88, 0xda, 0xce, 0x60, 0xb6, 0xa3, 0x92, 0xf3, 0x28, 0xc2, 0xb9, 0x71, 0xb2, 0xfe, 0x78];
+
+		let mut ghash = Ghash::new(&h);
+		ghash.update(&block);
+
+		let product = Gf128::from_reflected(u128::from_be_bytes(block)) * Gf128::from_reflected(u128::from_be_bytes(h));
+		assert_eq!(ghash.finish(), product.to_reflected().to_be_bytes());
+
+		// x is the top bit reflected, and for a smaller field the top of N
+		assert_eq!(Gf128::new(2), Gf128::from_reflected(1 << 126));
+		assert_eq!(Gf256::new(2), Gf256::from_reflected(0x40));
+		assert_eq!(0x40, Gf256::new(2).to_reflected());
+	}
+}
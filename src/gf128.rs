@@ -0,0 +1,72 @@
+// Arithmetic in GF(2^128) modulo x^128 + x^7 + x^2 + x + 1, the field used
+// for the tweaks of the IEEE 1619 family of disk encryption modes.  Elements
+// are held in a u128 with bit i as the coefficient of x^i; whether that is
+// loaded from bytes big or little endian is up to the mode
+const REDUCE: u128 = 0x87;
+
+// Multiplication by x.  The operands are often keys or derived from them, so
+// this and mul mask with the bits rather than branching on them
+pub fn mul_alpha(a: u128) -> u128 {
+	(a << 1) ^ (REDUCE & 0u128.wrapping_sub(a >> 127))
+}
+
+pub fn mul(a: u128, b: u128) -> u128 {
+	let mut res = 0;
+
+	for i in (0..128).rev() {
+		res = mul_alpha(res);
+		res ^= a & 0u128.wrapping_sub((b >> i) & 1);
+	}
+
+	res
+}
+
+pub fn pow(a: u128, n: u64) -> u128 {
+	let mut res = 1;
+	let mut square = a;
+
+	for i in 0..64 {
+		if n & (1 << i) != 0 {
+			res = mul(res, square);
+		}
+		square = mul(square, square);
+	}
+
+	res
+}
+
+#[cfg(test)]
+mod tests {
+	use gf128::*;
+
+	#[test]
+	fn test_mul_alpha() {
+		assert_eq!(2, mul_alpha(1));
+		assert_eq!(0x87, mul_alpha(1 << 127));
+		assert_eq!(0x85 | 1 << 64, mul_alpha(1 << 127 | 1 << 63 | 1));
+	}
+
+	#[test]
+	fn test_mul() {
+		let a = 0x0123456789abcdeffedcba9876543210;
+		let b = 0x66e94bd4ef8a2c3b884cfa59ca342b2e;
+
+		assert_eq!(a, mul(a, 1));
+		assert_eq!(mul_alpha(a), mul(a, 2));
+		assert_eq!(mul(a, b), mul(b, a));
+		assert_eq!(mul(a, b ^ 2), mul(a, b) ^ mul_alpha(a));
+
+		// x^127 * x = x^128 = x^7 + x^2 + x + 1
+		assert_eq!(0x87, mul(1 << 127, 2));
+	}
+
+	#[test]
+	fn test_pow() {
+		let a = 0x0123456789abcdeffedcba9876543210;
+
+		assert_eq!(1, pow(a, 0));
+		assert_eq!(mul(a, mul(a, a)), pow(a, 3));
+		assert_eq!(1 << 100, pow(2, 100));
+		assert_eq!(mul_alpha(1 << 127), pow(2, 128));
+	}
+}
@@ -0,0 +1,222 @@
+use aes::{Encryptor, Decryptor};
+use key::Key;
+use polyval::Polyval;
+use zeroize::zeroize;
+
+// HCTR2 from "Length-preserving encryption with HCTR2" (Crowley, Huckleberry
+// and Biggers), as used by Linux fscrypt for filenames.  The first block goes
+// through the block cipher sandwiched between two POLYVAL hashes of the rest of
+// the message, and the rest of the message is encrypted with XCTR using a
+// nonce derived from the first block, so every output bit depends on every
+// input bit and on the tweak
+pub struct Hctr2 {
+	encryptor: Encryptor,
+	decryptor: Decryptor,
+	h: [u8; 16],
+	l: [u8; 16]
+}
+
+impl Hctr2 {
+	pub fn using(key: Key) -> Hctr2 {
+		let encryptor = Encryptor::using(key.clone());
+		let h = encryptor.encrypt(&0u128.to_le_bytes(), false);
+		let l = encryptor.encrypt(&1u128.to_le_bytes(), false);
+
+		Hctr2{ encryptor, decryptor: Decryptor::using(key), h, l }
+	}
+
+	#[cfg(feature = "std")]
+	pub fn encrypt(&self, tweak: &[u8], input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.encrypt_in_place(tweak, &mut output);
+		output
+	}
+
+	#[cfg(feature = "std")]
+	pub fn decrypt(&self, tweak: &[u8], input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.decrypt_in_place(tweak, &mut output);
+		output
+	}
+
+	pub fn encrypt_in_place(&self, tweak: &[u8], data: &mut [u8]) {
+		if data.len() < 16 { panic!("Can only encrypt at least one full block!"); }
+
+		let (m, n) = data.split_at_mut(16);
+
+		let mm = xor(m, &self.hash(tweak, n));
+		let uu = self.encryptor.encrypt(&mm, false);
+		let s = xor(&xor(&mm, &uu), &self.l);
+
+		self.xctr(&s, n);
+		m.copy_from_slice(&xor(&uu, &self.hash(tweak, n)));
+	}
+
+	pub fn decrypt_in_place(&self, tweak: &[u8], data: &mut [u8]) {
+		if data.len() < 16 { panic!("Can only decrypt at least one full block!"); }
+
+		let (u, v) = data.split_at_mut(16);
+
+		let uu = xor(u, &self.hash(tweak, v));
+		let mm = self.decryptor.decrypt(&uu, false);
+		let s = xor(&xor(&mm, &uu), &self.l);
+
+		self.xctr(&s, v);
+		u.copy_from_slice(&xor(&mm, &self.hash(tweak, v)));
+	}
+
+	// POLYVAL over the tweak length, the zero padded tweak and the message.
+	// A partial message is padded with a single 1 byte first, and the length
+	// block records which kind of padding was used
+	fn hash(&self, tweak: &[u8], message: &[u8]) -> [u8; 16] {
+		let full = message.len() / 16;
+		let tail = &message[full*16..];
+		let length = 2 * 8 * tweak.len() as u128 + if tail.is_empty() { 2 } else { 3 };
+
+		let mut polyval = Polyval::new(&self.h);
+		polyval.update(&length.to_le_bytes());
+		polyval.update(tweak);
+		polyval.update(&message[..full*16]);
+
+		if !tail.is_empty() {
+			let mut padded = [0; 16];
+			padded[..tail.len()].copy_from_slice(tail);
+			padded[tail.len()] = 1;
+			polyval.update(&padded);
+		}
+
+		polyval.finish()
+	}
+
+	// XCTR is CTR mode with the little endian counter (starting at 1) xored
+	// into the nonce rather than added to it
+	fn xctr(&self, nonce: &[u8; 16], data: &mut [u8]) {
+		let nonce = u128::from_le_bytes(*nonce);
+
+		for (i, chunk) in data.chunks_mut(16).enumerate() {
+			let counter = (nonce ^ (i as u128 + 1)).to_le_bytes();
+			let keystream = self.encryptor.encrypt(&counter, false);
+
+			for j in 0..chunk.len() {
+				chunk[j] ^= keystream[j];
+			}
+		}
+	}
+}
+
+impl Drop for Hctr2 {
+	fn drop(&mut self) {
+		zeroize(&mut self.h);
+		zeroize(&mut self.l);
+	}
+}
+
+fn xor(a: &[u8], b: &[u8; 16]) -> [u8; 16] {
+	let mut ret = [0; 16];
+
+	for i in 0..16 {
+		ret[i] = a[i] ^ b[i];
+	}
+
+	ret
+}
+
+#[cfg(test)]
+mod tests {
+	use hctr2::*;
+
+	fn key() -> Key {
+		Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f, 0x10111213, 0x14151617, 0x18191a1b, 0x1c1d1e1f])
+	}
+
+	#[cfg(feature = "std")]
+	fn sequence(start: u8, len: usize) -> Vec<u8> {
+		(0..len).map(|i| start + i as u8).collect()
+	}
+
+	// Expected outputs were cross-checked against a separate implementation of
+	// the algorithm from the HCTR2 paper, with a 32 byte tweak as fscrypt uses
+	const VECTORS: [(usize, &[u8]); 3] = [
+		(16, &[
+			0x7d, 0xfd, 0x88, 0xac, 0xcf, 0x6f, 0x40, 0x4e, 0xe8, 0x81, 0x09, 0x1a, 0x21, 0xd5, 0x4f, 0xd9
+		]),
+		(17, &[
+			0x2e, 0x17, 0x8c, 0x3f, 0x9a, 0xf9, 0xdb, 0x18, 0x7c, 0xcd, 0xb6, 0x8f, 0xa9, 0xec, 0x84, 0x63,
+			0xaf
+		]),
+		(48, &[
+			0x94, 0x52, 0xc9, 0x51, 0x66, 0x4c, 0xe2, 0x8e, 0x0f, 0x98, 0xb3, 0x11, 0x85, 0x0d, 0xe8, 0x2f,
+			0x82, 0x67, 0x64, 0x74, 0x59, 0x5a, 0xc3, 0x93, 0xf7, 0xad, 0x4a, 0x44, 0xd3, 0x51, 0x70, 0xda,
+			0xae, 0x03, 0x35, 0x16, 0x0b, 0x80, 0xb1, 0xc1, 0x92, 0x8f, 0xf6, 0x12, 0x2d, 0x42, 0xa9, 0xd1
+		]),
+	];
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_encrypt() {
+		let hctr2 = Hctr2::using(key());
+		let tweak = sequence(0x20, 32);
+
+		for &(len, expected) in VECTORS.iter() {
+			assert_eq!(expected, &hctr2.encrypt(&tweak, &sequence(0x40, len))[..]);
+		}
+
+		let hctr2 = Hctr2::using(Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f]));
+		assert_eq!(&[
+			0xcd, 0x7c, 0x93, 0x5e, 0x61, 0x82, 0xce, 0x21, 0x5a, 0xd4, 0x4e, 0x23, 0x86, 0xe6, 0xd6, 0x34,
+			0xa2
+		][..], &hctr2.encrypt(&[], &sequence(0x40, 17))[..]);
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_decrypt() {
+		let hctr2 = Hctr2::using(key());
+		let tweak = sequence(0x20, 32);
+
+		for &(len, input) in VECTORS.iter() {
+			assert_eq!(sequence(0x40, len), hctr2.decrypt(&tweak, input));
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_diffusion() {
+		let hctr2 = Hctr2::using(key());
+		let tweak = sequence(0x20, 32);
+		let input = sequence(0x40, 100);
+		let ciphertext = hctr2.encrypt(&tweak, &input);
+
+		// Changing the last byte of the input or the tweak changes the first
+		// block of the output
+		let mut changed = input.clone();
+		changed[99] ^= 1;
+		assert_ne!(&ciphertext[..16], &hctr2.encrypt(&tweak, &changed)[..16]);
+		assert_ne!(&ciphertext[..16], &hctr2.encrypt(&tweak[..31], &input)[..16]);
+
+		for len in 16..100 {
+			let ciphertext = hctr2.encrypt(&tweak, &input[..len]);
+			assert_eq!(len, ciphertext.len());
+			assert_eq!(&input[..len], &hctr2.decrypt(&tweak, &ciphertext)[..]);
+		}
+	}
+
+	#[test]
+	fn test_in_place() {
+		let hctr2 = Hctr2::using(key());
+
+		let mut tweak = [0; 32];
+		for i in 0..32 { tweak[i] = 0x20 + i as u8; }
+
+		for &(len, expected) in VECTORS.iter() {
+			let mut data = [0; 48];
+			for i in 0..len { data[i] = 0x40 + i as u8; }
+			let plaintext = data;
+
+			hctr2.encrypt_in_place(&tweak, &mut data[..len]);
+			assert_eq!(expected, &data[..len]);
+			hctr2.decrypt_in_place(&tweak, &mut data[..len]);
+			assert_eq!(plaintext, data);
+		}
+	}
+}
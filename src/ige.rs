@@ -0,0 +1,333 @@
+use aes::{Encryptor, Decryptor};
+use key::Key;
+
+// Infinite Garble Extension mode.  Each ciphertext block depends on the
+// previous ciphertext block and the previous plaintext block:
+//   c[i] = E(p[i] ^ c[i-1]) ^ p[i-1]
+// The 32 byte IV supplies c[0] followed by p[0], matching OpenSSL's layout
+pub struct Ige {
+	encryptor: Encryptor,
+	decryptor: Decryptor
+}
+
+// Bi-directional IGE runs IGE forwards under the first key and then backwards
+// over the result under the second key, so that an error in any block garbles
+// the whole message.  The 64 byte IV holds the IVs of both passes.
+// OpenSSL's AES_bi_ige_encrypt never reads its second key and runs both
+// passes under the first, so to match it pass the first key twice
+pub struct BiIge {
+	forward: Ige,
+	backward: Ige
+}
+
+impl Ige {
+	pub fn using(key: Key) -> Ige {
+		Ige{
+			encryptor: Encryptor::using(key.clone()),
+			decryptor: Decryptor::using(key)
+		}
+	}
+
+	#[cfg(feature = "std")]
+	pub fn encrypt(&self, iv: &[u8], input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.encrypt_in_place(iv, &mut output);
+		output
+	}
+
+	#[cfg(feature = "std")]
+	pub fn decrypt(&self, iv: &[u8], input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.decrypt_in_place(iv, &mut output);
+		output
+	}
+
+	pub fn encrypt_in_place(&self, iv: &[u8], data: &mut [u8]) {
+		if iv.len() != 32 { panic!("IGE IV must be 32 bytes!"); }
+		if !data.len().is_multiple_of(16) { panic!("IGE can only encrypt whole blocks!"); }
+
+		let mut prev_out = [0; 16];
+		let mut prev_in = [0; 16];
+		prev_out.copy_from_slice(&iv[..16]);
+		prev_in.copy_from_slice(&iv[16..]);
+
+		for chunk in data.chunks_mut(16) {
+			let mut block = [0; 16];
+			for j in 0..16 {
+				block[j] = chunk[j] ^ prev_out[j];
+			}
+			prev_in = swap_block(chunk, prev_in);
+
+			let block = self.encryptor.encrypt(&block, false);
+			for j in 0..16 {
+				chunk[j] ^= block[j];
+			}
+			prev_out.copy_from_slice(chunk);
+		}
+	}
+
+	pub fn decrypt_in_place(&self, iv: &[u8], data: &mut [u8]) {
+		if iv.len() != 32 { panic!("IGE IV must be 32 bytes!"); }
+		if !data.len().is_multiple_of(16) { panic!("IGE can only decrypt whole blocks!"); }
+
+		let mut prev_in = [0; 16];
+		let mut prev_out = [0; 16];
+		prev_in.copy_from_slice(&iv[..16]);
+		prev_out.copy_from_slice(&iv[16..]);
+
+		for chunk in data.chunks_mut(16) {
+			let mut block = [0; 16];
+			for j in 0..16 {
+				block[j] = chunk[j] ^ prev_out[j];
+			}
+			prev_in = swap_block(chunk, prev_in);
+
+			let block = self.decryptor.decrypt(&block, false);
+			for j in 0..16 {
+				chunk[j] ^= block[j];
+			}
+			prev_out.copy_from_slice(chunk);
+		}
+	}
+}
+
+// Puts the block into the chunk and returns what the chunk held
+fn swap_block(chunk: &mut [u8], block: [u8; 16]) -> [u8; 16] {
+	let mut old = [0; 16];
+	old.copy_from_slice(chunk);
+	chunk.copy_from_slice(&block);
+	old
+}
+
+impl BiIge {
+	pub fn using(key1: Key, key2: Key) -> BiIge {
+		BiIge{
+			forward: Ige::using(key1),
+			backward: Ige::using(key2)
+		}
+	}
+
+	#[cfg(feature = "std")]
+	pub fn encrypt(&self, iv: &[u8], input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.encrypt_in_place(iv, &mut output);
+		output
+	}
+
+	#[cfg(feature = "std")]
+	pub fn decrypt(&self, iv: &[u8], input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.decrypt_in_place(iv, &mut output);
+		output
+	}
+
+	pub fn encrypt_in_place(&self, iv: &[u8], data: &mut [u8]) {
+		if iv.len() != 64 { panic!("Bi-directional IGE IV must be 64 bytes!"); }
+
+		self.forward.encrypt_in_place(&iv[..32], data);
+		reverse_blocks(data);
+		self.backward.encrypt_in_place(&iv[32..], data);
+		reverse_blocks(data);
+	}
+
+	pub fn decrypt_in_place(&self, iv: &[u8], data: &mut [u8]) {
+		if iv.len() != 64 { panic!("Bi-directional IGE IV must be 64 bytes!"); }
+
+		reverse_blocks(data);
+		self.backward.decrypt_in_place(&iv[32..], data);
+		reverse_blocks(data);
+		self.forward.decrypt_in_place(&iv[..32], data);
+	}
+}
+
+fn reverse_blocks(data: &mut [u8]) {
+	let blocks = data.len() / 16;
+	for i in 0..blocks/2 {
+		let (front, back) = data.split_at_mut((blocks - 1 - i) * 16);
+		front[i*16..(i+1)*16].swap_with_slice(&mut back[..16]);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use ige::*;
+
+	// Test vectors are from OpenSSL's test/igetest.c
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_ige_encrypt() {
+		let ige = Ige::using(Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f]));
+
+		let iv = [
+			0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+			0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f
+		];
+		let expected = [
+			0x1a, 0x85, 0x19, 0xa6, 0x55, 0x7b, 0xe6, 0x52, 0xe9, 0xda, 0x8e, 0x43, 0xda, 0x4e, 0xf4, 0x45,
+			0x3c, 0xf4, 0x56, 0xb4, 0xca, 0x48, 0x8a, 0xa3, 0x83, 0xc7, 0x9c, 0x98, 0xb3, 0x47, 0x97, 0xcb
+		];
+		assert_eq!(expected, &ige.encrypt(&iv, &[0; 32])[..]);
+
+		let ige = Ige::using(Key::from_bytes(b"This is an imple"));
+
+		let input = [
+			0x99, 0x70, 0x64, 0x87, 0xa1, 0xcd, 0xe6, 0x13, 0xbc, 0x6d, 0xe0, 0xb6, 0xf2, 0x4b, 0x1c, 0x7a,
+			0xa4, 0x48, 0xc8, 0xb9, 0xc3, 0x40, 0x3e, 0x34, 0x67, 0xa8, 0xca, 0xd8, 0x93, 0x40, 0xf5, 0x3b
+		];
+		assert_eq!(&b"L. Let's hope Ben got it right!\n"[..], &ige.encrypt(b"mentation of IGE mode for OpenSS", &input)[..]);
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_ige_decrypt() {
+		let ige = Ige::using(Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f]));
+
+		let iv = [
+			0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+			0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f
+		];
+		let input = [
+			0x1a, 0x85, 0x19, 0xa6, 0x55, 0x7b, 0xe6, 0x52, 0xe9, 0xda, 0x8e, 0x43, 0xda, 0x4e, 0xf4, 0x45,
+			0x3c, 0xf4, 0x56, 0xb4, 0xca, 0x48, 0x8a, 0xa3, 0x83, 0xc7, 0x9c, 0x98, 0xb3, 0x47, 0x97, 0xcb
+		];
+		assert_eq!(&[0; 32], &ige.decrypt(&iv, &input)[..]);
+
+		let ige = Ige::using(Key::from_bytes(b"This is an imple"));
+
+		let expected = [
+			0x99, 0x70, 0x64, 0x87, 0xa1, 0xcd, 0xe6, 0x13, 0xbc, 0x6d, 0xe0, 0xb6, 0xf2, 0x4b, 0x1c, 0x7a,
+			0xa4, 0x48, 0xc8, 0xb9, 0xc3, 0x40, 0x3e, 0x34, 0x67, 0xa8, 0xca, 0xd8, 0x93, 0x40, 0xf5, 0x3b
+		];
+		assert_eq!(&expected, &ige.decrypt(b"mentation of IGE mode for OpenSS", b"L. Let's hope Ben got it right!\n")[..]);
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_bi_ige() {
+		let key = [0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f];
+		let bi_ige = BiIge::using(Key::new(&key), Key::new(&key));
+
+		let iv = [
+			0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+			0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f,
+			0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x2b, 0x2c, 0x2d, 0x2e, 0x2f,
+			0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x3b, 0x3c, 0x3d, 0x3e, 0x3f
+		];
+		let expected = [
+			0x14, 0x40, 0x6f, 0xae, 0xa2, 0x79, 0xf2, 0x56, 0x1f, 0x86, 0xeb, 0x3b, 0x7d, 0xff, 0x53, 0xdc,
+			0x4e, 0x27, 0x0c, 0x03, 0xde, 0x7c, 0xe5, 0x16, 0x6a, 0x9c, 0x20, 0x33, 0x9d, 0x33, 0xfe, 0x12
+		];
+		assert_eq!(expected, &bi_ige.encrypt(&iv, &[0; 32])[..]);
+		assert_eq!(&[0; 32], &bi_ige.decrypt(&iv, &expected)[..]);
+	}
+
+	// OpenSSL's second vector has distinct 256 bit keys, but its output only
+	// comes from the first one
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_bi_ige_openssl_keys() {
+		let key1 = [
+			0x58, 0x0a, 0x06, 0xe9, 0x97, 0x07, 0x59, 0x5c, 0x9e, 0x19, 0xd2, 0xa7, 0xbb, 0x40, 0x2b, 0x7a,
+			0xc7, 0xd8, 0x11, 0x9e, 0x4c, 0x51, 0x35, 0x75, 0x64, 0x28, 0x0f, 0x23, 0xad, 0x74, 0xac, 0x37
+		];
+		let key2 = [
+			0xd1, 0x80, 0xa0, 0x31, 0x47, 0xa3, 0x11, 0x13, 0x86, 0x26, 0x9e, 0x6d, 0xff, 0xaf, 0x72, 0x74,
+			0x5b, 0xa2, 0x35, 0x81, 0xd2, 0xa6, 0x3d, 0x21, 0x67, 0x7b, 0x58, 0xa8, 0x18, 0xf9, 0x72, 0xe4
+		];
+		let iv = [
+			0x80, 0x3d, 0xbd, 0x4c, 0xe6, 0x7b, 0x06, 0xa9, 0x53, 0x35, 0xd5, 0x7e, 0x71, 0xc1, 0x70, 0x70,
+			0x74, 0x9a, 0x00, 0x28, 0x0c, 0xbf, 0x6c, 0x42, 0x9b, 0xa4, 0xdd, 0x65, 0x11, 0x77, 0x7c, 0x67,
+			0xfe, 0x76, 0x0a, 0xf0, 0xd5, 0xc6, 0x6e, 0x6a, 0xe7, 0x5e, 0x4c, 0xf2, 0x7e, 0x9e, 0xf9, 0x20,
+			0x0e, 0x54, 0x6f, 0x2d, 0x8a, 0x8d, 0x7e, 0xbd, 0x48, 0x79, 0x37, 0x99, 0xff, 0x27, 0x93, 0xa3
+		];
+		let input = [
+			0xf1, 0x54, 0x3d, 0xca, 0xfe, 0xb5, 0xef, 0x1c, 0x4f, 0xa6, 0x43, 0xf6, 0xe6, 0x48, 0x57, 0xf0,
+			0xee, 0x15, 0x7f, 0xe3, 0xe7, 0x2f, 0xd0, 0x2f, 0x11, 0x95, 0x7a, 0x17, 0x00, 0xab, 0xa7, 0x0b,
+			0xbe, 0x44, 0x09, 0x9c, 0xcd, 0xac, 0xa8, 0x52, 0xa1, 0x8e, 0x7b, 0x75, 0xbc, 0xa4, 0x92, 0x5a,
+			0xab, 0x46, 0xd3, 0x3a, 0xa0, 0xd5, 0x35, 0x1c, 0x55, 0xa4, 0xb3, 0xa8, 0x40, 0x81, 0xa5, 0x0b
+		];
+		let expected = [
+			0x42, 0xe5, 0x28, 0x30, 0x31, 0xc2, 0xa0, 0x23, 0x68, 0x49, 0x4e, 0xb3, 0x24, 0x59, 0x92, 0x79,
+			0xc1, 0xa5, 0xcc, 0xe6, 0x76, 0x53, 0xb1, 0xcf, 0x20, 0x86, 0x23, 0xe8, 0x72, 0x55, 0x99, 0x92,
+			0x0d, 0x16, 0x1c, 0x5a, 0x2f, 0xce, 0xcb, 0x51, 0xe2, 0x67, 0xfa, 0x10, 0xec, 0xcd, 0x3d, 0x67,
+			0xa5, 0xe6, 0xf7, 0x31, 0x26, 0xb0, 0x0d, 0x76, 0x5e, 0x28, 0xdc, 0x7f, 0x01, 0xc5, 0xa5, 0x4c
+		];
+
+		let openssl = BiIge::using(Key::from_bytes(&key1), Key::from_bytes(&key1));
+		assert_eq!(&expected[..], &openssl.encrypt(&iv, &input)[..]);
+		assert_eq!(&input[..], &openssl.decrypt(&iv, &expected)[..]);
+
+		let bi_ige = BiIge::using(Key::from_bytes(&key1), Key::from_bytes(&key2));
+		assert_ne!(&expected[..], &bi_ige.encrypt(&iv, &input)[..]);
+	}
+
+	// With distinct keys, the first key is the forward pass and the second
+	// the backward pass over the reversed blocks
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_bi_ige_key_order() {
+		let key1 = Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f]);
+		let key2 = Key::new(&[0x10111213, 0x14151617, 0x18191a1b, 0x1c1d1e1f]);
+		let iv: Vec<u8> = (0x40..0x80).collect();
+		let input: Vec<u8> = (0..64).collect();
+
+		let mut expected = Ige::using(key1.clone()).encrypt(&iv[..32], &input);
+		reverse_blocks(&mut expected);
+		let mut expected = Ige::using(key2.clone()).encrypt(&iv[32..], &expected);
+		reverse_blocks(&mut expected);
+
+		let bi_ige = BiIge::using(key1.clone(), key2.clone());
+		assert_eq!(expected, bi_ige.encrypt(&iv, &input));
+		assert_eq!(input, bi_ige.decrypt(&iv, &expected));
+		assert_ne!(expected, BiIge::using(key2, key1).encrypt(&iv, &input));
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_round_trip256() {
+		let key = Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f, 0x10111213, 0x14151617, 0x18191a1b, 0x1c1d1e1f]);
+		let key2 = Key::new(&[0x1f1e1d1c, 0x1b1a1918, 0x17161514, 0x13121110, 0x0f0e0d0c, 0x0b0a0908, 0x07060504, 0x03020100]);
+		let input = b"The quick brown fox jumps over the lazy dog, twice!!";
+		let input = &input[..48];
+
+		let ige = Ige::using(key.clone());
+		let iv = [0x5a; 64];
+		let ciphertext = ige.encrypt(&iv[..32], input);
+		assert_eq!(input, &ige.decrypt(&iv[..32], &ciphertext)[..]);
+
+		let bi_ige = BiIge::using(key, key2);
+		let ciphertext = bi_ige.encrypt(&iv, input);
+		assert_eq!(input, &bi_ige.decrypt(&iv, &ciphertext)[..]);
+
+		// Flipping a bit in the last block garbles the first block too
+		let mut corrupted = ciphertext.clone();
+		corrupted[47] ^= 1;
+		assert_ne!(&input[..16], &bi_ige.decrypt(&iv, &corrupted)[..16]);
+	}
+
+	#[test]
+	fn test_in_place() {
+		let key = [0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f];
+		let ige = Ige::using(Key::new(&key));
+		let bi_ige = BiIge::using(Key::new(&key), Key::new(&key));
+
+		let mut iv = [0; 64];
+		for i in 0..64 { iv[i] = i as u8; }
+
+		let mut data = [0; 32];
+		ige.encrypt_in_place(&iv[..32], &mut data);
+		assert_eq!([0x1a, 0x85, 0x19, 0xa6], data[..4]);
+		ige.decrypt_in_place(&iv[..32], &mut data);
+		assert_eq!([0; 32], data);
+
+		// An odd number of blocks leaves the middle one where it is
+		let mut data = [0; 48];
+		bi_ige.encrypt_in_place(&iv, &mut data);
+		bi_ige.decrypt_in_place(&iv, &mut data);
+		assert_eq!([0; 48], data);
+
+		let mut data = [0; 32];
+		bi_ige.encrypt_in_place(&iv, &mut data);
+		assert_eq!([0x14, 0x40, 0x6f, 0xae], data[..4]);
+	}
+}
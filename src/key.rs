@@ -1,9 +1,13 @@
-use std::ops;
-use std::fmt;
-use std::result;
+use core::ops;
+use core::fmt;
+use core::result;
 
+use buffer::{Buffer, LockError};
 use sbox;
+#[cfg(feature = "std")]
+use secret;
 use util;
+use zeroize::zeroize;
 
 // Rcon[] is 1-based, so the first entry is just a place holder
 const R_CON: [u32; 13*4] = [ 0x00000000, 
@@ -22,17 +26,24 @@ const R_CON: [u32; 13*4] = [ 0x00000000,
     0xE8000000, 0xCB000000, 0x8D000000
 ];
 
+#[derive(Clone)]
 pub struct Key {
-    words: Vec<u32>
+    words: Buffer<u32, 8>
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct KeySchedule {
-    words: Vec<u32>
+    words: Buffer<u32, 60>
+}
+
+// Shows the words of a key or schedule, which Debug and Display won't.  Only
+// for test vectors and tracing, where printing the key is the point
+pub struct Revealed<'a> {
+    words: &'a [u32]
 }
 
 impl Key {
-    pub fn new(words: &[u32]) -> Key {
+    pub const fn new(words: &[u32]) -> Key {
         match words.len() {
             4 => {},
             6 => {},
@@ -40,59 +51,284 @@ impl Key {
             _ => {panic!("Invalid key size!")}
         };
 
+        let mut values = [0; 8];
+        let mut i = 0;
+        while i < words.len() {
+            values[i] = words[i];
+            i += 1;
+        }
+
         Key {
-            words: words.to_vec()
+            words: Buffer::from_array(values, words.len())
+        }
+    }
+
+    // For keys written into the source.  In a constant a bad key fails the
+    // build rather than panicking
+    pub const fn from_hex(hex: &str) -> Key {
+        let hex = hex.as_bytes();
+        if !hex.len().is_multiple_of(8) || hex.len() > 64 { panic!("Invalid key size!"); }
+
+        let mut words = [0; 8];
+        let mut i = 0;
+        while i < hex.len() {
+            words[i / 8] = words[i / 8] << 4 | hex_digit(hex[i]);
+            i += 1;
         }
+
+        Key::new(words.split_at(hex.len() / 8).0)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Key {
+        if !bytes.len().is_multiple_of(4) { panic!("Invalid key size!"); }
+
+        if bytes.len() > 32 { panic!("Invalid key size!"); }
+
+        let mut words: Buffer<u32, 8> = Buffer::new();
+        for b in bytes.chunks(4) {
+            words.push(util::bytes_to_word((b[0], b[1], b[2], b[3])));
+        }
+
+        Key::new(&words)
+    }
+
+    // A const fn so that fixed keys can be expanded at compile time, see
+    // encryptor!.  Nothing can be locked in a const fn, so a SecretKey's key
+    // panics here; its locked schedule is SecretKey::schedule
+    pub const fn create_schedule(&self) -> KeySchedule {
+        KeySchedule { words: expand(self.words.inline_slice(), 4) }
+    }
+
+    pub(crate) fn expand_unlocked(&self) -> KeySchedule {
+        KeySchedule { words: expand(&self.words, 4) }
     }
 
-    pub fn create_schedule(&self) -> KeySchedule {
-        let nk = self.words.len();
-        let nr = nk + 6;
-        let mut vector: Vec<u32> = self.words.clone();
-
-        for i in nk..4*(nr+1) {
-            let temp = vector[i-1];
-            let temp = if i % nk == 0 {
-                sbox::sub_word(util::rot_word(temp)) ^ R_CON[i/nk]
-            } else if nk > 6 && i % nk == 4 {
-                sbox::sub_word(temp)
-            } else {
-                temp
-            };
-            let prev = vector[i-nk];
-            vector.push(prev ^ temp);
+    // The cipher key from Nk consecutive words of its schedule, starting at
+    // word `first`, by running the expansion backwards.  Nk is however many
+    // words are given.  For AES-128 one round key is enough; the longer keys
+    // need the words next to it as well
+    pub fn from_schedule_words(first: usize, words: &[u32]) -> Key {
+        let nk = words.len();
+        match nk {
+            4 => {},
+            6 => {},
+            8 => {},
+            _ => {panic!("Invalid key size!")}
+        };
+        if first + nk > 4 * (nk + 7) { panic!("Words past the end of the key schedule!"); }
+
+        let mut vector = [0; 60];
+        vector[first..first+nk].copy_from_slice(words);
+        for i in (nk..first+nk).rev() {
+            vector[i-nk] = vector[i] ^ schedule_core(vector[i-1], i, nk);
         }
 
-        KeySchedule::new(&vector)
+        let key = Key::new(&vector[..nk]);
+        zeroize(&mut vector);
+        key
+    }
+
+    // Round key `round` followed by any more words the key size needs
+    pub fn from_round_key(round: usize, words: &[u32]) -> Key {
+        Key::from_schedule_words(4 * round, words)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.words.iter().flat_map(|w| w.to_be_bytes().to_vec()).collect()
+    }
+
+    pub(crate) fn words(&self) -> &[u32] {
+        &self.words
+    }
+
+    // Key material worked out from this key, kept locked if this is
+    pub(crate) fn derive(&self, words: &[u32]) -> Key {
+        Key {
+            words: Buffer::from_slice(words).like(self.is_locked())
+        }
     }
 
 	pub fn get_size_bits(&self) -> usize {
 		self.words.len() * 32
 	}
+
+	pub fn reveal(&self) -> Revealed<'_> {
+		Revealed{ words: &self.words }
+	}
+
+	// Whether this is a SecretKey's key, in locked memory
+	pub const fn is_locked(&self) -> bool {
+		self.words.is_locked()
+	}
+
+	#[cfg(feature = "std")]
+	pub(crate) fn lock(&self) -> result::Result<Key, secret::Error> {
+		Ok(Key{ words: self.words.lock()? })
+	}
+}
+
+// Rijndael's key expansion, for a block of nb words.  There's a round key of
+// nb words for each of the max(Nk, Nb) + 6 rounds and one more before them
+pub(crate) const fn expand<const N: usize>(key: &[u32], nb: usize) -> Buffer<u32, N> {
+    let nk = key.len();
+    let rounds = if nk > nb { nk } else { nb } + 6;
+
+    expand_with(key, nb, rounds, R_CON.split_at(1).1)
+}
+
+// The expansion for some other number of rounds, with rcon[j - 1] as Rcon[j]
+pub(crate) const fn expand_with<const N: usize>(key: &[u32], nb: usize, rounds: usize, rcon: &[u32]) -> Buffer<u32, N> {
+    let nk = key.len();
+    let mut vector: Buffer<u32, N> = Buffer::from_array([0; N], 0);
+
+    let mut i = 0;
+    while i < nb*(rounds+1) {
+        if i < nk {
+            vector.push(key[i]);
+            i += 1;
+            continue;
+        }
+
+        let r = if i.is_multiple_of(nk) { rcon[i/nk - 1] } else { 0 };
+        let temp = schedule_core_with(vector.inline_slice()[i-1], i, nk, r);
+        let prev = vector.inline_slice()[i-nk];
+        vector.push(prev ^ temp);
+        i += 1;
+    }
+
+    vector
+}
+
+// What word i of the schedule xors into the word Nk before it, from the word
+// just before it.  Xoring it into word i gives back word i - Nk again, which
+// is how the schedule runs backwards
+pub(crate) const fn schedule_core(temp: u32, i: usize, nk: usize) -> u32 {
+    schedule_core_with(temp, i, nk, R_CON[i/nk])
+}
+
+// With some other round constant than Rcon[i/Nk], where one is used
+pub(crate) const fn schedule_core_with(temp: u32, i: usize, nk: usize, rcon: u32) -> u32 {
+    if i.is_multiple_of(nk) {
+        sbox::sub_word(util::rot_word(temp)) ^ rcon
+    } else if nk > 6 && i % nk == 4 {
+        sbox::sub_word(temp)
+    } else {
+        temp
+    }
+}
+
+const fn hex_digit(digit: u8) -> u32 {
+    (match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        b'A'..=b'F' => digit - b'A' + 10,
+        _ => panic!("Invalid hex digit in key!")
+    }) as u32
 }
 
 impl fmt::Display for Key {
 	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
-		for word in &self.words {
-            write!(formatter, "{:0>8x}", word);
-        }
-        result::Result::Ok(())
+		write!(formatter, "<redacted {} bit key>", self.get_size_bits())
+	}
+}
+
+impl fmt::Debug for Key {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
+		formatter.debug_struct("Key").field("bits", &self.get_size_bits()).finish_non_exhaustive()
 	}
 }
 
 impl KeySchedule {
-    pub fn new(words: &[u32]) -> KeySchedule {
+    pub const fn new(words: &[u32]) -> KeySchedule {
         match words.len() {
             44 => {},
             52 => {},
             60 => {},
             _ => {panic!("Invalid key schedule size!")}
         };
-        
+
+        let mut values = [0; 60];
+        let mut i = 0;
+        while i < words.len() {
+            values[i] = words[i];
+            i += 1;
+        }
+
+        KeySchedule {
+            words: Buffer::from_array(values, words.len())
+        }
+    }
+
+    // The whole schedule back from part of it, see Key::from_schedule_words
+    pub fn from_schedule_words(first: usize, words: &[u32]) -> KeySchedule {
+        Key::from_schedule_words(first, words).create_schedule()
+    }
+
+    // A schedule the key was expanded into some other way
+    pub(crate) fn for_key(key: &Key, words: Buffer<u32, 60>) -> KeySchedule {
+        if words.len() != 4 * (key.words.len() + 7) { panic!("Invalid key schedule size!"); }
+
+        KeySchedule { words }
+    }
+
+    // Kept in locked memory if the key it was expanded from is
+    pub(crate) fn try_like(self, key: &Key) -> result::Result<KeySchedule, LockError> {
+        Ok(KeySchedule {
+            words: self.words.try_like(key.is_locked())?
+        })
+    }
+
+    pub(crate) fn like(self, key: &Key) -> KeySchedule {
         KeySchedule {
-            words: words.to_vec()
+            words: self.words.like(key.is_locked())
         }
     }
+
+    // Whether this could be the key's schedule, going by its size and the
+    // key words it starts with.  Checking the rest would mean expanding the
+    // key again
+    pub(crate) fn is_for(&self, key: &Key) -> bool {
+        self.rounds() == key.words.len() + 6 && self.words.starts_with(&key.words)
+    }
+
+    pub fn rounds(&self) -> usize {
+        self.words.len() / 4 - 1
+    }
+
+	pub fn reveal(&self) -> Revealed<'_> {
+		Revealed{ words: &self.words }
+	}
+
+	pub const fn is_locked(&self) -> bool {
+		self.words.is_locked()
+	}
+
+	#[cfg(feature = "std")]
+	pub(crate) fn lock(&self) -> result::Result<KeySchedule, secret::Error> {
+		Ok(KeySchedule{ words: self.words.lock()? })
+	}
+}
+
+impl fmt::Debug for KeySchedule {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
+		formatter.debug_struct("KeySchedule").field("rounds", &self.rounds()).finish_non_exhaustive()
+	}
+}
+
+impl<'a> fmt::Display for Revealed<'a> {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
+		for word in self.words {
+			write!(formatter, "{:0>8x}", word)?;
+		}
+		result::Result::Ok(())
+	}
+}
+
+impl<'a> fmt::Debug for Revealed<'a> {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
+		fmt::Display::fmt(self, formatter)
+	}
 }
 
 impl ops::Index<usize> for KeySchedule {
@@ -129,6 +365,16 @@ mod tests {
         assert_eq!(KeySchedule::new(&expected), schedule);
     }
 
+    #[test]
+    fn test_key_from_bytes() {
+        let key = Key::from_bytes(&[
+            0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6,
+            0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c
+        ]);
+
+        assert_eq!(Key::new(&[0x2b7e1516, 0x28aed2a6, 0xabf71588, 0x09cf4f3c]).create_schedule(), key.create_schedule());
+    }
+
 	#[test]
 	fn test_index_key_schedule() {
 		let schedule = KeySchedule::new(&[
@@ -148,4 +394,63 @@ mod tests {
 		assert_eq!([0x2b7e1516, 0x28aed2a6, 0xabf71588, 0x09cf4f3c], schedule[0]);
 		assert_eq!([0xa0fafe17, 0x88542cb1, 0x23a33939, 0x2a6c7605], schedule[1]);
 	}
+
+	static KEY: Key = Key::from_hex("2b7e151628AED2A6abf7158809cf4f3c");
+	static SCHEDULE: KeySchedule = KEY.create_schedule();
+
+	#[test]
+	fn test_const_schedule() {
+		let key = Key::new(&[0x2b7e1516, 0x28aed2a6, 0xabf71588, 0x09cf4f3c]);
+
+		assert_eq!(key.create_schedule(), SCHEDULE);
+		assert_eq!([0xd014f9a8, 0xc9ee2589, 0xe13f0cc8, 0xb6630ca6], SCHEDULE[10]);
+		assert!(SCHEDULE.is_for(&key));
+		assert!(!SCHEDULE.is_for(&Key::new(&[0; 4])));
+	}
+
+	#[test]
+	#[should_panic]
+	fn test_bad_hex() {
+		Key::from_hex("2b7e151628aed2a6abf7158809cf4f3g");
+	}
+
+	// The expansions in FIPS-197 Appendix A, run backwards from their ends
+	#[test]
+	fn test_invert_schedule() {
+		let key = Key::new(&[0x2b7e1516, 0x28aed2a6, 0xabf71588, 0x09cf4f3c]);
+		assert_eq!(key.words(), Key::from_round_key(10, &[0xd014f9a8, 0xc9ee2589, 0xe13f0cc8, 0xb6630ca6]).words());
+		assert_eq!(key.words(), Key::from_round_key(5, &[0xd4d1c6f8, 0x7c839d87, 0xcaf2b8bc, 0x11f915bc]).words());
+		assert_eq!(key.create_schedule(), KeySchedule::from_schedule_words(40, &[0xd014f9a8, 0xc9ee2589, 0xe13f0cc8, 0xb6630ca6]));
+
+		let key = Key::new(&[0x8e73b0f7, 0xda0e6452, 0xc810f32b, 0x809079e5, 0x62f8ead2, 0x522c6b7b]);
+		let last = [0x282d166a, 0xbc3ce7b5, 0xe98ba06f, 0x448c773c, 0x8ecc7204, 0x01002202];
+		assert_eq!(key.words(), Key::from_schedule_words(46, &last).words());
+		assert_eq!(key.create_schedule(), KeySchedule::from_schedule_words(46, &last));
+
+		let key = Key::new(&[0x603deb10, 0x15ca71be, 0x2b73aef0, 0x857d7781, 0x1f352c07, 0x3b6108d7, 0x2d9810a3, 0x0914dff4]);
+		let last = [0xcafaaae3, 0xe4d59b34, 0x9adf6ace, 0xbd10190d, 0xfe4890d1, 0xe6188d0b, 0x046df344, 0x706c631e];
+		assert_eq!(key.words(), Key::from_round_key(13, &last).words());
+
+		// Starting at the key itself gives it straight back
+		assert_eq!(key.words(), Key::from_round_key(0, key.words()).words());
+	}
+
+	#[test]
+	#[should_panic]
+	fn test_invert_past_end() {
+		Key::from_round_key(12, &[0; 6]);
+	}
+
+	#[test]
+	fn test_redacted() {
+		let key = Key::new(&[0x2b7e1516, 0x28aed2a6, 0xabf71588, 0x09cf4f3c]);
+		let schedule = key.create_schedule();
+
+		assert_eq!("<redacted 128 bit key>", format!("{}", key));
+		assert_eq!("Key { bits: 128, .. }", format!("{:?}", key));
+		assert_eq!("KeySchedule { rounds: 10, .. }", format!("{:?}", schedule));
+
+		assert_eq!("2b7e151628aed2a6abf7158809cf4f3c", format!("{}", key.reveal()));
+		assert!(format!("{:?}", schedule.reveal()).ends_with("d014f9a8c9ee2589e13f0cc8b6630ca6"));
+	}
 }
\ No newline at end of file
@@ -0,0 +1,49 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![allow(clippy::needless_range_loop)]
+
+// no_std brings in core by itself, but with std it has to be named so that
+// the same paths work either way
+#[cfg(any(feature = "std", test))]
+extern crate core;
+
+pub mod ff;
+pub mod gf;
+pub mod polynomial;
+pub mod matrix;
+mod state;
+mod tower;
+mod ttable;
+mod vperm;
+mod aesni;
+mod bitslice;
+pub mod sbox;
+mod util;
+mod zeroize;
+mod buffer;
+#[cfg(feature = "std")]
+mod locked;
+pub mod key;
+#[cfg(feature = "std")]
+pub mod secret;
+pub mod aes;
+pub mod compact;
+pub mod rijndael;
+pub mod reduced;
+pub mod poly1305;
+pub mod cts;
+pub mod ige;
+#[cfg(feature = "std")]
+mod sha1;
+#[cfg(feature = "std")]
+pub mod openpgp;
+pub mod gf128;
+pub mod polyval;
+pub mod hctr2;
+pub mod eme2;
+pub mod tweakable;
+pub mod lrw;
+pub mod ctr;
+pub mod xts;
+pub mod gcm;
+#[cfg(feature = "std")]
+pub mod parallel;
@@ -0,0 +1,157 @@
+use std::io;
+use std::ops;
+use std::slice;
+
+use secret::Error;
+use zeroize::zeroize;
+
+// The few libc calls needed, declared by hand to avoid the dependency.  The
+// constants are the Linux values
+#[cfg(target_os = "linux")]
+mod sys {
+	use std::os::raw::{c_int, c_long, c_void};
+
+	pub const PROT_NONE: c_int = 0;
+	pub const PROT_READ: c_int = 1;
+	pub const PROT_WRITE: c_int = 2;
+	pub const MAP_PRIVATE: c_int = 0x02;
+	pub const MAP_ANONYMOUS: c_int = 0x20;
+	pub const MADV_DONTDUMP: c_int = 16;
+	pub const SC_PAGESIZE: c_int = 30;
+
+	extern "C" {
+		pub fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: c_long) -> *mut c_void;
+		pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+		pub fn mprotect(addr: *mut c_void, len: usize, prot: c_int) -> c_int;
+		pub fn madvise(addr: *mut c_void, len: usize, advice: c_int) -> c_int;
+		pub fn mlock(addr: *const c_void, len: usize) -> c_int;
+		pub fn munlock(addr: *const c_void, len: usize) -> c_int;
+		pub fn sysconf(name: c_int) -> c_long;
+	}
+}
+
+// Lets tests make locking fail the way it does once RLIMIT_MEMLOCK is used up
+#[cfg(test)]
+thread_local! {
+	pub static FAIL_LOCKS: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+fn errno() -> i32 {
+	io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+// A slice in its own mapping, locked into RAM and left out of core dumps.
+// There's an inaccessible guard page on either side, and the values are put
+// right up against the one after them so that running off the end faults
+// straight away
+pub struct Locked<T> {
+	region: *mut u8,
+	region_len: usize,
+	page: usize,
+	data: *mut T,
+	len: usize
+}
+
+// It's only ever read through shared references once it's made
+unsafe impl<T: Sync> Sync for Locked<T> {}
+unsafe impl<T: Send> Send for Locked<T> {}
+
+#[cfg(target_os = "linux")]
+impl<T: Copy + Default> Locked<T> {
+	pub fn new(values: &[T]) -> Result<Locked<T>, Error> {
+		use std::mem;
+		use std::ptr;
+
+		let page = unsafe { sys::sysconf(sys::SC_PAGESIZE) } as usize;
+		let bytes = mem::size_of_val(values);
+		let data_len = bytes.div_ceil(page).max(1) * page;
+		let region_len = data_len + 2 * page;
+
+		unsafe {
+			let region = sys::mmap(
+				ptr::null_mut(), region_len,
+				sys::PROT_READ | sys::PROT_WRITE, sys::MAP_PRIVATE | sys::MAP_ANONYMOUS, -1, 0
+			);
+			if region as isize == -1 { return Err(Error::Map(errno())); }
+			let region = region as *mut u8;
+
+			// Everything after this unmaps the region again if it fails
+			let fail = |error: Error| {
+				sys::munmap(region as *mut _, region_len);
+				Err(error)
+			};
+
+			let data_pages = region.add(page);
+			if sys::mprotect(region as *mut _, page, sys::PROT_NONE) != 0
+				|| sys::mprotect(data_pages.add(data_len) as *mut _, page, sys::PROT_NONE) != 0 {
+				return fail(Error::Protect(errno()));
+			}
+			if sys::madvise(data_pages as *mut _, data_len, sys::MADV_DONTDUMP) != 0 {
+				return fail(Error::DontDump(errno()));
+			}
+			#[cfg(test)]
+			if FAIL_LOCKS.with(|fail| fail.get()) {
+				return fail(Error::Lock(12));
+			}
+			if sys::mlock(data_pages as *const _, data_len) != 0 {
+				return fail(Error::Lock(errno()));
+			}
+
+			let data = data_pages.add(data_len - bytes) as *mut T;
+			ptr::copy_nonoverlapping(values.as_ptr(), data, values.len());
+
+			Ok(Locked{ region, region_len, page, data, len: values.len() })
+		}
+	}
+}
+
+#[cfg(not(target_os = "linux"))]
+impl<T: Copy + Default> Locked<T> {
+	pub fn new(_values: &[T]) -> Result<Locked<T>, Error> {
+		Err(Error::Unsupported)
+	}
+}
+
+impl<T> ops::Deref for Locked<T> {
+	type Target = [T];
+
+	fn deref(&self) -> &[T] {
+		unsafe { slice::from_raw_parts(self.data, self.len) }
+	}
+}
+
+#[cfg(target_os = "linux")]
+impl<T> Drop for Locked<T> {
+	fn drop(&mut self) {
+		unsafe {
+			zeroize(slice::from_raw_parts_mut(self.data as *mut u8, self.len * std::mem::size_of::<T>()));
+
+			let data_pages = self.region.add(self.page);
+			sys::munlock(data_pages as *const _, self.region_len - 2 * self.page);
+			sys::munmap(self.region as *mut _, self.region_len);
+		}
+	}
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+	use locked::*;
+
+	#[test]
+	fn test_locked() {
+		let values: Vec<u32> = (0..100).collect();
+		let locked = match Locked::new(&values) {
+			Ok(locked) => locked,
+			// Not everywhere the tests run allows locking memory
+			Err(Error::Lock(_)) => return,
+			Err(error) => panic!("{}", error)
+		};
+
+		assert_eq!(&values[..], &locked[..]);
+
+		// The values end exactly where the guard page starts
+		let end = locked.data as usize + 400;
+		assert_eq!(0, end % locked.page);
+		assert_eq!(locked.region as usize + locked.region_len - locked.page, end);
+	}
+}
@@ -0,0 +1,172 @@
+use core::slice;
+
+use aes::{Encryptor, Decryptor};
+use gf128;
+use key::Key;
+use tweakable::TweakableBlockCipher;
+use zeroize::zeroize;
+
+// LRW (Liskov, Rivest and Wagner) as specified in the IEEE P1619 drafts.  Each
+// block is masked before and after encryption with T = K2 * I in GF(2^128),
+// where I is the big endian block index used as the tweak:
+//   C = E_K1(P ^ T) ^ T
+pub struct Lrw {
+	encryptor: Encryptor,
+	decryptor: Decryptor,
+	tweak_key: u128
+}
+
+impl Lrw {
+	pub fn using(key: Key, tweak_key: &[u8]) -> Lrw {
+		if tweak_key.len() != 16 { panic!("LRW tweak key must be 16 bytes!"); }
+
+		let mut bytes = [0; 16];
+		bytes.copy_from_slice(tweak_key);
+
+		Lrw{
+			encryptor: Encryptor::using(key.clone()),
+			decryptor: Decryptor::using(key),
+			tweak_key: u128::from_be_bytes(bytes)
+		}
+	}
+
+	// Encrypts consecutive blocks starting at the given block index
+	#[cfg(feature = "std")]
+	pub fn encrypt(&self, index: u128, input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.encrypt_in_place(index, &mut output);
+		output
+	}
+
+	#[cfg(feature = "std")]
+	pub fn decrypt(&self, index: u128, input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.decrypt_in_place(index, &mut output);
+		output
+	}
+
+	pub fn encrypt_in_place(&self, index: u128, data: &mut [u8]) {
+		if !data.len().is_multiple_of(16) { panic!("LRW can only encrypt whole blocks!"); }
+
+		for (i, chunk) in data.chunks_mut(16).enumerate() {
+			let block = self.encrypt_block(&index.wrapping_add(i as u128).to_be_bytes(), chunk);
+			chunk.copy_from_slice(&block);
+		}
+	}
+
+	pub fn decrypt_in_place(&self, index: u128, data: &mut [u8]) {
+		if !data.len().is_multiple_of(16) { panic!("LRW can only decrypt whole blocks!"); }
+
+		for (i, chunk) in data.chunks_mut(16).enumerate() {
+			let block = self.decrypt_block(&index.wrapping_add(i as u128).to_be_bytes(), chunk);
+			chunk.copy_from_slice(&block);
+		}
+	}
+
+	fn mask(&self, tweak: &[u8; 16]) -> u128 {
+		gf128::mul(self.tweak_key, u128::from_be_bytes(*tweak))
+	}
+}
+
+impl Drop for Lrw {
+	fn drop(&mut self) {
+		zeroize(slice::from_mut(&mut self.tweak_key));
+	}
+}
+
+impl TweakableBlockCipher for Lrw {
+	fn encrypt_block(&self, tweak: &[u8; 16], input: &[u8]) -> [u8; 16] {
+		let mask = self.mask(tweak);
+		let block = self.encryptor.encrypt(&(to_block(input) ^ mask).to_be_bytes(), false);
+
+		(u128::from_be_bytes(block) ^ mask).to_be_bytes()
+	}
+
+	fn decrypt_block(&self, tweak: &[u8; 16], input: &[u8]) -> [u8; 16] {
+		let mask = self.mask(tweak);
+		let block = self.decryptor.decrypt(&(to_block(input) ^ mask).to_be_bytes(), false);
+
+		(u128::from_be_bytes(block) ^ mask).to_be_bytes()
+	}
+}
+
+fn to_block(bytes: &[u8]) -> u128 {
+	if bytes.len() != 16 { panic!("Can only process 16 byte blocks!"); }
+
+	let mut block = [0; 16];
+	block.copy_from_slice(bytes);
+	u128::from_be_bytes(block)
+}
+
+#[cfg(test)]
+mod tests {
+	use lrw::*;
+
+	fn lrw() -> Lrw {
+		Lrw::using(Key::from_bytes(&[
+			0x45, 0x62, 0xac, 0x25, 0xf8, 0x28, 0x17, 0x6d, 0x4c, 0x26, 0x84, 0x14, 0xb5, 0x68, 0x01, 0x85
+		]), &[
+			0x25, 0x8e, 0x2a, 0x05, 0xe7, 0x3e, 0x9d, 0x03, 0xee, 0x5a, 0x83, 0x0c, 0xcc, 0x09, 0x4c, 0x87
+		])
+	}
+
+	// LRW-32-AES 1 from the IEEE P1619/D16 draft
+	#[test]
+	fn test_encrypt_block() {
+		let mut tweak = [0; 16];
+		tweak[15] = 1;
+
+		assert_eq!([
+			0xf1, 0xb2, 0x73, 0xcd, 0x65, 0xa3, 0xdf, 0x5f, 0xe9, 0x5d, 0x48, 0x92, 0x54, 0x63, 0x4e, 0xb8
+		], lrw().encrypt_block(&tweak, b"0123456789ABCDEF"));
+	}
+
+	#[test]
+	fn test_decrypt_block() {
+		let mut tweak = [0; 16];
+		tweak[15] = 1;
+
+		assert_eq!(*b"0123456789ABCDEF", lrw().decrypt_block(&tweak, &[
+			0xf1, 0xb2, 0x73, 0xcd, 0x65, 0xa3, 0xdf, 0x5f, 0xe9, 0x5d, 0x48, 0x92, 0x54, 0x63, 0x4e, 0xb8
+		]));
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_consecutive_blocks() {
+		let lrw = lrw();
+		let input = [0x42; 64];
+
+		let ciphertext = lrw.encrypt(1, &input);
+		assert_eq!(&ciphertext[..16], &lrw.encrypt(1, &input[..16])[..]);
+		assert_eq!(&ciphertext[16..32], &lrw.encrypt(2, &input[16..32])[..]);
+
+		// Identical plaintext blocks at different indices encrypt differently
+		assert_ne!(&ciphertext[..16], &ciphertext[16..32]);
+
+		assert_eq!(&input[..], &lrw.decrypt(1, &ciphertext)[..]);
+	}
+
+	#[test]
+	fn test_in_place() {
+		let lrw = lrw();
+		let mut data = [0x42; 64];
+		lrw.encrypt_in_place(1, &mut data);
+
+		let mut second = [0x42; 16];
+		lrw.encrypt_in_place(2, &mut second);
+		assert_eq!(second, data[16..32]);
+
+		lrw.decrypt_in_place(1, &mut data);
+		assert_eq!([0x42; 64], data);
+	}
+
+	#[test]
+	fn test_trait_object() {
+		let cipher: Box<dyn TweakableBlockCipher> = Box::new(lrw());
+		let tweak = [0x17; 16];
+
+		let block = cipher.encrypt_block(&tweak, b"tweakable cipher");
+		assert_eq!(*b"tweakable cipher", cipher.decrypt_block(&tweak, &block));
+	}
+}
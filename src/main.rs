@@ -1,12 +1,7 @@
-mod ff;
-mod state;
-mod sbox;
-mod util;
-mod key;
-mod aes;
+extern crate aes;
 
-use key::*;
-use aes::*;
+use aes::key::*;
+use aes::aes::*;
 
 fn main() {
     encrypt(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f]);
@@ -36,8 +31,8 @@ fn main() {
 fn encrypt(key: &[u32]) {
     let key = Key::new(key);
 
-    println!("key   {}", key);
-    println!("input {}\n", "00112233445566778899aabbccddeeff");
+    println!("key   {}", key.reveal());
+    println!("input 00112233445566778899aabbccddeeff\n");
 
     let encryptor = Encryptor::using(key);
 
@@ -51,13 +46,13 @@ fn encrypt(key: &[u32]) {
 fn decrypt(key: &[u32], input: &[u8]) {
     let key = Key::new(key);
 
-    println!("key   {}", key);
+    println!("key   {}", key.reveal());
     print!("input ");
     
     for byte in input {
         print!("{:0>2x}", byte);
     }
-    print!("\n");    
+    println!();
 
     let decryptor = Decryptor::using(key);
 
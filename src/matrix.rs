@@ -0,0 +1,240 @@
+use core::ops;
+
+use ff::FF;
+
+// A square matrix over GF(2^8), for the linear diffusion layers of AES-like
+// ciphers.  Bytes go in as a column vector, so row i of the matrix gives
+// output byte i
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix<const N: usize> {
+	rows: [[FF; N]; N]
+}
+
+impl<const N: usize> Matrix<N> {
+	pub const fn new(rows: [[u8; N]; N]) -> Matrix<N> {
+		let mut elements = [[FF::new(0); N]; N];
+		let mut i = 0;
+		while i < N {
+			let mut j = 0;
+			while j < N {
+				elements[i][j] = FF::new(rows[i][j]);
+				j += 1;
+			}
+			i += 1;
+		}
+
+		Matrix{ rows: elements }
+	}
+
+	pub fn identity() -> Matrix<N> {
+		let mut rows = [[FF::new(0); N]; N];
+		for i in 0..N {
+			rows[i][i] = FF::new(1);
+		}
+
+		Matrix{ rows }
+	}
+
+	pub fn get(&self, row: usize, col: usize) -> FF {
+		self.rows[row][col]
+	}
+
+	pub fn apply(&self, input: &[u8; N]) -> [u8; N] {
+		let mut output = [0; N];
+		for i in 0..N {
+			let mut sum = FF::new(0);
+			for j in 0..N {
+				sum += self.rows[i][j] * FF::new(input[j]);
+			}
+			output[i] = sum.value();
+		}
+
+		output
+	}
+
+	// By Gauss-Jordan elimination, or None if the matrix is singular
+	pub fn inverse(&self) -> Option<Matrix<N>> {
+		let mut rows = self.rows;
+		let mut inverse = Matrix::identity().rows;
+
+		for col in 0..N {
+			let pivot = (col..N).find(|&row| !rows[row][col].is_zero())?;
+			rows.swap(col, pivot);
+			inverse.swap(col, pivot);
+
+			let scale = FF::new(1) / rows[col][col];
+			for j in 0..N {
+				rows[col][j] *= scale;
+				inverse[col][j] *= scale;
+			}
+
+			for row in 0..N {
+				let factor = rows[row][col];
+				if row == col || factor.is_zero() { continue; }
+
+				for j in 0..N {
+					let (above, this) = (rows[col][j], inverse[col][j]);
+					rows[row][j] -= factor * above;
+					inverse[row][j] -= factor * this;
+				}
+			}
+		}
+
+		Some(Matrix{ rows: inverse })
+	}
+
+	pub fn rank(&self) -> usize {
+		rank(self.rows, N, N)
+	}
+
+	pub fn is_invertible(&self) -> bool {
+		self.rank() == N
+	}
+
+	// The fewest non-zero bytes in an input and its output together, over
+	// every non-zero input.  It's at most N + 1.
+	//
+	// There's an input on the columns S whose output is zero on the rows T
+	// exactly when the submatrix on T and S doesn't have full column rank,
+	// and then the input and output weigh at most |S| + N - |T| between them.
+	// The least of those over every S and T is the branch number, which means
+	// checking 4^N submatrices rather than 256^N inputs
+	pub fn branch_number(&self) -> usize {
+		if N > 16 { panic!("Matrix too big to find the branch number of!"); }
+
+		let mut best = N + 1;
+		for columns in 1..1u32 << N {
+			for rows in 0..1u32 << N {
+				let weight = columns.count_ones() as usize + N - rows.count_ones() as usize;
+				if weight < best && self.submatrix_rank(rows, columns) < columns.count_ones() as usize {
+					best = weight;
+				}
+			}
+		}
+
+		best
+	}
+
+	// Maximum distance separable: every square submatrix is invertible, or
+	// equivalently the branch number is as high as it goes
+	pub fn is_mds(&self) -> bool {
+		self.branch_number() == N + 1
+	}
+
+	fn submatrix_rank(&self, rows: u32, columns: u32) -> usize {
+		let mut sub = [[FF::new(0); N]; N];
+		let (mut height, mut width) = (0, 0);
+
+		for i in (0..N).filter(|i| rows & 1 << i != 0) {
+			width = 0;
+			for j in (0..N).filter(|j| columns & 1 << j != 0) {
+				sub[height][width] = self.rows[i][j];
+				width += 1;
+			}
+			height += 1;
+		}
+
+		rank(sub, height, width)
+	}
+}
+
+// The rank of the top left height by width corner, by Gaussian elimination
+fn rank<const N: usize>(rows: [[FF; N]; N], height: usize, width: usize) -> usize {
+	let mut rows = rows;
+	let mut rank = 0;
+
+	for col in 0..width {
+		let pivot = match (rank..height).find(|&row| !rows[row][col].is_zero()) {
+			Some(pivot) => pivot,
+			None => continue
+		};
+		rows.swap(rank, pivot);
+
+		for row in rank+1..height {
+			let factor = rows[row][col] / rows[rank][col];
+			for j in col..width {
+				let above = rows[rank][j];
+				rows[row][j] -= factor * above;
+			}
+		}
+		rank += 1;
+	}
+
+	rank
+}
+
+impl<const N: usize> ops::Mul for Matrix<N> {
+	type Output = Matrix<N>;
+
+	fn mul(self, rhs: Matrix<N>) -> Matrix<N> {
+		let mut rows = [[FF::new(0); N]; N];
+		for i in 0..N {
+			for j in 0..N {
+				for k in 0..N {
+					rows[i][j] += self.rows[i][k] * rhs.rows[k][j];
+				}
+			}
+		}
+
+		Matrix{ rows }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use matrix::*;
+	use polynomial::{MIX_COLUMNS, INV_MIX_COLUMNS};
+
+	#[test]
+	fn test_apply() {
+		// The first column from FIPS-197 Appendix B round 1
+		assert_eq!([0x04, 0x66, 0x81, 0xe5], MIX_COLUMNS.matrix().apply(&[0xd4, 0xbf, 0x5d, 0x30]));
+		assert_eq!([0xd4, 0xbf, 0x5d, 0x30], INV_MIX_COLUMNS.matrix().apply(&[0x04, 0x66, 0x81, 0xe5]));
+	}
+
+	#[test]
+	fn test_inverse() {
+		let matrix = MIX_COLUMNS.matrix();
+		assert_eq!(Some(INV_MIX_COLUMNS.matrix()), matrix.inverse());
+		assert_eq!(Matrix::identity(), matrix * INV_MIX_COLUMNS.matrix());
+		assert_eq!(Some(Matrix::<4>::identity()), Matrix::identity().inverse());
+
+		// Needs a row swap to find a pivot
+		let swapped = Matrix::new([[0, 1], [1, 0]]);
+		assert_eq!(Some(swapped), swapped.inverse());
+
+		let singular = Matrix::new([[1, 2, 3], [2, 4, 6], [0, 0, 1]]);
+		assert_eq!(None, singular.inverse());
+		assert_eq!(2, singular.rank());
+		assert!(!singular.is_invertible());
+	}
+
+	#[test]
+	fn test_branch_number() {
+		assert_eq!(5, MIX_COLUMNS.matrix().branch_number());
+		assert!(MIX_COLUMNS.matrix().is_mds());
+		assert!(INV_MIX_COLUMNS.matrix().is_mds());
+
+		// A byte in gives a byte out
+		assert_eq!(2, Matrix::<4>::identity().branch_number());
+
+		// Invertible, but made of two 2 by 2 blocks, so a byte only spreads to
+		// its own half of the column
+		let weak = Matrix::new([
+			[0x01, 0x01, 0x00, 0x00],
+			[0x01, 0x02, 0x00, 0x00],
+			[0x00, 0x00, 0x01, 0x01],
+			[0x00, 0x00, 0x01, 0x02]
+		]);
+		assert!(weak.is_invertible());
+		assert_eq!(3, weak.branch_number());
+		assert!(!weak.is_mds());
+
+		// The all ones matrix sends two equal bytes to zero
+		assert_eq!(2, Matrix::new([[1; 4]; 4]).branch_number());
+
+		// Every non-zero 1 by 1 matrix is MDS
+		assert!(Matrix::new([[0x57]]).is_mds());
+		assert!(!Matrix::new([[0x00]]).is_mds());
+	}
+}
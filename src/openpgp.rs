@@ -0,0 +1,670 @@
+use std::error;
+use std::fmt;
+use std::result;
+
+use aes::Encryptor;
+use key::Key;
+use sha1;
+use sha1::Sha1;
+
+const TAG_SKESK: u8 = 3;
+const TAG_SED: u8 = 9;
+const TAG_SEIPD: u8 = 18;
+const TAG_MDC: u8 = 0xd3;
+
+const HASH_SHA1: u8 = 2;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+	Truncated,
+	InvalidHeader,
+	UnsupportedVersion(u8),
+	UnsupportedAlgorithm(u8),
+	UnsupportedS2k(u8),
+	UnsupportedHash(u8),
+	InvalidKeySize(usize),
+	QuickCheckFailed,
+	ModificationDetected
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
+		match *self {
+			Error::Truncated => write!(formatter, "packet is truncated"),
+			Error::InvalidHeader => write!(formatter, "invalid packet header"),
+			Error::UnsupportedVersion(v) => write!(formatter, "unsupported packet version {}", v),
+			Error::UnsupportedAlgorithm(a) => write!(formatter, "unsupported symmetric algorithm {}", a),
+			Error::UnsupportedS2k(s) => write!(formatter, "unsupported S2K specifier {}", s),
+			Error::UnsupportedHash(h) => write!(formatter, "unsupported hash algorithm {}", h),
+			Error::InvalidKeySize(n) => write!(formatter, "a {} byte key doesn't fit the algorithm", n),
+			Error::QuickCheckFailed => write!(formatter, "quick check failed, the key is probably wrong"),
+			Error::ModificationDetected => write!(formatter, "modification detection code did not match")
+		}
+	}
+}
+
+impl error::Error for Error {}
+
+// The OpenPGP flavour of CFB from RFC 4880 section 13.9.  Instead of an IV,
+// the plaintext is preceded by a block of random data whose last two bytes are
+// repeated, so that a wrong key can be spotted after decrypting 18 bytes.
+//
+// In the resync form (used by the legacy Symmetrically Encrypted Data packet)
+// the CFB register is reloaded from the ciphertext after those 18 bytes.  The
+// integrity protected packet uses plain CFB throughout
+pub struct OpenPgpCfb {
+	encryptor: Encryptor
+}
+
+impl OpenPgpCfb {
+	pub fn using(key: Key) -> OpenPgpCfb {
+		OpenPgpCfb{ encryptor: Encryptor::using(key) }
+	}
+
+	pub fn encrypt(&self, prefix: &[u8], input: &[u8], resync: bool) -> Vec<u8> {
+		let mut data = self.quick_check_prefix(prefix);
+		if resync {
+			let mut output = self.cfb(&[0; 16], &data, false);
+
+			let mut iv = [0; 16];
+			iv.copy_from_slice(&output[2..18]);
+			output.extend(self.cfb(&iv, input, false));
+
+			output
+		} else {
+			data.extend_from_slice(input);
+			self.cfb(&[0; 16], &data, false)
+		}
+	}
+
+	pub fn decrypt(&self, input: &[u8], resync: bool) -> result::Result<Vec<u8>, Error> {
+		let mut output = self.decrypt_with_prefix(input, resync)?;
+		Ok(output.split_off(18))
+	}
+
+	// Returns the 18 byte prefix along with the plaintext, because the
+	// modification detection code covers both
+	fn decrypt_with_prefix(&self, input: &[u8], resync: bool) -> result::Result<Vec<u8>, Error> {
+		if input.len() < 18 { return Err(Error::Truncated); }
+
+		let output = if resync {
+			let mut output = self.cfb(&[0; 16], &input[..18], true);
+
+			let mut iv = [0; 16];
+			iv.copy_from_slice(&input[2..18]);
+			output.extend(self.cfb(&iv, &input[18..], true));
+
+			output
+		} else {
+			self.cfb(&[0; 16], input, true)
+		};
+
+		if output[14..16] != output[16..18] {
+			return Err(Error::QuickCheckFailed);
+		}
+
+		Ok(output)
+	}
+
+	fn quick_check_prefix(&self, prefix: &[u8]) -> Vec<u8> {
+		if prefix.len() != 16 { panic!("OpenPGP CFB prefix must be 16 bytes!"); }
+
+		let mut data = prefix.to_vec();
+		data.extend_from_slice(&prefix[14..]);
+		data
+	}
+
+	fn cfb(&self, iv: &[u8; 16], input: &[u8], decrypt: bool) -> Vec<u8> {
+		let mut output = Vec::with_capacity(input.len());
+		let mut register = *iv;
+
+		for chunk in input.chunks(16) {
+			let keystream = self.encryptor.encrypt(&register, false);
+
+			for i in 0..chunk.len() {
+				let c = chunk[i] ^ keystream[i];
+				register[i] = if decrypt { chunk[i] } else { c };
+				output.push(c);
+			}
+		}
+
+		output
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymmetricAlgorithm {
+	Aes128,
+	Aes192,
+	Aes256
+}
+
+impl SymmetricAlgorithm {
+	pub fn from_id(id: u8) -> result::Result<SymmetricAlgorithm, Error> {
+		match id {
+			7 => Ok(SymmetricAlgorithm::Aes128),
+			8 => Ok(SymmetricAlgorithm::Aes192),
+			9 => Ok(SymmetricAlgorithm::Aes256),
+			_ => Err(Error::UnsupportedAlgorithm(id))
+		}
+	}
+
+	pub fn id(self) -> u8 {
+		match self {
+			SymmetricAlgorithm::Aes128 => 7,
+			SymmetricAlgorithm::Aes192 => 8,
+			SymmetricAlgorithm::Aes256 => 9
+		}
+	}
+
+	pub fn key_size_bytes(self) -> usize {
+		match self {
+			SymmetricAlgorithm::Aes128 => 16,
+			SymmetricAlgorithm::Aes192 => 24,
+			SymmetricAlgorithm::Aes256 => 32
+		}
+	}
+}
+
+// String-to-key specifiers from RFC 4880 section 3.7.1
+#[derive(Debug, Clone, PartialEq)]
+pub enum S2k {
+	Simple{ hash: u8 },
+	Salted{ hash: u8, salt: [u8; 8] },
+	IteratedSalted{ hash: u8, salt: [u8; 8], count: u8 }
+}
+
+impl S2k {
+	fn parse(input: &[u8]) -> result::Result<(S2k, usize), Error> {
+		if input.len() < 2 { return Err(Error::Truncated); }
+
+		let hash = input[1];
+		let salt = || -> result::Result<[u8; 8], Error> {
+			if input.len() < 10 { return Err(Error::Truncated); }
+			let mut salt = [0; 8];
+			salt.copy_from_slice(&input[2..10]);
+			Ok(salt)
+		};
+
+		match input[0] {
+			0 => Ok((S2k::Simple{hash}, 2)),
+			1 => Ok((S2k::Salted{hash, salt: salt()?}, 10)),
+			3 => {
+				let salt = salt()?;
+				if input.len() < 11 { return Err(Error::Truncated); }
+				Ok((S2k::IteratedSalted{hash, salt, count: input[10]}, 11))
+			},
+			s => Err(Error::UnsupportedS2k(s))
+		}
+	}
+
+	fn write(&self, output: &mut Vec<u8>) {
+		match *self {
+			S2k::Simple{hash} => output.extend_from_slice(&[0, hash]),
+			S2k::Salted{hash, salt} => {
+				output.extend_from_slice(&[1, hash]);
+				output.extend_from_slice(&salt);
+			},
+			S2k::IteratedSalted{hash, salt, count} => {
+				output.extend_from_slice(&[3, hash]);
+				output.extend_from_slice(&salt);
+				output.push(count);
+			}
+		}
+	}
+
+	// The number of bytes of salt and passphrase fed into the hash
+	pub fn iterations(&self) -> usize {
+		match *self {
+			S2k::IteratedSalted{count, ..} => (16 + (count as usize & 15)) << ((count >> 4) + 6),
+			_ => 0
+		}
+	}
+
+	// Only SHA-1 is available, which is what older implementations default to
+	pub fn derive(&self, passphrase: &[u8], key_size_bytes: usize) -> result::Result<Vec<u8>, Error> {
+		let (hash, salt) = match *self {
+			S2k::Simple{hash} => (hash, &[][..]),
+			S2k::Salted{hash, ref salt} => (hash, &salt[..]),
+			S2k::IteratedSalted{hash, ref salt, ..} => (hash, &salt[..])
+		};
+		if hash != HASH_SHA1 { return Err(Error::UnsupportedHash(hash)); }
+
+		let mut data = salt.to_vec();
+		data.extend_from_slice(passphrase);
+		let total = self.iterations().max(data.len());
+
+		// Each extra hash context is preloaded with one more zero byte
+		let mut key = Vec::with_capacity(key_size_bytes);
+		let mut preload = 0;
+		while key.len() < key_size_bytes {
+			let mut sha1 = Sha1::new();
+			sha1.update(&vec![0; preload]);
+
+			let mut remaining = total;
+			while remaining > 0 {
+				let take = remaining.min(data.len());
+				sha1.update(&data[..take]);
+				remaining -= take;
+			}
+
+			key.extend_from_slice(&sha1.finish());
+			preload += 1;
+		}
+
+		key.truncate(key_size_bytes);
+		Ok(key)
+	}
+}
+
+// Symmetric-Key Encrypted Session Key packet (tag 3), version 4
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skesk {
+	pub algorithm: SymmetricAlgorithm,
+	pub s2k: S2k,
+	pub encrypted_session_key: Vec<u8>
+}
+
+impl Skesk {
+	fn parse(body: &[u8]) -> result::Result<Skesk, Error> {
+		if body.len() < 2 { return Err(Error::Truncated); }
+		if body[0] != 4 { return Err(Error::UnsupportedVersion(body[0])); }
+
+		let algorithm = SymmetricAlgorithm::from_id(body[1])?;
+		let (s2k, len) = S2k::parse(&body[2..])?;
+
+		Ok(Skesk{ algorithm, s2k, encrypted_session_key: body[2+len..].to_vec() })
+	}
+
+	// Works out the algorithm and key used for the encrypted data from the
+	// key derived from the passphrase.  Without an encrypted session key the
+	// derived key is used directly
+	pub fn session_key(&self, key: &[u8]) -> result::Result<(SymmetricAlgorithm, Vec<u8>), Error> {
+		if self.encrypted_session_key.is_empty() {
+			return Ok((self.algorithm, key.to_vec()));
+		}
+
+		// The derived key is for the packet's algorithm
+		if key.len() != self.algorithm.key_size_bytes() { return Err(Error::InvalidKeySize(key.len())); }
+
+		let cfb = OpenPgpCfb::using(Key::from_bytes(key));
+		let decrypted = cfb.cfb(&[0; 16], &self.encrypted_session_key, true);
+
+		let algorithm = SymmetricAlgorithm::from_id(decrypted[0])?;
+		if decrypted.len() != 1 + algorithm.key_size_bytes() { return Err(Error::Truncated); }
+
+		Ok((algorithm, decrypted[1..].to_vec()))
+	}
+}
+
+// Symmetrically Encrypted Data packet (tag 9).  This has no integrity
+// protection and uses the resync form of OpenPGP CFB
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sed {
+	pub data: Vec<u8>
+}
+
+impl Sed {
+	pub fn encrypt(key: Key, prefix: &[u8], plaintext: &[u8]) -> Sed {
+		Sed{ data: OpenPgpCfb::using(key).encrypt(prefix, plaintext, true) }
+	}
+
+	pub fn decrypt(&self, key: Key) -> result::Result<Vec<u8>, Error> {
+		OpenPgpCfb::using(key).decrypt(&self.data, true)
+	}
+}
+
+// Symmetrically Encrypted Integrity Protected Data packet (tag 18), version 1.
+// The plaintext is followed by a Modification Detection Code packet holding
+// the SHA-1 of everything before it, and no resync is done
+#[derive(Debug, Clone, PartialEq)]
+pub struct Seipd {
+	pub data: Vec<u8>
+}
+
+impl Seipd {
+	fn parse(body: &[u8]) -> result::Result<Seipd, Error> {
+		if body.is_empty() { return Err(Error::Truncated); }
+		if body[0] != 1 { return Err(Error::UnsupportedVersion(body[0])); }
+
+		Ok(Seipd{ data: body[1..].to_vec() })
+	}
+
+	pub fn encrypt(key: Key, prefix: &[u8], plaintext: &[u8]) -> Seipd {
+		let cfb = OpenPgpCfb::using(key);
+
+		let mut data = plaintext.to_vec();
+		data.extend_from_slice(&[TAG_MDC, 0x14]);
+
+		let mut hashed = cfb.quick_check_prefix(prefix);
+		hashed.extend_from_slice(&data);
+		data.extend_from_slice(&sha1::digest(&hashed));
+
+		Seipd{ data: cfb.encrypt(prefix, &data, false) }
+	}
+
+	pub fn decrypt(&self, key: Key) -> result::Result<Vec<u8>, Error> {
+		let mut output = OpenPgpCfb::using(key).decrypt_with_prefix(&self.data, false)?;
+		if output.len() < 18 + 22 { return Err(Error::Truncated); }
+
+		let mdc = output.split_off(output.len() - 20);
+		let expected = sha1::digest(&output);
+
+		// Check every byte so the comparison doesn't leak where it failed
+		let mut diff = (output[output.len() - 2] ^ TAG_MDC) | (output[output.len() - 1] ^ 0x14);
+		for i in 0..20 {
+			diff |= expected[i] ^ mdc[i];
+		}
+		if diff != 0 { return Err(Error::ModificationDetected); }
+
+		output.truncate(output.len() - 2);
+		Ok(output.split_off(18))
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Packet {
+	Skesk(Skesk),
+	Sed(Sed),
+	Seipd(Seipd),
+	Other{ tag: u8, body: Vec<u8> }
+}
+
+impl Packet {
+	pub fn tag(&self) -> u8 {
+		match *self {
+			Packet::Skesk(_) => TAG_SKESK,
+			Packet::Sed(_) => TAG_SED,
+			Packet::Seipd(_) => TAG_SEIPD,
+			Packet::Other{tag, ..} => tag
+		}
+	}
+
+	// Always writes a new format header with a definite length
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut body = Vec::new();
+		match *self {
+			Packet::Skesk(ref skesk) => {
+				body.extend_from_slice(&[4, skesk.algorithm.id()]);
+				skesk.s2k.write(&mut body);
+				body.extend_from_slice(&skesk.encrypted_session_key);
+			},
+			Packet::Sed(ref sed) => body.extend_from_slice(&sed.data),
+			Packet::Seipd(ref seipd) => {
+				body.push(1);
+				body.extend_from_slice(&seipd.data);
+			},
+			Packet::Other{body: ref other, ..} => body.extend_from_slice(other)
+		}
+
+		let mut output = vec![0xc0 | self.tag()];
+		let len = body.len();
+		if len < 192 {
+			output.push(len as u8);
+		} else if len < 8384 {
+			output.push((((len - 192) >> 8) + 192) as u8);
+			output.push((len - 192) as u8);
+		} else {
+			output.push(0xff);
+			output.extend_from_slice(&(len as u32).to_be_bytes());
+		}
+
+		output.extend(body);
+		output
+	}
+}
+
+pub fn parse_packets(input: &[u8]) -> result::Result<Vec<Packet>, Error> {
+	let mut packets = Vec::new();
+	let mut input = input;
+
+	while !input.is_empty() {
+		let (tag, body, len) = read_packet(input)?;
+		input = &input[len..];
+
+		packets.push(match tag {
+			TAG_SKESK => Packet::Skesk(Skesk::parse(&body)?),
+			TAG_SED => Packet::Sed(Sed{ data: body }),
+			TAG_SEIPD => Packet::Seipd(Seipd::parse(&body)?),
+			_ => Packet::Other{tag, body}
+		});
+	}
+
+	Ok(packets)
+}
+
+// Returns the tag, the body (with any partial lengths joined up) and the
+// number of bytes of input consumed
+fn read_packet(input: &[u8]) -> result::Result<(u8, Vec<u8>, usize), Error> {
+	let ctb = input[0];
+	if ctb & 0x80 == 0 { return Err(Error::InvalidHeader); }
+
+	if ctb & 0x40 == 0 {
+		let tag = (ctb >> 2) & 0x0f;
+		let (len, header) = match ctb & 0x03 {
+			0 => (read_be(input, 1, 1)?, 2),
+			1 => (read_be(input, 1, 2)?, 3),
+			2 => (read_be(input, 1, 4)?, 5),
+			_ => (input.len() - 1, 1)
+		};
+
+		let body = input.get(header..header + len).ok_or(Error::Truncated)?;
+		return Ok((tag, body.to_vec(), header + len));
+	}
+
+	let tag = ctb & 0x3f;
+	let mut body = Vec::new();
+	let mut pos = 1;
+
+	loop {
+		let first = *input.get(pos).ok_or(Error::Truncated)? as usize;
+		let (len, partial) = match first {
+			0..=191 => { pos += 1; (first, false) },
+			192..=223 => {
+				let second = read_be(input, pos + 1, 1)?;
+				pos += 2;
+				(((first - 192) << 8) + second + 192, false)
+			},
+			255 => {
+				let len = read_be(input, pos + 1, 4)?;
+				pos += 5;
+				(len, false)
+			},
+			_ => { pos += 1; (1 << (first & 0x1f), true) }
+		};
+
+		body.extend_from_slice(input.get(pos..pos + len).ok_or(Error::Truncated)?);
+		pos += len;
+
+		if !partial { break; }
+	}
+
+	Ok((tag, body, pos))
+}
+
+fn read_be(input: &[u8], pos: usize, len: usize) -> result::Result<usize, Error> {
+	let bytes = input.get(pos..pos + len).ok_or(Error::Truncated)?;
+	Ok(bytes.iter().fold(0, |acc, &b| (acc << 8) | b as usize))
+}
+
+#[cfg(test)]
+mod tests {
+	use openpgp::*;
+
+	// Both messages were produced by GnuPG 2.2 encrypting "Hello, OpenPGP!\n"
+	// with the passphrase "hunter2", the first with --rfc4880 and the second
+	// with --rfc2440 to get the legacy packet without integrity protection
+	const SEIPD_MESSAGE: [u8; 89] = [
+		0x8c, 0x0d, 0x04, 0x07, 0x03, 0x02, 0x96, 0x53, 0xfe, 0x80, 0x80, 0xa8, 0x85, 0x9e, 0x60, 0xd2,
+		0x48, 0x01, 0x69, 0x54, 0x65, 0x0a, 0xa5, 0xd0, 0x5e, 0xec, 0x5d, 0xa4, 0xb5, 0xc7, 0xfa, 0x85,
+		0xbe, 0x89, 0xad, 0x27, 0xd0, 0x15, 0x0e, 0xa1, 0xe0, 0x2f, 0x7b, 0xa4, 0xc5, 0xee, 0x8a, 0xfb,
+		0xe1, 0xf1, 0xa1, 0xf7, 0x27, 0x52, 0x60, 0x33, 0xc7, 0x84, 0xc4, 0x4b, 0x8e, 0x52, 0x78, 0xf2,
+		0x89, 0x4d, 0x04, 0x57, 0x4c, 0x23, 0xcb, 0x2f, 0xbd, 0x40, 0xb4, 0x67, 0x6d, 0x23, 0x15, 0xc2,
+		0x53, 0xbc, 0x7b, 0xcf, 0x02, 0x39, 0xf6, 0x80, 0xfd
+	];
+
+	const SED_MESSAGE: [u8; 66] = [
+		0x8c, 0x0d, 0x04, 0x07, 0x03, 0x02, 0x1b, 0x94, 0x4e, 0x8e, 0xd5, 0x56, 0xbc, 0x8f, 0x60, 0xa4,
+		0x31, 0x8a, 0x2e, 0x73, 0x64, 0x4a, 0x49, 0x34, 0x41, 0x94, 0x61, 0xbe, 0x13, 0xae, 0x7d, 0xa1,
+		0x42, 0x23, 0x48, 0x21, 0x4f, 0x70, 0x54, 0xed, 0x55, 0x95, 0xa8, 0x78, 0x61, 0xc4, 0x81, 0xbb,
+		0x6f, 0x47, 0x1f, 0x6e, 0x69, 0x2c, 0xb8, 0x4f, 0x85, 0x1b, 0x53, 0xf3, 0x0e, 0x50, 0x73, 0x70,
+		0xb3, 0x2c
+	];
+
+	fn literal_text(plaintext: &[u8]) -> Vec<u8> {
+		match parse_packets(plaintext).unwrap()[0] {
+			Packet::Other{tag: 11, ref body} => {
+				// Skip the format, file name and date
+				let name_len = body[1] as usize;
+				body[2 + name_len + 4..].to_vec()
+			},
+			ref p => panic!("Expected a literal data packet, got {:?}", p)
+		}
+	}
+
+	#[test]
+	fn test_parse_skesk() {
+		let packets = parse_packets(&SEIPD_MESSAGE).unwrap();
+		assert_eq!(2, packets.len());
+
+		assert_eq!(Packet::Skesk(Skesk{
+			algorithm: SymmetricAlgorithm::Aes128,
+			s2k: S2k::IteratedSalted{
+				hash: 2,
+				salt: [0x96, 0x53, 0xfe, 0x80, 0x80, 0xa8, 0x85, 0x9e],
+				count: 96
+			},
+			encrypted_session_key: vec![]
+		}), packets[0]);
+		assert_eq!(18, packets[1].tag());
+	}
+
+	#[test]
+	fn test_s2k_derive() {
+		let s2k = S2k::IteratedSalted{
+			hash: 2,
+			salt: [0x96, 0x53, 0xfe, 0x80, 0x80, 0xa8, 0x85, 0x9e],
+			count: 96
+		};
+		assert_eq!(65536, s2k.iterations());
+
+		// The session key reported by gpg --show-session-key
+		let expected = [
+			0xd0, 0x77, 0x46, 0x17, 0x5a, 0x4c, 0x2c, 0x7e, 0x40, 0x4a, 0x32, 0xfb, 0x00, 0x05, 0xb6, 0x2c
+		];
+		assert_eq!(expected, &s2k.derive(b"hunter2", 16).unwrap()[..]);
+		assert_eq!(Err(Error::UnsupportedHash(8)), S2k::Simple{hash: 8}.derive(b"hunter2", 16));
+	}
+
+	#[test]
+	fn test_decrypt_seipd() {
+		let packets = parse_packets(&SEIPD_MESSAGE).unwrap();
+		let (skesk, seipd) = match (&packets[0], &packets[1]) {
+			(Packet::Skesk(skesk), Packet::Seipd(seipd)) => (skesk, seipd),
+			_ => panic!("Unexpected packets")
+		};
+
+		let key = skesk.s2k.derive(b"hunter2", skesk.algorithm.key_size_bytes()).unwrap();
+		let (_, session_key) = skesk.session_key(&key).unwrap();
+
+		let plaintext = seipd.decrypt(Key::from_bytes(&session_key)).unwrap();
+		assert_eq!(b"Hello, OpenPGP!\n", &literal_text(&plaintext)[..]);
+	}
+
+	#[test]
+	fn test_decrypt_sed() {
+		let packets = parse_packets(&SED_MESSAGE).unwrap();
+		let sed = match packets[1] {
+			Packet::Sed(ref sed) => sed,
+			_ => panic!("Unexpected packet")
+		};
+
+		let session_key = [
+			0x98, 0xe8, 0x27, 0xb1, 0x13, 0x5c, 0x20, 0x24, 0x57, 0x7e, 0x1e, 0x29, 0x90, 0x25, 0x82, 0xd3
+		];
+
+		let plaintext = sed.decrypt(Key::from_bytes(&session_key)).unwrap();
+		assert_eq!(b"Hello, OpenPGP!\n", &literal_text(&plaintext)[..]);
+
+		// Without the resync the data after the prefix comes out garbled
+		let garbled = OpenPgpCfb::using(Key::from_bytes(&session_key)).decrypt(&sed.data, false).unwrap();
+		assert_ne!(plaintext, garbled);
+	}
+
+	#[test]
+	fn test_wrong_key() {
+		let packets = parse_packets(&SEIPD_MESSAGE).unwrap();
+		let seipd = match packets[1] {
+			Packet::Seipd(ref seipd) => seipd,
+			_ => panic!("Unexpected packet")
+		};
+
+		assert_eq!(Err(Error::QuickCheckFailed), seipd.decrypt(Key::from_bytes(&[0; 16])));
+	}
+
+	#[test]
+	fn test_round_trip() {
+		let key = Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f, 0x10111213, 0x14151617, 0x18191a1b, 0x1c1d1e1f]);
+		let prefix = [
+			0x1f, 0x2e, 0x3d, 0x4c, 0x5b, 0x6a, 0x79, 0x88,
+			0x97, 0xa6, 0xb5, 0xc4, 0xd3, 0xe2, 0xf1, 0x00
+		];
+		let plaintext = [0xab; 300];
+
+		let seipd = Packet::Seipd(Seipd::encrypt(key.clone(), &prefix, &plaintext));
+		let sed = Packet::Sed(Sed::encrypt(key.clone(), &prefix, &plaintext));
+
+		let mut message = seipd.to_bytes();
+		message.extend(sed.to_bytes());
+		let packets = parse_packets(&message).unwrap();
+		assert_eq!(vec![seipd, sed], packets);
+
+		match (&packets[0], &packets[1]) {
+			(Packet::Seipd(seipd), Packet::Sed(sed)) => {
+				assert_eq!(&plaintext[..], &seipd.decrypt(key.clone()).unwrap()[..]);
+				assert_eq!(&plaintext[..], &sed.decrypt(key.clone()).unwrap()[..]);
+
+				let mut tampered = seipd.clone();
+				tampered.data[100] ^= 0x01;
+				assert_eq!(Err(Error::ModificationDetected), tampered.decrypt(key));
+			},
+			_ => panic!("Unexpected packets")
+		}
+	}
+
+	#[test]
+	fn test_encrypted_session_key() {
+		let key = [0x42; 16];
+		let session_key = [0x17; 32];
+
+		let mut data = vec![9];
+		data.extend_from_slice(&session_key);
+		let cfb = OpenPgpCfb::using(Key::from_bytes(&key));
+
+		let skesk = Skesk{
+			algorithm: SymmetricAlgorithm::Aes128,
+			s2k: S2k::Salted{hash: 2, salt: [1, 2, 3, 4, 5, 6, 7, 8]},
+			encrypted_session_key: cfb.cfb(&[0; 16], &data, false)
+		};
+
+		let packet = Packet::Skesk(skesk.clone());
+		assert_eq!(vec![packet.clone()], parse_packets(&packet.to_bytes()).unwrap());
+		assert_eq!((SymmetricAlgorithm::Aes256, session_key.to_vec()), skesk.session_key(&key).unwrap());
+
+		// Key::from_bytes would panic on these
+		assert_eq!(Err(Error::InvalidKeySize(20)), skesk.session_key(&[0x42; 20]));
+		assert_eq!(Err(Error::InvalidKeySize(28)), skesk.session_key(&[0x42; 28]));
+		assert_eq!(Err(Error::InvalidKeySize(32)), skesk.session_key(&[0x42; 32]));
+	}
+
+	#[test]
+	fn test_partial_lengths() {
+		// Literal data split into partial bodies of 2 and 1 bytes, then a
+		// final 2 byte body
+		let input = [0xcb, 0xe1, 0x01, 0x02, 0xe0, 0x03, 0x02, 0x04, 0x05];
+		assert_eq!(vec![Packet::Other{tag: 11, body: vec![1, 2, 3, 4, 5]}], parse_packets(&input).unwrap());
+
+		assert_eq!(Err(Error::Truncated), parse_packets(&input[..8]));
+		assert_eq!(Err(Error::InvalidHeader), parse_packets(&[0x3f, 0x00]));
+	}
+}
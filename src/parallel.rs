@@ -0,0 +1,207 @@
+use std::thread;
+
+use aes::{Encryptor, Decryptor};
+use ctr::Ctr;
+use gcm::Gcm;
+use xts::Xts;
+
+// Bulk encryption with the modes whose blocks don't depend on each other,
+// split across several threads.  The threads all share the same read-only
+// cipher, and each piece is done with the same code as the sequential path
+// starting partway into the message, so the output is exactly the same
+pub struct Parallel {
+	threads: usize
+}
+
+// Below this many blocks a thread costs more to start than it saves
+const MIN_BLOCKS_PER_THREAD: usize = 1024;
+
+impl Parallel {
+	pub fn new(threads: usize) -> Parallel {
+		if threads == 0 { panic!("Need at least one thread!"); }
+
+		Parallel{ threads }
+	}
+
+	// One thread for each CPU we can run on
+	pub fn available() -> Parallel {
+		Parallel::new(thread::available_parallelism().map(|n| n.get()).unwrap_or(1))
+	}
+
+	pub fn threads(&self) -> usize {
+		self.threads
+	}
+
+	pub fn ecb_encrypt(&self, encryptor: &Encryptor, input: &[u8]) -> Vec<u8> {
+		self.map(input, |_, piece| encryptor.encrypt_blocks(piece)).concat()
+	}
+
+	pub fn ecb_decrypt(&self, decryptor: &Decryptor, input: &[u8]) -> Vec<u8> {
+		self.map(input, |_, piece| decryptor.decrypt_blocks(piece)).concat()
+	}
+
+	pub fn ctr(&self, ctr: &Ctr, iv: &[u8], input: &[u8]) -> Vec<u8> {
+		self.map(input, |block, piece| ctr.apply_from(iv, block as u128, piece)).concat()
+	}
+
+	pub fn xts_encrypt(&self, xts: &Xts, sector: u128, input: &[u8]) -> Vec<u8> {
+		self.map(input, |block, piece| xts.encrypt_from(sector, block as u64, piece)).concat()
+	}
+
+	pub fn xts_decrypt(&self, xts: &Xts, sector: u128, input: &[u8]) -> Vec<u8> {
+		self.map(input, |block, piece| xts.decrypt_from(sector, block as u64, piece)).concat()
+	}
+
+	// Each thread hashes its own piece of the ciphertext, and the hashes are
+	// then combined in order
+	pub fn gcm_encrypt(&self, gcm: &Gcm, iv: &[u8], aad: &[u8], plaintext: &[u8]) -> (Vec<u8>, [u8; 16]) {
+		let j0 = gcm.initial_counter(iv);
+
+		let pieces = self.map(plaintext, |block, piece| {
+			let ciphertext = gcm.apply_keystream(&j0, block as u32, piece);
+			let mut ghash = gcm.ghash();
+			ghash.update(&ciphertext);
+			(ciphertext, ghash)
+		});
+
+		let mut ciphertext = Vec::with_capacity(plaintext.len());
+		let mut ghash = gcm.ghash();
+		for (piece, hash) in pieces {
+			ciphertext.extend(piece);
+			ghash.append(&hash);
+		}
+
+		let tag = gcm.tag(&j0, aad, &ghash, ciphertext.len());
+		(ciphertext, tag)
+	}
+
+	pub fn gcm_decrypt(&self, gcm: &Gcm, iv: &[u8], aad: &[u8], ciphertext: &[u8], tag: &[u8]) -> Option<Vec<u8>> {
+		let j0 = gcm.initial_counter(iv);
+
+		let mut ghash = gcm.ghash();
+		for hash in self.map(ciphertext, |_, piece| { let mut ghash = gcm.ghash(); ghash.update(piece); ghash }) {
+			ghash.append(&hash);
+		}
+		if !gcm.verify(&j0, aad, &ghash, ciphertext.len(), tag) {
+			return None;
+		}
+
+		Some(self.map(ciphertext, |block, piece| gcm.apply_keystream(&j0, block as u32, piece)).concat())
+	}
+
+	// Runs f on consecutive pieces of the input, each on its own thread, with
+	// the index of the piece's first block.  Pieces start on block boundaries
+	// and a partial last block stays with at least one full one, which XTS
+	// needs for ciphertext stealing
+	fn map<T, F>(&self, input: &[u8], f: F) -> Vec<T>
+		where T: Send, F: Fn(usize, &[u8]) -> T + Sync
+	{
+		let full = input.len() / 16;
+		let per_thread = full.div_ceil(self.threads).max(MIN_BLOCKS_PER_THREAD);
+
+		let mut starts = vec![0];
+		while starts[starts.len() - 1] + per_thread < full {
+			starts.push(starts[starts.len() - 1] + per_thread);
+		}
+
+		if starts.len() == 1 {
+			return vec![f(0, input)];
+		}
+
+		let f = &f;
+		thread::scope(|scope| {
+			let workers: Vec<_> = starts.iter().enumerate().map(|(i, &start)| {
+				let end = starts.get(i + 1).map_or(input.len(), |next| next * 16);
+				let piece = &input[start*16..end];
+
+				scope.spawn(move || f(start, piece))
+			}).collect();
+
+			workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use parallel::*;
+	use key::Key;
+
+	fn key() -> Key {
+		Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f])
+	}
+
+	// Enough for four threads to get a piece each, with a partial block
+	fn input() -> Vec<u8> {
+		(0..4 * MIN_BLOCKS_PER_THREAD * 16 + 1000 + 5).map(|i| (i * 31 + i / 256) as u8).collect()
+	}
+
+	#[test]
+	fn test_map() {
+		let input = input();
+		let pieces = Parallel::new(4).map(&input, |start, piece| (start, piece.len()));
+
+		assert_eq!(4, pieces.len());
+		assert_eq!(input.len(), pieces.iter().map(|&(_, len)| len).sum());
+		for (i, &(start, _)) in pieces.iter().enumerate() {
+			assert_eq!(i * 1040, start);
+		}
+		assert!(pieces[3].1 > 16);
+
+		assert_eq!(1, Parallel::new(4).map(&input[..100], |_, _| ()).len());
+	}
+
+	#[test]
+	fn test_ecb() {
+		let input = input();
+		let input = &input[..input.len() / 16 * 16];
+		let encryptor = Encryptor::using(key());
+		let decryptor = Decryptor::using(key());
+
+		for threads in 1..5 {
+			let parallel = Parallel::new(threads);
+			let ciphertext = parallel.ecb_encrypt(&encryptor, input);
+			assert_eq!(encryptor.encrypt_blocks(input), ciphertext);
+			assert_eq!(input, &parallel.ecb_decrypt(&decryptor, &ciphertext)[..]);
+		}
+	}
+
+	#[test]
+	fn test_ctr() {
+		let input = input();
+		let ctr = Ctr::using(key());
+		let iv = [0xff; 16];
+
+		for threads in 1..5 {
+			assert_eq!(ctr.apply(&iv, &input), Parallel::new(threads).ctr(&ctr, &iv, &input));
+		}
+	}
+
+	#[test]
+	fn test_xts() {
+		let input = input();
+		let xts = Xts::using(key(), Key::new(&[0x10111213, 0x14151617, 0x18191a1b, 0x1c1d1e1f]));
+
+		for threads in 1..5 {
+			let parallel = Parallel::new(threads);
+			let ciphertext = parallel.xts_encrypt(&xts, 42, &input);
+			assert_eq!(xts.encrypt(42, &input), ciphertext);
+			assert_eq!(input, parallel.xts_decrypt(&xts, 42, &ciphertext));
+		}
+	}
+
+	#[test]
+	fn test_gcm() {
+		let input = input();
+		let gcm = Gcm::using(key());
+		let iv = [0x5a; 12];
+
+		let (ciphertext, tag) = gcm.encrypt(&iv, b"header", &input);
+		for threads in 1..5 {
+			let parallel = Parallel::new(threads);
+			assert_eq!((ciphertext.clone(), tag), parallel.gcm_encrypt(&gcm, &iv, b"header", &input));
+			assert_eq!(Some(input.clone()), parallel.gcm_decrypt(&gcm, &iv, b"header", &ciphertext, &tag));
+			assert_eq!(None, parallel.gcm_decrypt(&gcm, &iv, b"headed", &ciphertext, &tag));
+		}
+	}
+}
@@ -0,0 +1,300 @@
+use aes::Encryptor;
+use key::Key;
+use zeroize::zeroize;
+
+const MASK: u32 = 0x3ffffff;
+
+// Bits of r that must be cleared before use, as required by the Poly1305 spec
+const R_CLAMP: u128 = 0x0ffffffc0ffffffc0ffffffc0fffffff;
+
+// Poly1305-AES as originally described by Bernstein: the per-message value s
+// is AES_k(nonce), so a single (k, r) pair can authenticate many messages as
+// long as every nonce is unique
+pub struct Poly1305 {
+	encryptor: Encryptor,
+	r: Element
+}
+
+impl Poly1305 {
+	pub fn using(key: Key, r: &[u8]) -> Poly1305 {
+		if key.get_size_bits() != 128 { panic!("Poly1305-AES requires a 128 bit key!"); }
+		if r.len() != 16 { panic!("Poly1305 r must be 16 bytes!"); }
+
+		let mut bytes = [0; 16];
+		bytes.copy_from_slice(r);
+
+		Poly1305{
+			encryptor: Encryptor::using(key),
+			r: Element::from_u128(u128::from_le_bytes(bytes) & R_CLAMP, 0)
+		}
+	}
+
+	pub fn authenticate(&self, nonce: &[u8], message: &[u8]) -> [u8; 16] {
+		if nonce.len() != 16 { panic!("Poly1305-AES nonce must be 16 bytes!"); }
+
+		let s = self.encryptor.encrypt(nonce, false);
+
+		let mut h = Element::from_u128(0, 0);
+		for chunk in message.chunks(16) {
+			h = h.add(&Element::from_chunk(chunk)).mul(&self.r);
+		}
+
+		h.finalize(&s)
+	}
+
+	pub fn verify(&self, nonce: &[u8], message: &[u8], tag: &[u8]) -> bool {
+		if tag.len() != 16 { return false; }
+
+		let expected = self.authenticate(nonce, message);
+
+		// Don't short circuit so that the comparison takes the same time
+		// regardless of where the first difference is
+		let mut diff = 0;
+		for i in 0..16 {
+			diff |= expected[i] ^ tag[i];
+		}
+
+		diff == 0
+	}
+}
+
+impl Drop for Poly1305 {
+	fn drop(&mut self) {
+		zeroize(&mut self.r.limbs);
+	}
+}
+
+// An integer modulo 2^130 - 5, stored as five 26 bit limbs so that limb
+// products fit comfortably in a u64
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Element {
+	limbs: [u32; 5]
+}
+
+impl Element {
+	fn from_u128(low: u128, high: u32) -> Element {
+		Element{ limbs: [
+			(low as u32) & MASK,
+			((low >> 26) as u32) & MASK,
+			((low >> 52) as u32) & MASK,
+			((low >> 78) as u32) & MASK,
+			((low >> 104) as u32) | (high << 24)
+		]}
+	}
+
+	// Each 16 byte chunk of the message is read as a little endian number with
+	// a 1 bit appended just past its last byte
+	fn from_chunk(chunk: &[u8]) -> Element {
+		let mut bytes = [0; 17];
+		bytes[..chunk.len()].copy_from_slice(chunk);
+		bytes[chunk.len()] = 1;
+
+		let mut low = [0; 16];
+		low.copy_from_slice(&bytes[..16]);
+
+		Element::from_u128(u128::from_le_bytes(low), bytes[16] as u32)
+	}
+
+	fn add(&self, rhs: &Element) -> Element {
+		let mut limbs = [0; 5];
+
+		for i in 0..5 {
+			limbs[i] = self.limbs[i] + rhs.limbs[i];
+		}
+
+		Element{limbs}
+	}
+
+	fn mul(&self, rhs: &Element) -> Element {
+		let mut h = [0u64; 5];
+		let mut r = [0u64; 5];
+		for i in 0..5 {
+			h[i] = self.limbs[i] as u64;
+			r[i] = rhs.limbs[i] as u64;
+		}
+
+		// 2^130 = 5 (mod p), so anything that wraps past the top limb is
+		// folded back into the bottom multiplied by 5
+		let s = [0, r[1] * 5, r[2] * 5, r[3] * 5, r[4] * 5];
+
+		let d = [
+			h[0]*r[0] + h[1]*s[4] + h[2]*s[3] + h[3]*s[2] + h[4]*s[1],
+			h[0]*r[1] + h[1]*r[0] + h[2]*s[4] + h[3]*s[3] + h[4]*s[2],
+			h[0]*r[2] + h[1]*r[1] + h[2]*r[0] + h[3]*s[4] + h[4]*s[3],
+			h[0]*r[3] + h[1]*r[2] + h[2]*r[1] + h[3]*r[0] + h[4]*s[4],
+			h[0]*r[4] + h[1]*r[3] + h[2]*r[2] + h[3]*r[1] + h[4]*r[0]
+		];
+
+		let mut limbs = [0; 5];
+		let mut carry = 0;
+		for i in 0..5 {
+			let t = d[i] + carry;
+			limbs[i] = (t as u32) & MASK;
+			carry = t >> 26;
+		}
+
+		let t = limbs[0] as u64 + carry * 5;
+		limbs[0] = (t as u32) & MASK;
+		limbs[1] += (t >> 26) as u32;
+
+		Element{limbs}
+	}
+
+	// Fully reduces the accumulator modulo 2^130 - 5 and adds s modulo 2^128
+	fn finalize(&self, s: &[u8; 16]) -> [u8; 16] {
+		let mut h = self.limbs;
+
+		let mut carry = 0;
+		for i in 1..5 {
+			h[i] += carry;
+			carry = h[i] >> 26;
+			h[i] &= MASK;
+		}
+		h[0] += carry * 5;
+		carry = h[0] >> 26;
+		h[0] &= MASK;
+		h[1] += carry;
+
+		// g = h - p; if that didn't underflow then h was not fully reduced
+		let mut g = [0; 5];
+		carry = 5;
+		for i in 0..5 {
+			let t = h[i] + carry;
+			g[i] = t & MASK;
+			carry = t >> 26;
+		}
+		g[4] = g[4].wrapping_add(carry << 26).wrapping_sub(1 << 26);
+
+		// All ones if g is negative (keep h), all zeroes otherwise (take g)
+		let keep = 0u32.wrapping_sub(g[4] >> 31);
+		for i in 0..5 {
+			h[i] = (h[i] & keep) | (g[i] & !keep);
+		}
+
+		let h = (h[0] as u128)
+			| (h[1] as u128) << 26
+			| (h[2] as u128) << 52
+			| (h[3] as u128) << 78
+			| (h[4] as u128) << 104;
+
+		h.wrapping_add(u128::from_le_bytes(*s)).to_le_bytes()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use poly1305::*;
+
+	// Test vectors are from Appendix B of "The Poly1305-AES message-authentication code"
+	#[test]
+	fn test_poly1305_aes_vector1() {
+		let poly = Poly1305::using(Key::from_bytes(&[
+			0xec, 0x07, 0x4c, 0x83, 0x55, 0x80, 0x74, 0x17, 0x01, 0x42, 0x5b, 0x62, 0x32, 0x35, 0xad, 0xd6
+		]), &[
+			0x85, 0x1f, 0xc4, 0x0c, 0x34, 0x67, 0xac, 0x0b, 0xe0, 0x5c, 0xc2, 0x04, 0x04, 0xf3, 0xf7, 0x00
+		]);
+
+		let nonce = [
+			0xfb, 0x44, 0x73, 0x50, 0xc4, 0xe8, 0x68, 0xc5, 0x2a, 0xc3, 0x27, 0x5c, 0xf9, 0xd4, 0x32, 0x7e
+		];
+		let message = [
+			0xf3, 0xf6
+		];
+		let expected = [
+			0xf4, 0xc6, 0x33, 0xc3, 0x04, 0x4f, 0xc1, 0x45, 0xf8, 0x4f, 0x33, 0x5c, 0xb8, 0x19, 0x53, 0xde
+		];
+		assert_eq!(expected, poly.authenticate(&nonce, &message));
+	}
+
+	#[test]
+	fn test_poly1305_aes_vector2() {
+		let poly = Poly1305::using(Key::from_bytes(&[
+			0x75, 0xde, 0xaa, 0x25, 0xc0, 0x9f, 0x20, 0x8e, 0x1d, 0xc4, 0xce, 0x6b, 0x5c, 0xad, 0x3f, 0xbf
+		]), &[
+			0xa0, 0xf3, 0x08, 0x00, 0x00, 0xf4, 0x64, 0x00, 0xd0, 0xc7, 0xe9, 0x07, 0x6c, 0x83, 0x44, 0x03
+		]);
+
+		let nonce = [
+			0x61, 0xee, 0x09, 0x21, 0x8d, 0x29, 0xb0, 0xaa, 0xed, 0x7e, 0x15, 0x4a, 0x2c, 0x55, 0x09, 0xcc
+		];
+		let message: [u8; 0] = [];
+		let expected = [
+			0xdd, 0x3f, 0xab, 0x22, 0x51, 0xf1, 0x1a, 0xc7, 0x59, 0xf0, 0x88, 0x71, 0x29, 0xcc, 0x2e, 0xe7
+		];
+		assert_eq!(expected, poly.authenticate(&nonce, &message));
+	}
+
+	#[test]
+	fn test_poly1305_aes_vector3() {
+		let poly = Poly1305::using(Key::from_bytes(&[
+			0x6a, 0xcb, 0x5f, 0x61, 0xa7, 0x17, 0x6d, 0xd3, 0x20, 0xc5, 0xc1, 0xeb, 0x2e, 0xdc, 0xdc, 0x74
+		]), &[
+			0x48, 0x44, 0x3d, 0x0b, 0xb0, 0xd2, 0x11, 0x09, 0xc8, 0x9a, 0x10, 0x0b, 0x5c, 0xe2, 0xc2, 0x08
+		]);
+
+		let nonce = [
+			0xae, 0x21, 0x2a, 0x55, 0x39, 0x97, 0x29, 0x59, 0x5d, 0xea, 0x45, 0x8b, 0xc6, 0x21, 0xff, 0x0e
+		];
+		let message = [
+			0x66, 0x3c, 0xea, 0x19, 0x0f, 0xfb, 0x83, 0xd8, 0x95, 0x93, 0xf3, 0xf4, 0x76, 0xb6, 0xbc, 0x24,
+			0xd7, 0xe6, 0x79, 0x10, 0x7e, 0xa2, 0x6a, 0xdb, 0x8c, 0xaf, 0x66, 0x52, 0xd0, 0x65, 0x61, 0x36
+		];
+		let expected = [
+			0x0e, 0xe1, 0xc1, 0x6b, 0xb7, 0x3f, 0x0f, 0x4f, 0xd1, 0x98, 0x81, 0x75, 0x3c, 0x01, 0xcd, 0xbe
+		];
+		assert_eq!(expected, poly.authenticate(&nonce, &message));
+	}
+
+	#[test]
+	fn test_poly1305_aes_vector4() {
+		let poly = Poly1305::using(Key::from_bytes(&[
+			0xe1, 0xa5, 0x66, 0x8a, 0x4d, 0x5b, 0x66, 0xa5, 0xf6, 0x8c, 0xc5, 0x42, 0x4e, 0xd5, 0x98, 0x2d
+		]), &[
+			0x12, 0x97, 0x6a, 0x08, 0xc4, 0x42, 0x6d, 0x0c, 0xe8, 0xa8, 0x24, 0x07, 0xc4, 0xf4, 0x82, 0x07
+		]);
+
+		let nonce = [
+			0x9a, 0xe8, 0x31, 0xe7, 0x43, 0x97, 0x8d, 0x3a, 0x23, 0x52, 0x7c, 0x71, 0x28, 0x14, 0x9e, 0x3a
+		];
+		let message = [
+			0xab, 0x08, 0x12, 0x72, 0x4a, 0x7f, 0x1e, 0x34, 0x27, 0x42, 0xcb, 0xed, 0x37, 0x4d, 0x94, 0xd1,
+			0x36, 0xc6, 0xb8, 0x79, 0x5d, 0x45, 0xb3, 0x81, 0x98, 0x30, 0xf2, 0xc0, 0x44, 0x91, 0xfa, 0xf0,
+			0x99, 0x0c, 0x62, 0xe4, 0x8b, 0x80, 0x18, 0xb2, 0xc3, 0xe4, 0xa0, 0xfa, 0x31, 0x34, 0xcb, 0x67,
+			0xfa, 0x83, 0xe1, 0x58, 0xc9, 0x94, 0xd9, 0x61, 0xc4, 0xcb, 0x21, 0x09, 0x5c, 0x1b, 0xf9
+		];
+		let expected = [
+			0x51, 0x54, 0xad, 0x0d, 0x2c, 0xb2, 0x6e, 0x01, 0x27, 0x4f, 0xc5, 0x11, 0x48, 0x49, 0x1f, 0x1b
+		];
+		assert_eq!(expected, poly.authenticate(&nonce, &message));
+	}
+
+	#[test]
+	fn test_verify() {
+		let poly = Poly1305::using(Key::from_bytes(&[
+			0xec, 0x07, 0x4c, 0x83, 0x55, 0x80, 0x74, 0x17, 0x01, 0x42, 0x5b, 0x62, 0x32, 0x35, 0xad, 0xd6
+		]), &[
+			0x85, 0x1f, 0xc4, 0x0c, 0x34, 0x67, 0xac, 0x0b, 0xe0, 0x5c, 0xc2, 0x04, 0x04, 0xf3, 0xf7, 0x00
+		]);
+
+		let nonce = [
+			0xfb, 0x44, 0x73, 0x50, 0xc4, 0xe8, 0x68, 0xc5, 0x2a, 0xc3, 0x27, 0x5c, 0xf9, 0xd4, 0x32, 0x7e
+		];
+		let mut tag = [
+			0xf4, 0xc6, 0x33, 0xc3, 0x04, 0x4f, 0xc1, 0x45, 0xf8, 0x4f, 0x33, 0x5c, 0xb8, 0x19, 0x53, 0xde
+		];
+		assert!(poly.verify(&nonce, &[0xf3, 0xf6], &tag));
+		assert!(!poly.verify(&nonce, &[0xf3, 0xf7], &tag));
+
+		tag[15] ^= 1;
+		assert!(!poly.verify(&nonce, &[0xf3, 0xf6], &tag));
+	}
+
+	#[test]
+	fn test_finalize_reduces() {
+		// p + 3 should reduce to 3 before s is added
+		let p_plus_3 = Element{ limbs: [MASK - 1, MASK, MASK, MASK, MASK] };
+		let mut expected = [0; 16];
+		expected[0] = 3;
+		assert_eq!(expected, p_plus_3.finalize(&[0; 16]));
+	}
+}
@@ -0,0 +1,127 @@
+use core::ops;
+
+use ff::FF;
+use matrix::Matrix;
+
+// A polynomial of degree below 4 with coefficients in GF(2^8), multiplied
+// modulo x^4 + 1.  A column of the AES state is one, with the byte in row i
+// as the coefficient of x^i, and MixColumns multiplies it by a fixed one.
+// Since x^i mod x^4 + 1 just rotates, multiplying by a(x) is the circulant
+// matrix with a's coefficients down its first column
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Polynomial {
+	coeffs: [FF; 4]
+}
+
+// {03}x^3 + {01}x^2 + {01}x + {02} and its inverse,
+// {0b}x^3 + {0d}x^2 + {09}x + {0e}
+pub const MIX_COLUMNS: Polynomial = Polynomial::new([0x02, 0x01, 0x01, 0x03]);
+pub const INV_MIX_COLUMNS: Polynomial = Polynomial::new([0x0e, 0x09, 0x0d, 0x0b]);
+
+impl Polynomial {
+	// Lowest coefficient first
+	pub const fn new(coeffs: [u8; 4]) -> Polynomial {
+		Polynomial{ coeffs: [FF::new(coeffs[0]), FF::new(coeffs[1]), FF::new(coeffs[2]), FF::new(coeffs[3])] }
+	}
+
+	pub fn coeff(&self, i: usize) -> FF {
+		self.coeffs[i]
+	}
+
+	pub const fn matrix(&self) -> Matrix<4> {
+		let a = [self.coeffs[0].value(), self.coeffs[1].value(), self.coeffs[2].value(), self.coeffs[3].value()];
+		let mut rows = [[0; 4]; 4];
+		let mut i = 0;
+		while i < 4 {
+			let mut j = 0;
+			while j < 4 {
+				rows[i][j] = a[(i + 4 - j) % 4];
+				j += 1;
+			}
+			i += 1;
+		}
+
+		Matrix::new(rows)
+	}
+
+	// x^4 + 1 = (x + 1)^4 isn't irreducible, so only the polynomials that
+	// don't share that factor have inverses
+	pub fn inverse(&self) -> Option<Polynomial> {
+		let inverse = self.matrix().inverse()?;
+
+		Some(Polynomial{ coeffs: [inverse.get(0, 0), inverse.get(1, 0), inverse.get(2, 0), inverse.get(3, 0)] })
+	}
+}
+
+impl ops::Add for Polynomial {
+	type Output = Polynomial;
+
+	fn add(self, rhs: Polynomial) -> Polynomial {
+		let mut coeffs = self.coeffs;
+		for i in 0..4 {
+			coeffs[i] += rhs.coeffs[i];
+		}
+
+		Polynomial{ coeffs }
+	}
+}
+
+impl ops::Mul for Polynomial {
+	type Output = Polynomial;
+
+	fn mul(self, rhs: Polynomial) -> Polynomial {
+		let mut coeffs = [FF::new(0); 4];
+		for i in 0..4 {
+			for j in 0..4 {
+				coeffs[(i + j) % 4] += self.coeffs[i] * rhs.coeffs[j];
+			}
+		}
+
+		Polynomial{ coeffs }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use polynomial::*;
+
+	#[test]
+	fn test_mul() {
+		let one = Polynomial::new([1, 0, 0, 0]);
+		let x = Polynomial::new([0, 1, 0, 0]);
+
+		assert_eq!(one, MIX_COLUMNS * INV_MIX_COLUMNS);
+		assert_eq!(MIX_COLUMNS, MIX_COLUMNS * one);
+
+		// Multiplying by x rotates the coefficients, and x^4 = 1
+		assert_eq!(Polynomial::new([0x03, 0x02, 0x01, 0x01]), MIX_COLUMNS * x);
+		assert_eq!(one, x * x * x * x);
+	}
+
+	#[test]
+	fn test_add() {
+		assert_eq!(Polynomial::new([0x0c, 0x08, 0x0c, 0x08]), MIX_COLUMNS + INV_MIX_COLUMNS);
+		assert_eq!(Polynomial::new([0; 4]), MIX_COLUMNS + MIX_COLUMNS);
+	}
+
+	#[test]
+	fn test_inverse() {
+		assert_eq!(Some(INV_MIX_COLUMNS), MIX_COLUMNS.inverse());
+		assert_eq!(Some(MIX_COLUMNS), INV_MIX_COLUMNS.inverse());
+
+		// x + 1 divides x^4 + 1
+		assert_eq!(None, Polynomial::new([1, 1, 0, 0]).inverse());
+	}
+
+	// The matrix does the same as multiplying
+	#[test]
+	fn test_matrix() {
+		let column = Polynomial::new([0xd4, 0xbf, 0x5d, 0x30]);
+		let product = MIX_COLUMNS * column;
+
+		let output = MIX_COLUMNS.matrix().apply(&[0xd4, 0xbf, 0x5d, 0x30]);
+		for i in 0..4 {
+			assert_eq!(product.coeff(i).value(), output[i]);
+		}
+	}
+}
@@ -0,0 +1,95 @@
+use core::slice;
+
+use zeroize::zeroize;
+
+// POLYVAL from RFC 8452.  Field elements are little endian, and the product
+// of two elements is a * b * x^-128 modulo x^128 + x^127 + x^126 + x^121 + 1
+pub struct Polyval {
+	h: u128,
+	s: u128
+}
+
+// x^-1 of the reduction polynomial's low terms, folded back in when a set
+// bit falls off the bottom during a division by x
+const REDUCE: u128 = 1 << 127 | 1 << 126 | 1 << 125 | 1 << 120;
+
+impl Polyval {
+	pub fn new(h: &[u8]) -> Polyval {
+		if h.len() != 16 { panic!("POLYVAL key must be 16 bytes!"); }
+
+		let mut bytes = [0; 16];
+		bytes.copy_from_slice(h);
+
+		Polyval{ h: u128::from_le_bytes(bytes), s: 0 }
+	}
+
+	// A trailing partial block is padded with zeroes, so the input should be
+	// split at 16 byte boundaries if more is going to follow
+	pub fn update(&mut self, input: &[u8]) {
+		for chunk in input.chunks(16) {
+			let mut bytes = [0; 16];
+			bytes[..chunk.len()].copy_from_slice(chunk);
+
+			self.s = dot(self.s ^ u128::from_le_bytes(bytes), self.h);
+		}
+	}
+
+	pub fn finish(&self) -> [u8; 16] {
+		self.s.to_le_bytes()
+	}
+}
+
+impl Drop for Polyval {
+	fn drop(&mut self) {
+		zeroize(slice::from_mut(&mut self.h));
+		zeroize(slice::from_mut(&mut self.s));
+	}
+}
+
+// Montgomery style multiplication: adding in a for every set bit of b and
+// dividing by x each step leaves a * b * x^-128 after 128 steps.  b is the
+// hash key, so its bits and those of the running result are used as masks
+// rather than branched on
+fn dot(a: u128, b: u128) -> u128 {
+	let mut res = 0;
+
+	for i in 0..128 {
+		res ^= a & 0u128.wrapping_sub((b >> i) & 1);
+		res = (res >> 1) ^ (REDUCE & 0u128.wrapping_sub(res & 1));
+	}
+
+	res
+}
+
+#[cfg(test)]
+mod tests {
+	use polyval::*;
+
+	// Test vector is from Appendix A of RFC 8452
+	#[test]
+	fn test_polyval() {
+		let mut polyval = Polyval::new(&[
+			0x25, 0x62, 0x93, 0x47, 0x58, 0x92, 0x42, 0x76, 0x1d, 0x31, 0xf8, 0x26, 0xba, 0x4b, 0x75, 0x7b
+		]);
+
+		polyval.update(&[
+			0x4f, 0x4f, 0x95, 0x66, 0x8c, 0x83, 0xdf, 0xb6, 0x40, 0x17, 0x62, 0xbb, 0x2d, 0x01, 0xa2, 0x62,
+			0xd1, 0xa2, 0x4d, 0xdd, 0x27, 0x21, 0xd0, 0x06, 0xbb, 0xe4, 0x5f, 0x20, 0xd3, 0xc9, 0xf3, 0x62
+		]);
+
+		assert_eq!([
+			0xf7, 0xa3, 0xb4, 0x7b, 0x84, 0x61, 0x19, 0xfa, 0xe5, 0xb7, 0x86, 0x6c, 0xf5, 0xe5, 0xb7, 0x7e
+		], polyval.finish());
+	}
+
+	#[test]
+	fn test_dot() {
+		let a = 0x0123456789abcdeffedcba9876543210;
+		let b = 0x66e94bd4ef8a2c3b884cfa59ca342b2e;
+		let c = 0x80000000000000000000000000000001;
+
+		assert_eq!(dot(a, b), dot(b, a));
+		assert_eq!(dot(a ^ c, b), dot(a, b) ^ dot(c, b));
+		assert_eq!(0, dot(a, 0));
+	}
+}
@@ -0,0 +1,189 @@
+use buffer::Buffer;
+use key::{self, Key};
+use state::State;
+
+// AES with the parts cryptanalysis varies left open, for experiments on
+// reduced-round versions: how many rounds there are, whether the last one
+// leaves out MixColumns, and the round constants of the key schedule.  It's
+// built from the same State operations as the reference AES, so with the
+// standard parameters it is AES
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Params {
+	pub rounds: usize,
+	pub final_mix_columns: bool,
+	pub rcon: [u8; MAX_ROUNDS]
+}
+
+pub const MAX_ROUNDS: usize = 14;
+
+// x^(i-1) in GF(2^8) for Rcon[i], as FIPS-197 has it
+pub const RCON: [u8; MAX_ROUNDS] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36, 0x6c, 0xd8, 0xab, 0x4d];
+
+impl Params {
+	// What AES uses for the key
+	pub fn standard(key: &Key) -> Params {
+		Params{ rounds: key.get_size_bits() / 32 + 6, final_mix_columns: false, rcon: RCON }
+	}
+
+	// Standard apart from the number of rounds
+	pub fn reduced(key: &Key, rounds: usize) -> Params {
+		Params{ rounds, ..Params::standard(key) }
+	}
+}
+
+pub struct ReducedAes {
+	params: Params,
+	key_schedule: Buffer<u32, 60>
+}
+
+impl ReducedAes {
+	pub fn using(key: Key, params: Params) -> ReducedAes {
+		if params.rounds == 0 || params.rounds > MAX_ROUNDS { panic!("Invalid number of rounds!"); }
+
+		// Rcon[i/Nk] for word i never gets past Rcon[rounds]
+		let mut rcon = [0; MAX_ROUNDS];
+		for i in 0..MAX_ROUNDS {
+			rcon[i] = (params.rcon[i] as u32) << 24;
+		}
+		let key_schedule: Buffer<u32, 60> = key::expand_with(key.words(), 4, params.rounds, &rcon);
+
+		ReducedAes{ params, key_schedule: key_schedule.like(key.is_locked()) }
+	}
+
+	pub fn params(&self) -> &Params {
+		&self.params
+	}
+
+	// Attacks usually end by guessing at a round key, so they're out in the
+	// open here
+	pub fn round_key(&self, round: usize) -> [u32; 4] {
+		let words = &self.key_schedule[round*4..(round+1)*4];
+		[words[0], words[1], words[2], words[3]]
+	}
+
+	pub fn encrypt(&self, input: &[u8]) -> [u8; 16] {
+		if input.len() != 16 { panic!("Input must be 16 bytes!"); }
+
+		let nr = self.params.rounds;
+		let mut state = State::from_slice(input).add_round_key(&self.round_key(0));
+
+		for round in 1..=nr {
+			state = state.sub_bytes().shift_rows();
+			if round < nr || self.params.final_mix_columns {
+				state = state.mix_columns();
+			}
+			state = state.add_round_key(&self.round_key(round));
+		}
+
+		state.to_byte_array()
+	}
+
+	pub fn decrypt(&self, input: &[u8]) -> [u8; 16] {
+		if input.len() != 16 { panic!("Input must be 16 bytes!"); }
+
+		let nr = self.params.rounds;
+		let mut state = State::from_slice(input);
+
+		for round in (1..=nr).rev() {
+			state = state.add_round_key(&self.round_key(round));
+			if round < nr || self.params.final_mix_columns {
+				state = state.inv_mix_columns();
+			}
+			state = state.inv_shift_rows().inv_sub_bytes();
+		}
+
+		state.add_round_key(&self.round_key(0)).to_byte_array()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use reduced::*;
+	use aes::Encryptor;
+
+	fn fips_key() -> Key {
+		Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f])
+	}
+
+	const PLAINTEXT: [u8; 16] = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+
+	#[test]
+	fn test_standard() {
+		for &nk in [4, 6, 8].iter() {
+			let words: [u32; 8] = [0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f, 0x10111213, 0x14151617, 0x18191a1b, 0x1c1d1e1f];
+			let key = Key::new(&words[..nk]);
+			let aes = ReducedAes::using(key.clone(), Params::standard(&key));
+			assert_eq!(nk + 6, aes.params().rounds);
+
+			let expected = Encryptor::using(key).encrypt(&PLAINTEXT, false);
+			assert_eq!(expected, aes.encrypt(&PLAINTEXT));
+			assert_eq!(PLAINTEXT, aes.decrypt(&expected));
+		}
+	}
+
+	// With MixColumns left in, r rounds end where FIPS-197 Appendix C.1
+	// starts round r + 1
+	#[test]
+	fn test_reduced() {
+		let key = fips_key();
+		let starts = [
+			[0x89, 0xd8, 0x10, 0xe8, 0x85, 0x5a, 0xce, 0x68, 0x2d, 0x18, 0x43, 0xd8, 0xcb, 0x12, 0x8f, 0xe4],
+			[0x49, 0x15, 0x59, 0x8f, 0x55, 0xe5, 0xd7, 0xa0, 0xda, 0xca, 0x94, 0xfa, 0x1f, 0x0a, 0x63, 0xf7],
+			[0xfa, 0x63, 0x6a, 0x28, 0x25, 0xb3, 0x39, 0xc9, 0x40, 0x66, 0x8a, 0x31, 0x57, 0x24, 0x4d, 0x17]
+		];
+
+		for (i, start) in starts.iter().enumerate() {
+			let params = Params{ final_mix_columns: true, ..Params::reduced(&key, i + 1) };
+			let aes = ReducedAes::using(key.clone(), params);
+			assert_eq!(*start, aes.encrypt(&PLAINTEXT));
+			assert_eq!(PLAINTEXT, aes.decrypt(start));
+		}
+
+		for rounds in 1..=MAX_ROUNDS {
+			let aes = ReducedAes::using(key.clone(), Params::reduced(&key, rounds));
+			assert_eq!(PLAINTEXT, aes.decrypt(&aes.encrypt(&PLAINTEXT)));
+		}
+	}
+
+	#[test]
+	fn test_rcon() {
+		// FIPS-197 Appendix A.1, where w[4] = a0fafe17 takes in Rcon[1] = 01
+		let key = Key::new(&[0x2b7e1516, 0x28aed2a6, 0xabf71588, 0x09cf4f3c]);
+		assert_eq!([0xa0fafe17, 0x88542cb1, 0x23a33939, 0x2a6c7605], ReducedAes::using(key.clone(), Params::standard(&key)).round_key(1));
+
+		let params = Params{ rcon: [0; MAX_ROUNDS], ..Params::standard(&key) };
+		let aes = ReducedAes::using(key.clone(), params);
+		assert_eq!(0xa1fafe17, aes.round_key(1)[0]);
+		assert_eq!(PLAINTEXT, aes.decrypt(&aes.encrypt(&PLAINTEXT)));
+	}
+
+	// The integral property of three rounds: run the first byte through all
+	// 256 values with the rest fixed, and every byte of the outputs sums to
+	// zero.  A fourth round loses it
+	#[test]
+	fn test_integral() {
+		let key = fips_key();
+		let sums = |rounds| {
+			let aes = ReducedAes::using(key.clone(), Params::reduced(&key, rounds));
+			let mut sum = [0; 16];
+			for x in 0..=255 {
+				let mut input = PLAINTEXT;
+				input[0] = x;
+				for (s, b) in sum.iter_mut().zip(aes.encrypt(&input).iter()) {
+					*s ^= b;
+				}
+			}
+			sum
+		};
+
+		assert_eq!([0; 16], sums(3));
+		assert_ne!([0; 16], sums(4));
+	}
+
+	#[test]
+	#[should_panic]
+	fn test_too_many_rounds() {
+		let key = fips_key();
+		ReducedAes::using(key.clone(), Params::reduced(&key, 15));
+	}
+}
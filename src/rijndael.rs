@@ -0,0 +1,201 @@
+use buffer::Buffer;
+use key::{self, Key};
+use state::State;
+
+// Rijndael as it was submitted, before AES fixed the block at 128 bits.  The
+// block is NB words, anywhere from 4 to 8, and the cipher runs
+// max(Nk, Nb) + 6 rounds with an Nb word round key for each.  NB = 4 is AES.
+// There's only the reference implementation, none of the fast backends go
+// past 16 byte blocks, so this is for data that already uses a bigger block
+pub struct Rijndael<const NB: usize> {
+	key_schedule: Buffer<u32, 120>,
+	rounds: usize
+}
+
+pub type Rijndael128 = Rijndael<4>;
+pub type Rijndael160 = Rijndael<5>;
+pub type Rijndael192 = Rijndael<6>;
+pub type Rijndael224 = Rijndael<7>;
+pub type Rijndael256 = Rijndael<8>;
+
+impl<const NB: usize> Rijndael<NB> {
+	pub fn using(key: Key) -> Rijndael<NB> {
+		if !(4..=8).contains(&NB) { panic!("Invalid block size!"); }
+
+		let words = key.words();
+		Rijndael{
+			key_schedule: key::expand(words, NB).like(key.is_locked()),
+			rounds: words.len().max(NB) + 6
+		}
+	}
+
+	pub const fn block_size() -> usize {
+		4 * NB
+	}
+
+	pub fn rounds(&self) -> usize {
+		self.rounds
+	}
+
+	#[cfg(feature = "std")]
+	pub fn encrypt_blocks(&self, input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.encrypt_blocks_in_place(&mut output);
+		output
+	}
+
+	#[cfg(feature = "std")]
+	pub fn decrypt_blocks(&self, input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.decrypt_blocks_in_place(&mut output);
+		output
+	}
+
+	pub fn encrypt_blocks_in_place(&self, data: &mut [u8]) {
+		if !data.len().is_multiple_of(4 * NB) { panic!("Can only encrypt whole blocks!"); }
+
+		for block in data.chunks_mut(4 * NB) {
+			self.encrypt_block(block);
+		}
+	}
+
+	pub fn decrypt_blocks_in_place(&self, data: &mut [u8]) {
+		if !data.len().is_multiple_of(4 * NB) { panic!("Can only decrypt whole blocks!"); }
+
+		for block in data.chunks_mut(4 * NB) {
+			self.decrypt_block(block);
+		}
+	}
+
+	fn encrypt_block(&self, block: &mut [u8]) {
+		let nr = self.rounds;
+		let mut state: State<NB> = State::from_block(block);
+		state = state.add_round_key(self.round_key(0));
+
+		for round in 1..nr {
+			state = state.sub_bytes().shift_rows().mix_columns().add_round_key(self.round_key(round));
+		}
+
+		state = state.sub_bytes().shift_rows().add_round_key(self.round_key(nr));
+		state.copy_to_slice(block);
+	}
+
+	fn decrypt_block(&self, block: &mut [u8]) {
+		let nr = self.rounds;
+		let mut state: State<NB> = State::from_block(block);
+		state = state.add_round_key(self.round_key(nr));
+
+		for round in (1..nr).rev() {
+			state = state.inv_shift_rows().inv_sub_bytes().add_round_key(self.round_key(round)).inv_mix_columns();
+		}
+
+		state = state.inv_shift_rows().inv_sub_bytes().add_round_key(self.round_key(0));
+		state.copy_to_slice(block);
+	}
+
+	fn round_key(&self, round: usize) -> &[u32] {
+		&self.key_schedule[NB*round..NB*(round+1)]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rijndael::*;
+	use aes::Encryptor;
+
+	const KEY: [u32; 8] = [0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f, 0x10111213, 0x14151617, 0x18191a1b, 0x1c1d1e1f];
+
+	fn check<const NB: usize>(key_words: usize, expected: &[u8]) {
+		let rijndael: Rijndael<NB> = Rijndael::using(Key::new(&KEY[..key_words]));
+		assert_eq!(key_words.max(NB) + 6, rijndael.rounds());
+
+		let mut data = [0; 32];
+		for i in 0..4 * NB { data[i] = i as u8; }
+		let plaintext = data;
+
+		rijndael.encrypt_blocks_in_place(&mut data[..4 * NB]);
+		assert_eq!(expected, &data[..4 * NB]);
+		rijndael.decrypt_blocks_in_place(&mut data[..4 * NB]);
+		assert_eq!(plaintext, data);
+	}
+
+	// The plaintext is the bytes 0 to 4Nb - 1 and the key 0 to 4Nk - 1
+	#[test]
+	fn test_block_sizes() {
+		check::<5>(4, &[0x59, 0x57, 0x77, 0x23, 0xd4, 0x55, 0xf1, 0xd2, 0x1b, 0xa0, 0xbd, 0xdd, 0xd8, 0x37, 0xfb, 0xa9, 0x6b, 0x2c, 0xb4, 0x84]);
+		check::<5>(8, &[0xb7, 0x7a, 0xe6, 0xa3, 0x9a, 0x7e, 0xf1, 0xa5, 0x6a, 0x25, 0xdd, 0x20, 0xe8, 0x29, 0xa2, 0x39, 0x46, 0xd0, 0x2e, 0x4d]);
+		check::<6>(4, &[
+			0x54, 0x03, 0x06, 0x26, 0xe3, 0x66, 0xbb, 0xa5, 0x82, 0x7f, 0x46, 0xbe,
+			0x06, 0x0b, 0x53, 0xc7, 0x56, 0x68, 0xfc, 0x25, 0xfb, 0x1a, 0x60, 0x74
+		]);
+		check::<6>(6, &[
+			0x7a, 0x5a, 0x73, 0xc8, 0xfb, 0xdb, 0xb2, 0xaa, 0x68, 0x66, 0xcc, 0x95,
+			0x1b, 0x3e, 0x05, 0x9a, 0x63, 0x1c, 0xfe, 0xfc, 0x09, 0xc4, 0x24, 0xcf
+		]);
+		check::<7>(6, &[
+			0x96, 0xef, 0x82, 0x9b, 0x78, 0xb1, 0xd1, 0xb7, 0x2f, 0xe6, 0x2d, 0x0e, 0xb1, 0x0a,
+			0x4a, 0x7b, 0x53, 0xb7, 0x79, 0x48, 0x4e, 0x5f, 0x0b, 0xa8, 0xac, 0xe8, 0xc7, 0xe3
+		]);
+		check::<7>(8, &[
+			0xf6, 0x0c, 0xe1, 0x7f, 0x09, 0x29, 0x23, 0x37, 0x96, 0x9e, 0x26, 0xa2, 0x02, 0x61,
+			0x49, 0x60, 0x9b, 0x5c, 0x5d, 0xc6, 0x71, 0x3e, 0x4a, 0x76, 0x16, 0x03, 0x4e, 0x7a
+		]);
+	}
+
+	#[test]
+	fn test_rijndael256() {
+		check::<8>(4, &[
+			0x21, 0xc8, 0x9c, 0x4a, 0x7a, 0xe3, 0x7f, 0x18, 0x55, 0x97, 0x36, 0x2e, 0x5d, 0x20, 0x48, 0x5f,
+			0x61, 0x44, 0xaf, 0xed, 0x71, 0xbd, 0x4a, 0x79, 0x86, 0x88, 0x66, 0x2e, 0x6c, 0xde, 0x7d, 0xc4
+		]);
+		check::<8>(6, &[
+			0xd4, 0xcc, 0x0b, 0x07, 0x0e, 0xbe, 0xbd, 0x98, 0xff, 0xa1, 0xc2, 0x8e, 0x40, 0xbf, 0xfa, 0x5d,
+			0xb8, 0xbd, 0xb8, 0xfb, 0x5b, 0xfb, 0x6c, 0xcf, 0x23, 0xaf, 0x2c, 0x16, 0x08, 0x96, 0x7a, 0xcc
+		]);
+		check::<8>(8, &[
+			0x62, 0x3d, 0x2b, 0xd4, 0xca, 0x37, 0x96, 0xdc, 0x3d, 0x02, 0xec, 0xf2, 0xf3, 0x7f, 0xb6, 0x37,
+			0xfd, 0x3d, 0xa5, 0x85, 0x09, 0xce, 0xbb, 0x67, 0xab, 0x92, 0x65, 0xb0, 0x4d, 0xb5, 0x1e, 0x7d
+		]);
+		assert_eq!(32, Rijndael256::block_size());
+	}
+
+	// With 4 columns it's AES
+	#[test]
+	fn test_aes() {
+		for &nk in [4, 6, 8].iter() {
+			let key = Key::new(&KEY[..nk]);
+			let encryptor = Encryptor::using(key.clone());
+			let rijndael = Rijndael128::using(key);
+
+			let mut block = [0x5a; 16];
+			let expected = encryptor.encrypt(&block, false);
+			rijndael.encrypt_blocks_in_place(&mut block);
+			assert_eq!(expected, block);
+		}
+
+		check::<4>(4, &[0x0a, 0x94, 0x0b, 0xb5, 0x41, 0x6e, 0xf0, 0x45, 0xf1, 0xc3, 0x94, 0x58, 0xc6, 0x53, 0xea, 0x5a]);
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_blocks() {
+		let rijndael = Rijndael256::using(Key::new(&KEY));
+		let input: Vec<u8> = (0..96).collect();
+		let ciphertext = rijndael.encrypt_blocks(&input);
+
+		assert_eq!(&ciphertext[32..64], &rijndael.encrypt_blocks(&input[32..64])[..]);
+		assert_eq!(input, rijndael.decrypt_blocks(&ciphertext));
+	}
+
+	#[test]
+	#[should_panic]
+	fn test_partial_block() {
+		Rijndael256::using(Key::new(&KEY)).encrypt_blocks_in_place(&mut [0; 48]);
+	}
+
+	#[test]
+	#[should_panic]
+	fn test_invalid_block_size() {
+		Rijndael::<9>::using(Key::new(&KEY));
+	}
+}
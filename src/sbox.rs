@@ -1,6 +1,7 @@
+use ff::FF;
 use util;
 
-const S_BOX: [u8; 256] = [
+pub const S_BOX: [u8; 256] = [
     0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
     0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
     0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
@@ -19,7 +20,7 @@ const S_BOX: [u8; 256] = [
     0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16
 ];
 
-const INV_S_BOX: [u8; 256] = [
+pub const INV_S_BOX: [u8; 256] = [
     0x52, 0x09, 0x6a, 0xd5, 0x30, 0x36, 0xa5, 0x38, 0xbf, 0x40, 0xa3, 0x9e, 0x81, 0xf3, 0xd7, 0xfb,
     0x7c, 0xe3, 0x39, 0x82, 0x9b, 0x2f, 0xff, 0x87, 0x34, 0x8e, 0x43, 0x44, 0xc4, 0xde, 0xe9, 0xcb,
     0x54, 0x7b, 0x94, 0x32, 0xa6, 0xc2, 0x23, 0x3d, 0xee, 0x4c, 0x95, 0x0b, 0x42, 0xfa, 0xc3, 0x4e,
@@ -38,37 +39,200 @@ const INV_S_BOX: [u8; 256] = [
     0x17, 0x2b, 0x04, 0x7e, 0xba, 0x77, 0xd6, 0x26, 0xe1, 0x69, 0x14, 0x63, 0x55, 0x21, 0x0c, 0x7d
 ];
 
-fn sub_word_using(word: u32, from_box: &[u8; 256]) -> u32 {
-    let mut bytes = util::word_to_bytes(word);
-    bytes.0 = sub_byte_using(bytes.0, from_box);
-    bytes.1 = sub_byte_using(bytes.1, from_box);
-    bytes.2 = sub_byte_using(bytes.2, from_box);
-    bytes.3 = sub_byte_using(bytes.3, from_box);
-    util::bytes_to_word(bytes)
+// The literal tables have to be what the construction below gives, and
+// undo each other.  Checked when the crate is compiled
+const _: () = assert!(tables_match(&S_BOX, &generate(&AFFINE)));
+const _: () = assert!(tables_match(&INV_S_BOX, &invert(&S_BOX)));
+
+// An affine map on bytes as vectors over GF(2), b -> Mb + c.  Bit j of row i
+// of the matrix is set when input bit j goes into output bit i, with bit 0
+// the least significant
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Affine {
+    rows: [u8; 8],
+    constant: u8
+}
+
+// The map from FIPS-197 section 5.1.1, whose matrix is circulant: each output
+// bit is its own input bit plus the next four up, wrapping round
+pub const AFFINE: Affine = Affine::circulant(0x1f, 0x63);
+pub const INV_AFFINE: Affine = Affine::circulant(0x4a, 0x05);
+
+impl Affine {
+    pub const fn new(rows: [u8; 8], constant: u8) -> Affine {
+        Affine{rows, constant}
+    }
+
+    // The map b -> sum of b rotated left by k, for each bit k of the mask,
+    // plus the constant
+    pub const fn circulant(mask: u8, constant: u8) -> Affine {
+        let mut rows = [0; 8];
+        let mut i = 0;
+
+        while i < 8 {
+            let mut k = 0;
+            while k < 8 {
+                if mask & (1 << k) != 0 {
+                    rows[i] |= 1 << ((i + 8 - k) % 8);
+                }
+                k += 1;
+            }
+            i += 1;
+        }
+
+        Affine{rows, constant}
+    }
+
+    // Each output bit is the parity of the input bits its row picks out, so
+    // this takes the same time for every byte
+    pub const fn apply(&self, byte: u8) -> u8 {
+        let mut res = 0;
+        let mut i = 0;
+
+        while i < 8 {
+            res |= (((self.rows[i] & byte).count_ones() & 1) as u8) << i;
+            i += 1;
+        }
+
+        res ^ self.constant
+    }
+
+    // Gauss-Jordan elimination over GF(2), or None if the matrix is singular.
+    // If y = Mb + c then b = M^-1 y + M^-1 c
+    pub const fn inverse(&self) -> Option<Affine> {
+        let mut rows = self.rows;
+        let mut inverse = [1, 2, 4, 8, 16, 32, 64, 128];
+        let mut col = 0;
+
+        while col < 8 {
+            let mut pivot = col;
+            while pivot < 8 && rows[pivot] & (1 << col) == 0 {
+                pivot += 1;
+            }
+            if pivot == 8 { return None; }
+
+            let (row, inv) = (rows[pivot], inverse[pivot]);
+            rows[pivot] = rows[col];
+            inverse[pivot] = inverse[col];
+            rows[col] = row;
+            inverse[col] = inv;
+
+            let mut i = 0;
+            while i < 8 {
+                if i != col && rows[i] & (1 << col) != 0 {
+                    rows[i] ^= rows[col];
+                    inverse[i] ^= inverse[col];
+                }
+                i += 1;
+            }
+            col += 1;
+        }
+
+        let linear = Affine{rows: inverse, constant: 0};
+        Some(Affine{rows: inverse, constant: linear.apply(self.constant)})
+    }
+}
+
+// The S-box built as AES builds it, inversion in GF(2^8) followed by the
+// affine map.  Other maps give other S-boxes to study, which are permutations
+// as long as the map is invertible
+pub const fn generate(affine: &Affine) -> [u8; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        table[i] = affine.apply(FF::new(i as u8).inverse().value());
+        i += 1;
+    }
+
+    table
 }
 
+// The inverse of a table that's a permutation
+pub const fn invert(table: &[u8; 256]) -> [u8; 256] {
+    let mut inverse = [0; 256];
+    let mut i = 0;
 
-fn sub_byte_using(byte: u8, from_box: &[u8; 256]) -> u8 {
+    while i < 256 {
+        inverse[table[i] as usize] = i as u8;
+        i += 1;
+    }
+
+    inverse
+}
+
+pub const fn is_permutation(table: &[u8; 256]) -> bool {
+    let mut seen = [false; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        if seen[table[i] as usize] { return false; }
+        seen[table[i] as usize] = true;
+        i += 1;
+    }
+
+    true
+}
+
+const fn tables_match(a: &[u8; 256], b: &[u8; 256]) -> bool {
+    let mut i = 0;
+
+    while i < 256 {
+        if a[i] != b[i] { return false; }
+        i += 1;
+    }
+
+    true
+}
+
+#[cfg(not(feature = "computed-sbox"))]
+const fn sub_byte_using(byte: u8, from_box: &[u8; 256]) -> u8 {
     from_box[byte as usize]
 }
 
-pub fn sub_word(word: u32) -> u32 {
-    sub_word_using(word, &S_BOX)
+// The S-box is the inverse in GF(2^8) followed by an affine map, so it can be
+// computed rather than looked up.  That's slower, but needs no tables and
+// takes the same time for every byte, where a lookup depends on the cache
+pub const fn compute_sub_byte(byte: u8) -> u8 {
+    AFFINE.apply(FF::new(byte).inverse().value())
+}
+
+pub const fn compute_inv_sub_byte(byte: u8) -> u8 {
+    FF::new(INV_AFFINE.apply(byte)).inverse().value()
 }
 
-pub fn sub_byte(byte: u8) -> u8 {
+// Everything used by the key expansion is const, so that a schedule can be
+// worked out at compile time
+pub const fn sub_word(word: u32) -> u32 {
+    let bytes = util::word_to_bytes(word);
+    util::bytes_to_word((sub_byte(bytes.0), sub_byte(bytes.1), sub_byte(bytes.2), sub_byte(bytes.3)))
+}
+
+#[cfg(not(feature = "computed-sbox"))]
+pub const fn sub_byte(byte: u8) -> u8 {
     sub_byte_using(byte, &S_BOX)
 }
 
-pub fn inv_sub_word(word: u32) -> u32 {
-    sub_word_using(word, &INV_S_BOX)
+#[cfg(feature = "computed-sbox")]
+pub const fn sub_byte(byte: u8) -> u8 {
+    compute_sub_byte(byte)
 }
 
+pub const fn inv_sub_word(word: u32) -> u32 {
+    let bytes = util::word_to_bytes(word);
+    util::bytes_to_word((inv_sub_byte(bytes.0), inv_sub_byte(bytes.1), inv_sub_byte(bytes.2), inv_sub_byte(bytes.3)))
+}
 
-pub fn inv_sub_byte(byte: u8) -> u8 {
+#[cfg(not(feature = "computed-sbox"))]
+pub const fn inv_sub_byte(byte: u8) -> u8 {
     sub_byte_using(byte, &INV_S_BOX)
 }
 
+#[cfg(feature = "computed-sbox")]
+pub const fn inv_sub_byte(byte: u8) -> u8 {
+    compute_inv_sub_byte(byte)
+}
+
 #[cfg(test)]
 mod tests {
 	use sbox::*;
@@ -98,4 +262,68 @@ mod tests {
     fn test_inv_sub_byte() {
         assert_eq!(inv_sub_byte(0x11), 0xe3);
     }
+
+    #[test]
+    fn test_computed_sbox() {
+        for x in 0..=255u8 {
+            assert_eq!(S_BOX[x as usize], compute_sub_byte(x));
+            assert_eq!(INV_S_BOX[x as usize], compute_inv_sub_byte(x));
+        }
+    }
+
+    #[test]
+    fn test_generate() {
+        assert_eq!(&S_BOX[..], &generate(&AFFINE)[..]);
+        assert_eq!(&INV_S_BOX[..], &invert(&S_BOX)[..]);
+        assert!(is_permutation(&S_BOX));
+
+        for x in 0..=255u8 {
+            assert_eq!(x, INV_S_BOX[S_BOX[x as usize] as usize]);
+            assert_eq!(x, S_BOX[INV_S_BOX[x as usize] as usize]);
+        }
+    }
+
+    #[test]
+    fn test_affine() {
+        // The circulant form against the matrix written out in FIPS-197
+        assert_eq!(Affine::new([0xf1, 0xe3, 0xc7, 0x8f, 0x1f, 0x3e, 0x7c, 0xf8], 0x63), AFFINE);
+        assert_eq!(Some(INV_AFFINE), AFFINE.inverse());
+        assert_eq!(Some(AFFINE), INV_AFFINE.inverse());
+
+        for x in 0..=255u8 {
+            assert_eq!(x ^ x.rotate_left(1) ^ x.rotate_left(2) ^ x.rotate_left(3) ^ x.rotate_left(4) ^ 0x63, AFFINE.apply(x));
+            assert_eq!(x, INV_AFFINE.apply(AFFINE.apply(x)));
+        }
+
+        // A circulant map is invertible when its mask has an odd number of
+        // bits, since otherwise x + 1 divides both it and x^8 + 1
+        assert_eq!(None, Affine::circulant(0x11, 0x63).inverse());
+    }
+
+    #[test]
+    fn test_other_affine() {
+        // Without the constant, 0 is a fixed point
+        let unshifted = generate(&Affine::circulant(0x1f, 0x00));
+        assert_eq!(0x00, unshifted[0x00]);
+        for x in 0..=255 {
+            assert_eq!(S_BOX[x] ^ 0x63, unshifted[x]);
+        }
+
+        // The identity map leaves just the inversion
+        let inversion = generate(&Affine::circulant(0x01, 0x00));
+        for x in 1..=255u8 {
+            assert_eq!(1, (FF::new(x) * FF::new(inversion[x as usize])).value());
+        }
+
+        let other = Affine::circulant(0x0b, 0x1d);
+        let table = generate(&other);
+        assert!(is_permutation(&table));
+        let inverse = invert(&table);
+        for x in 0..=255u8 {
+            assert_eq!(x, inverse[table[x as usize] as usize]);
+            assert_eq!(inverse[x as usize], FF::new(other.inverse().unwrap().apply(x)).inverse().value());
+        }
+
+        assert!(!is_permutation(&generate(&Affine::circulant(0x11, 0x00))));
+    }
 }
\ No newline at end of file
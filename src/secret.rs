@@ -0,0 +1,175 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+use key::{Key, KeySchedule};
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+	// Only Linux is supported
+	Unsupported,
+	Map(i32),
+	Protect(i32),
+	DontDump(i32),
+	// Usually EPERM or ENOMEM, when RLIMIT_MEMLOCK doesn't allow it
+	Lock(i32)
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
+		let os = |errno: i32| io::Error::from_raw_os_error(errno);
+
+		match *self {
+			Error::Unsupported => write!(formatter, "locked memory is not supported on this platform"),
+			Error::Map(e) => write!(formatter, "couldn't map pages: {}", os(e)),
+			Error::Protect(e) => write!(formatter, "couldn't protect guard pages: {}", os(e)),
+			Error::DontDump(e) => write!(formatter, "couldn't exclude pages from core dumps: {}", os(e)),
+			Error::Lock(e) => write!(formatter, "couldn't lock pages: {}", os(e))
+		}
+	}
+}
+
+impl error::Error for Error {}
+
+// A key and its schedule kept in pages that are locked into RAM, so they are
+// never swapped to disk, and left out of core dumps, with guard pages either
+// side.  The Key handed out shares the locked pages, and anything made from
+// it, like an Encryptor and the round keys it derives, keeps its key material
+// locked too.  Encryptor::try_using and Decryptor::try_using say if that fails
+pub struct SecretKey {
+	key: Key,
+	schedule: KeySchedule,
+	error: Option<Error>
+}
+
+impl SecretKey {
+	// The key passed in is left as it is; drop it once this succeeds.  If the
+	// pages can't be locked the error says why, and nothing is kept
+	pub fn new(key: &Key) -> result::Result<SecretKey, Error> {
+		let schedule = key.expand_unlocked().lock()?;
+		let key = key.lock()?;
+
+		Ok(SecretKey{ key, schedule, error: None })
+	}
+
+	// For hosts that don't allow locking, like ones without CAP_IPC_LOCK or
+	// with a low RLIMIT_MEMLOCK.  If the pages can't be locked the key and its
+	// schedule are kept unlocked instead, though they're still wiped when
+	// dropped, and lock_error says why
+	pub fn new_or_unlocked(key: &Key) -> SecretKey {
+		match SecretKey::new(key) {
+			Ok(secret) => secret,
+			Err(error) => SecretKey{ key: key.clone(), schedule: key.expand_unlocked(), error: Some(error) }
+		}
+	}
+
+	pub fn lock_error(&self) -> Option<&Error> {
+		self.error.as_ref()
+	}
+
+	pub fn key(&self) -> Key {
+		self.key.clone()
+	}
+
+	pub fn schedule(&self) -> &KeySchedule {
+		&self.schedule
+	}
+}
+
+impl fmt::Debug for SecretKey {
+	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
+		formatter.debug_struct("SecretKey").field("bits", &self.key.get_size_bits()).finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use secret::*;
+	use aes::{Encryptor, Decryptor, Backend};
+
+	#[test]
+	fn test_secret_key() {
+		let key = Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f]);
+		let secret = match SecretKey::new(&key) {
+			Ok(secret) => secret,
+			// Not everywhere the tests run allows locking memory
+			Err(Error::Lock(_)) | Err(Error::Unsupported) => return,
+			Err(error) => panic!("{}", error)
+		};
+
+		assert!(secret.key().is_locked());
+		assert!(secret.schedule().is_locked());
+		assert_eq!(&key.create_schedule(), secret.schedule());
+
+		// Expanding it again would leave the schedule unlocked
+		let locked = secret.key();
+		assert!(std::panic::catch_unwind(|| locked.create_schedule()).is_err());
+		assert_eq!("SecretKey { bits: 128, .. }", format!("{:?}", secret));
+
+		let input = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+		let expected = [0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4, 0xc5, 0x5a];
+		for &backend in [Backend::TTable, Backend::AesNi, Backend::Bitsliced, Backend::VectorPermute].iter() {
+			let encryptor = Encryptor::with_backend(secret.key(), backend);
+			let decryptor = Decryptor::with_backend(secret.key(), backend);
+
+			assert_eq!(expected, encryptor.encrypt(&input, false));
+			assert_eq!(input, decryptor.decrypt(&expected, false));
+		}
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn test_lock_failure() {
+		use locked::FAIL_LOCKS;
+
+		let key = Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f]);
+		let secret = match SecretKey::new(&key) {
+			Ok(secret) => secret,
+			Err(Error::Lock(_)) => return,
+			Err(error) => panic!("{}", error)
+		};
+
+		// The key is locked already, so it's the round keys worked out from it
+		// that can't be
+		FAIL_LOCKS.with(|fail| fail.set(true));
+		for &backend in [Backend::TTable, Backend::AesNi, Backend::Bitsliced, Backend::VectorPermute].iter() {
+			assert_eq!(Some(Error::Lock(12)), Encryptor::try_with_backend(secret.key(), backend).err());
+			assert_eq!(Some(Error::Lock(12)), Decryptor::try_with_backend(secret.key(), backend).err());
+		}
+		assert_eq!(Err(Error::Lock(12)), SecretKey::new(&key).map(|_| ()));
+		FAIL_LOCKS.with(|fail| fail.set(false));
+
+		assert_eq!(None, SecretKey::new_or_unlocked(&key).lock_error());
+
+		assert!(Encryptor::try_with_backend(secret.key(), Backend::TTable).is_ok());
+		assert!(Decryptor::try_using(secret.key()).is_ok());
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn test_unlocked_fallback() {
+		use locked::FAIL_LOCKS;
+
+		let key = Key::new(&[0x00010203, 0x04050607, 0x08090a0b, 0x0c0d0e0f]);
+
+		FAIL_LOCKS.with(|fail| fail.set(true));
+		let secret = SecretKey::new_or_unlocked(&key);
+		FAIL_LOCKS.with(|fail| fail.set(false));
+
+		assert_eq!(Some(&Error::Lock(12)), secret.lock_error());
+		assert!(!secret.key().is_locked());
+		assert!(!secret.schedule().is_locked());
+		assert_eq!(&key.create_schedule(), secret.schedule());
+
+		let input = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+		let expected = [0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4, 0xc5, 0x5a];
+		let encryptor = Encryptor::try_using(secret.key()).unwrap();
+		assert_eq!(expected, encryptor.encrypt(&input, false));
+	}
+
+	#[test]
+	fn test_error() {
+		assert!(Error::Lock(1).to_string().starts_with("couldn't lock pages: "));
+	}
+}
@@ -0,0 +1,126 @@
+// SHA-1 is only here because OpenPGP needs it for its modification detection
+// code and its S2K key derivation.  It is not suitable for anything new
+const H: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+pub struct Sha1 {
+	h: [u32; 5],
+	buffer: Vec<u8>,
+	length: u64
+}
+
+impl Sha1 {
+	pub fn new() -> Sha1 {
+		Sha1{ h: H, buffer: Vec::with_capacity(64), length: 0 }
+	}
+
+	pub fn update(&mut self, input: &[u8]) {
+		self.length += input.len() as u64;
+
+		let mut input = input;
+		if !self.buffer.is_empty() {
+			let take = (64 - self.buffer.len()).min(input.len());
+			self.buffer.extend_from_slice(&input[..take]);
+			input = &input[take..];
+
+			if self.buffer.len() < 64 { return; }
+
+			let block = self.buffer.split_off(0);
+			self.compress(&block);
+		}
+
+		let mut chunks = input.chunks_exact(64);
+		for block in &mut chunks {
+			self.compress(block);
+		}
+		self.buffer.extend_from_slice(chunks.remainder());
+	}
+
+	pub fn finish(mut self) -> [u8; 20] {
+		let bits = self.length * 8;
+
+		self.update(&[0x80]);
+		while self.buffer.len() != 56 {
+			self.update(&[0]);
+		}
+		self.update(&bits.to_be_bytes());
+
+		let mut ret = [0; 20];
+		for i in 0..5 {
+			ret[i*4..(i+1)*4].copy_from_slice(&self.h[i].to_be_bytes());
+		}
+
+		ret
+	}
+
+	fn compress(&mut self, block: &[u8]) {
+		let mut w = [0u32; 80];
+		for i in 0..16 {
+			w[i] = u32::from_be_bytes([block[i*4], block[i*4+1], block[i*4+2], block[i*4+3]]);
+		}
+		for i in 16..80 {
+			w[i] = (w[i-3] ^ w[i-8] ^ w[i-14] ^ w[i-16]).rotate_left(1);
+		}
+
+		let [mut a, mut b, mut c, mut d, mut e] = self.h;
+
+		for i in 0..80 {
+			let (f, k) = match i {
+				0..=19 => ((b & c) | (!b & d), 0x5a827999),
+				20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+				40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+				_ => (b ^ c ^ d, 0xca62c1d6)
+			};
+
+			let temp = a.rotate_left(5)
+				.wrapping_add(f)
+				.wrapping_add(e)
+				.wrapping_add(k)
+				.wrapping_add(w[i]);
+			e = d;
+			d = c;
+			c = b.rotate_left(30);
+			b = a;
+			a = temp;
+		}
+
+		self.h[0] = self.h[0].wrapping_add(a);
+		self.h[1] = self.h[1].wrapping_add(b);
+		self.h[2] = self.h[2].wrapping_add(c);
+		self.h[3] = self.h[3].wrapping_add(d);
+		self.h[4] = self.h[4].wrapping_add(e);
+	}
+}
+
+pub fn digest(input: &[u8]) -> [u8; 20] {
+	let mut sha1 = Sha1::new();
+	sha1.update(input);
+	sha1.finish()
+}
+
+#[cfg(test)]
+mod tests {
+	use sha1::*;
+
+	#[test]
+	fn test_digest() {
+		assert_eq!([
+			0xa9, 0x99, 0x3e, 0x36, 0x47, 0x06, 0x81, 0x6a, 0xba, 0x3e,
+			0x25, 0x71, 0x78, 0x50, 0xc2, 0x6c, 0x9c, 0xd0, 0xd8, 0x9d
+		], digest(b"abc"));
+		assert_eq!([
+			0x84, 0x98, 0x3e, 0x44, 0x1c, 0x3b, 0xd2, 0x6e, 0xba, 0xae,
+			0x4a, 0xa1, 0xf9, 0x51, 0x29, 0xe5, 0xe5, 0x46, 0x70, 0xf1
+		], digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"));
+	}
+
+	#[test]
+	fn test_incremental() {
+		let input = [0x61; 1000];
+
+		let mut sha1 = Sha1::new();
+		for chunk in input.chunks(7) {
+			sha1.update(chunk);
+		}
+		assert_eq!(digest(&input), sha1.finish());
+	}
+}
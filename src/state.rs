@@ -1,20 +1,24 @@
-use std::fmt;
-use std::result;
+use core::fmt;
+use core::result;
 
-use ff::FF;
+use matrix::Matrix;
+use polynomial::{MIX_COLUMNS, INV_MIX_COLUMNS};
 use sbox;
 use util;
+use zeroize::zeroize;
 
+// Four rows of NB columns.  AES always has 4 columns, Rijndael has anywhere
+// from 4 to 8
 #[derive(Debug, PartialEq)]
-pub struct State {
-    state: [[u8; 4]; 4]
+pub struct State<const NB: usize = 4> {
+    state: [[u8; NB]; 4]
 }
 
-impl State {
-	pub fn from_slice(slice: &[u8]) -> State {
-		let mut state = [[0; 4]; 4];
+impl<const NB: usize> State<NB> {
+	pub fn from_block(slice: &[u8]) -> State<NB> {
+		let mut state = [[0; NB]; 4];
 
-		for c in 0..4 {
+		for c in 0..NB {
 			for r in 0..4 {
 				state[r][c] = slice[c*4 + r];
 			}
@@ -23,11 +27,11 @@ impl State {
 		State{state}
 	}
 
-    pub fn sub_bytes(&self) -> State {
-        let mut ret = [[0;4]; 4];
+    pub fn sub_bytes(&self) -> State<NB> {
+        let mut ret = [[0; NB]; 4];
 
         for i in 0..4 {
-            for j in 0..4 {
+            for j in 0..NB {
                 ret[i][j] = sbox::sub_byte(self.state[i][j]);
             }
         }
@@ -35,11 +39,11 @@ impl State {
         State{ state: ret }
     }
 
-    pub fn inv_sub_bytes(&self) -> State {
-        let mut ret = [[0;4]; 4];
+    pub fn inv_sub_bytes(&self) -> State<NB> {
+        let mut ret = [[0; NB]; 4];
 
         for i in 0..4 {
-            for j in 0..4 {
+            for j in 0..NB {
                 ret[i][j] = sbox::inv_sub_byte(self.state[i][j]);
             }
         }
@@ -47,80 +51,63 @@ impl State {
         State{ state: ret }
     }
 
-    pub fn shift_rows(&self) -> State {
+    pub fn shift_rows(&self) -> State<NB> {
         State{state: [
-            State::shift_row(&self.state[0], 0),
-            State::shift_row(&self.state[1], 1),
-            State::shift_row(&self.state[2], 2),
-            State::shift_row(&self.state[3], 3),
+            State::shift_row(&self.state[0], shift_offset(NB, 0)),
+            State::shift_row(&self.state[1], shift_offset(NB, 1)),
+            State::shift_row(&self.state[2], shift_offset(NB, 2)),
+            State::shift_row(&self.state[3], shift_offset(NB, 3)),
         ]}
     }
 
-    pub fn inv_shift_rows(&self) -> State {
+    pub fn inv_shift_rows(&self) -> State<NB> {
         State{state: [
-            State::inv_shift_row(&self.state[0], 0),
-            State::inv_shift_row(&self.state[1], 1),
-            State::inv_shift_row(&self.state[2], 2),
-            State::inv_shift_row(&self.state[3], 3),
+            State::inv_shift_row(&self.state[0], shift_offset(NB, 0)),
+            State::inv_shift_row(&self.state[1], shift_offset(NB, 1)),
+            State::inv_shift_row(&self.state[2], shift_offset(NB, 2)),
+            State::inv_shift_row(&self.state[3], shift_offset(NB, 3)),
         ]}
     }
 
-    fn shift_row(row: &[u8; 4], amount: usize) -> [u8; 4] {
+    fn shift_row(row: &[u8; NB], amount: usize) -> [u8; NB] {
         if amount == 0 { return *row; }
 
-        let mut ret = [0; 4];
+        let mut ret = [0; NB];
 
-        for i in 0..4 {
-            ret[i] = row[(i+amount) % 4];
+        for i in 0..NB {
+            ret[i] = row[(i+amount) % NB];
         }
 
         ret
     }
 
-    fn inv_shift_row(row: &[u8; 4], amount: usize) -> [u8; 4] {
+    fn inv_shift_row(row: &[u8; NB], amount: usize) -> [u8; NB] {
         if amount == 0 { return *row; }
 
-        let mut ret = [0; 4];
+        let mut ret = [0; NB];
 
-        for i in 0..4 {
-            ret[i] = row[(i+4-amount) % 4];
+        for i in 0..NB {
+            ret[i] = row[(i+NB-amount) % NB];
         }
 
         ret
     }
 
-    pub fn mix_columns(&self) -> State {
-        let mut ret = self.state.clone();
-
-        for i in 0..4 {
-            let col = State::mix_column(&mut ret, i);
-            for j in 0..4 {
-                ret[j][i] = col[j];
-            }
-        }
-
-        State{state: ret}
+    pub fn mix_columns(&self) -> State<NB> {
+        self.mix_columns_with(&MIX_COLUMNS.matrix())
     }
 
-    fn mix_column(arr: &[[u8;4]; 4], col: usize) -> [u8; 4] {
-        let mut ret = [0; 4];
-        for i in 0..4 {
-            ret[i] = (
-                  FF::new(arr[(i+0)%4][col]) * FF::new(0x02)
-                + FF::new(arr[(i+1)%4][col]) * FF::new(0x03)
-                + FF::new(arr[(i+2)%4][col])
-                + FF::new(arr[(i+3)%4][col])
-            ).value();
-        }
-
-        ret
+    pub fn inv_mix_columns(&self) -> State<NB> {
+        self.mix_columns_with(&INV_MIX_COLUMNS.matrix())
     }
 
-    pub fn inv_mix_columns(&self) -> State {
-        let mut ret = self.state.clone();
+    // Multiplies each column by the matrix, so another diffusion layer can be
+    // tried in place of MixColumns
+    pub fn mix_columns_with(&self, matrix: &Matrix<4>) -> State<NB> {
+        let mut ret = self.state;
 
-        for i in 0..4 {
-            let col = State::inv_mix_column(&mut ret, i);
+        for i in 0..NB {
+            let col = matrix.apply(&[ret[0][i], ret[1][i], ret[2][i], ret[3][i]]);
             for j in 0..4 {
                 ret[j][i] = col[j];
             }
@@ -129,24 +116,10 @@ impl State {
         State{state: ret}
     }
 
-    fn inv_mix_column(arr: &[[u8;4]; 4], col: usize) -> [u8; 4] {
-        let mut ret = [0; 4];
-        for i in 0..4 {
-            ret[i] = (
-                  FF::new(arr[(i+0)%4][col]) * FF::new(0x0e)
-                + FF::new(arr[(i+1)%4][col]) * FF::new(0x0b)
-                + FF::new(arr[(i+2)%4][col]) * FF::new(0x0d)
-                + FF::new(arr[(i+3)%4][col]) * FF::new(0x09)
-            ).value();
-        }
-
-        ret
-    }
-
-	pub fn add_round_key(&self, slice: &[u32]) -> State {
+	pub fn add_round_key(&self, slice: &[u32]) -> State<NB> {
 
-		let mut ret = [[0;4]; 4];
-		for c in 0..4 {
+		let mut ret = [[0; NB]; 4];
+		for c in 0..NB {
 			let word = util::bytes_to_word((self.state[0][c],self.state[1][c],self.state[2][c],self.state[3][c]));
 
 			let res = word ^ slice[c];
@@ -161,35 +134,60 @@ impl State {
 		State{state: ret}
 	}
 
-	pub fn to_byte_array(self) -> [u8; 16] {
-		let mut ret = [0; 16];
-
-		for c in 0..4 {
+	pub fn copy_to_slice(&self, slice: &mut [u8]) {
+		for c in 0..NB {
 			for r in 0..4 {
-				ret[c*4 + r] = self.state[r][c];
+				slice[c*4 + r] = self.state[r][c];
 			}
 		}
+	}
+}
 
+impl State {
+	pub fn from_slice(slice: &[u8]) -> State {
+		State::from_block(slice)
+	}
+
+	pub fn to_byte_array(&self) -> [u8; 16] {
+		let mut ret = [0; 16];
+		self.copy_to_slice(&mut ret);
 		ret
+	}
 }
 
-	fn to_u128(&self) -> u128 {
-		let mut res = 0;
+// How far left each row is rotated.  Rijndael shifts further for 7 and 8
+// columns, so that the bytes of a column still end up in different columns
+const fn shift_offset(nb: usize, row: usize) -> usize {
+	match (nb, row) {
+		(_, 0) => 0,
+		(_, 1) => 1,
+		(8, 2) => 3,
+		(_, 2) => 2,
+		(7, 3) | (8, 3) => 4,
+		_ => 3
+	}
+}
 
-		for c in 0..4 {
-			for r in 0..4 {
-				res <<= 8;
-				res |= self.state[r][c] as u128;
-			}
+// Every round leaves a State behind, and those are intermediate values of
+// the cipher that shouldn't outlive it
+impl<const NB: usize> Drop for State<NB> {
+	fn drop(&mut self) {
+		for row in self.state.iter_mut() {
+			zeroize(row);
 		}
-
-		res
 	}
 }
 
-impl fmt::Display for State {
+// The bytes in order, as the FIPS-197 examples print them
+impl<const NB: usize> fmt::Display for State<NB> {
 	fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
-		write!(formatter, "{:0>32x}", self.to_u128())
+		for c in 0..NB {
+			for r in 0..4 {
+				write!(formatter, "{:0>2x}", self.state[r][c])?;
+			}
+		}
+
+		Ok(())
 	}
 }
 
@@ -296,6 +294,20 @@ mod tests {
         ]));
     }
 
+	#[test]
+	fn test_mix_columns_with() {
+		assert_eq!(State::from_slice(&TEST_STATE.to_byte_array()), TEST_STATE.mix_columns_with(&Matrix::identity()));
+
+		// Swapping the top and bottom halves of each column
+		let swap = Matrix::new([[0, 0, 1, 0], [0, 0, 0, 1], [1, 0, 0, 0], [0, 1, 0, 0]]);
+		assert_eq!(State{ state: [
+			[0xe3,0xe2,0x8d,0x48],
+			[0xbe,0x2b,0x2a,0x08],
+			[0x19,0xa0,0x9a,0xe9],
+			[0x3d,0xf4,0xc6,0xf8]
+		]}, TEST_STATE.mix_columns_with(&swap));
+	}
+
 	#[test]
 	fn test_add_round_key() {
 		assert_eq!(State{state: [
@@ -325,7 +337,35 @@ mod tests {
 	}
 
 	#[test]
-	fn test_to_u128() {
-		assert_eq!(0x193de3bea0f4e22b9ac68d2ae9f84808, TEST_STATE.to_u128());
+	fn test_display() {
+		assert_eq!("193de3bea0f4e22b9ac68d2ae9f84808", format!("{}", TEST_STATE));
+	}
+
+	#[test]
+	fn test_shift_offsets() {
+		let offsets = |nb| [shift_offset(nb, 1), shift_offset(nb, 2), shift_offset(nb, 3)];
+		assert_eq!([1, 2, 3], offsets(4));
+		assert_eq!([1, 2, 3], offsets(5));
+		assert_eq!([1, 2, 3], offsets(6));
+		assert_eq!([1, 2, 4], offsets(7));
+		assert_eq!([1, 3, 4], offsets(8));
+	}
+
+	#[test]
+	fn test_rijndael256_round() {
+		let input: Vec<u8> = (0..32).collect();
+		let state: State<8> = State::from_block(&input);
+
+		// Row 2 moves by 3 and row 3 by 4, so the first column picks up bytes
+		// from columns 0, 1, 3 and 4
+		let mut output = [0; 32];
+		state.shift_rows().copy_to_slice(&mut output);
+		assert_eq!([0, 5, 14, 19], output[..4]);
+
+		state.shift_rows().inv_shift_rows().copy_to_slice(&mut output);
+		assert_eq!(&input[..], &output[..]);
+
+		state.sub_bytes().mix_columns().inv_mix_columns().inv_sub_bytes().copy_to_slice(&mut output);
+		assert_eq!(&input[..], &output[..]);
 	}
 }
\ No newline at end of file
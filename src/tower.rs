@@ -0,0 +1,159 @@
+// GF(2^8) represented as the tower field GF(2^4)[t]/(t^2 + t + C), where
+// GF(2^4) is GF(2)[y]/(y^4 + y + 1).  Elements are bytes with the coefficient
+// of t in the high nibble.  Inverting k*t + i only needs GF(2^4) arithmetic:
+// with j = i + k and the norm N = C*k^2 + i*j, the inverse is (k*t + j)/N.
+//
+// Nothing here branches on or indexes by its arguments, so it all takes the
+// same time whatever the input.  The functions are const so the vector permute
+// tables can be built from them too
+pub const C: u8 = 0x08;
+
+// A root of the AES polynomial x^8 + x^4 + x^3 + x + 1 in the tower field,
+// which is where x goes under the isomorphism
+const ROOT: u8 = root();
+const TO_TOWER: [u8; 8] = to_tower_basis();
+const FROM_TOWER: [u8; 8] = from_tower_basis();
+
+// All ones if the low bit is set, without a branch
+const fn mask(bit: u8) -> u8 {
+	0u8.wrapping_sub(bit & 1)
+}
+
+pub const fn gf16_mul(a: u8, b: u8) -> u8 {
+	let mut a = a;
+	let mut res = 0;
+	let mut i = 0;
+
+	while i < 4 {
+		res ^= a & mask(b >> i);
+		a = (a << 1) ^ (mask(a >> 3) & 0x13);
+		i += 1;
+	}
+
+	res
+}
+
+// a^14, which is 1/a for everything but 0, and 0 for 0
+pub const fn gf16_inv(a: u8) -> u8 {
+	let a2 = gf16_mul(a, a);
+	let a4 = gf16_mul(a2, a2);
+	let a8 = gf16_mul(a4, a4);
+	gf16_mul(gf16_mul(a8, a4), a2)
+}
+
+pub const fn mul(x: u8, y: u8) -> u8 {
+	let (a, b) = (x >> 4, x & 0x0f);
+	let (d, e) = (y >> 4, y & 0x0f);
+	let ad = gf16_mul(a, d);
+
+	(ad ^ gf16_mul(a, e) ^ gf16_mul(b, d)) << 4 | (gf16_mul(ad, C) ^ gf16_mul(b, e))
+}
+
+pub const fn inverse(x: u8) -> u8 {
+	let (k, i) = (x >> 4, x & 0x0f);
+	let j = i ^ k;
+	let n = gf16_mul(C, gf16_mul(k, k)) ^ gf16_mul(i, j);
+	let n_inv = gf16_inv(n);
+
+	gf16_mul(k, n_inv) << 4 | gf16_mul(j, n_inv)
+}
+
+const fn root() -> u8 {
+	let mut r = 0x10;
+	loop {
+		let mut powers = [1; 9];
+		let mut i = 1;
+		while i < 9 {
+			powers[i] = mul(powers[i-1], r);
+			i += 1;
+		}
+		if powers[8] ^ powers[4] ^ powers[3] ^ powers[1] ^ powers[0] == 0 {
+			return r;
+		}
+		r += 1;
+	}
+}
+
+const fn to_tower_basis() -> [u8; 8] {
+	let mut basis = [1; 8];
+	let mut i = 1;
+
+	while i < 8 {
+		basis[i] = mul(basis[i-1], ROOT);
+		i += 1;
+	}
+
+	basis
+}
+
+// The isomorphism is linear, so its inverse is found one bit at a time
+const fn from_tower_basis() -> [u8; 8] {
+	let mut basis = [0; 8];
+	let mut x: u16 = 0;
+
+	while x < 256 {
+		let y = to_tower(x as u8);
+		if y.is_power_of_two() {
+			basis[y.trailing_zeros() as usize] = x as u8;
+		}
+		x += 1;
+	}
+
+	basis
+}
+
+const fn linear_map(x: u8, basis: &[u8; 8]) -> u8 {
+	let mut res = 0;
+	let mut i = 0;
+
+	while i < 8 {
+		res ^= basis[i] & mask(x >> i);
+		i += 1;
+	}
+
+	res
+}
+
+pub const fn to_tower(x: u8) -> u8 {
+	linear_map(x, &TO_TOWER)
+}
+
+pub const fn from_tower(y: u8) -> u8 {
+	linear_map(y, &FROM_TOWER)
+}
+
+#[cfg(test)]
+mod tests {
+	use tower::*;
+
+	#[test]
+	fn test_gf16() {
+		// y^3 * (y^3 + y^2 + y + 1) = y^6 + y^5 + y^4 + y^3 = 1
+		assert_eq!(0x01, gf16_mul(0x08, 0x0f));
+		assert_eq!(0x0f, gf16_inv(0x08));
+		assert_eq!(0x00, gf16_inv(0x00));
+
+		for a in 1..16 {
+			assert_eq!(1, gf16_mul(a, gf16_inv(a)));
+		}
+	}
+
+	#[test]
+	fn test_isomorphism() {
+		for x in 0..=255u8 {
+			assert_eq!(x, from_tower(to_tower(x)));
+		}
+
+		// {57} * {83} = {c1} from FIPS-197 section 4.2
+		assert_eq!(to_tower(0xc1), mul(to_tower(0x57), to_tower(0x83)));
+	}
+
+	#[test]
+	fn test_inverse() {
+		assert_eq!(0, inverse(0));
+
+		for x in 1..=255u8 {
+			assert_eq!(1, mul(x, inverse(x)));
+		}
+	}
+}
@@ -0,0 +1,312 @@
+use buffer::Buffer;
+use key::KeySchedule;
+use sbox::{S_BOX, INV_S_BOX};
+
+// Combines SubBytes, ShiftRows and MixColumns into four 256 entry lookups per
+// column per round.  TE[0][x] is the column (02, 01, 01, 03) * S[x], and each
+// further table is the previous one rotated by a byte, so that one lookup per
+// input byte covers its contribution to a whole output column.  The TD tables
+// do the same for the inverse cipher
+static TE: [[u32; 256]; 4] = rotations(encryption_table());
+static TD: [[u32; 256]; 4] = rotations(decryption_table());
+
+const fn xtime(x: u8) -> u8 {
+	(x << 1) ^ if x & 0x80 != 0 { 0x1b } else { 0 }
+}
+
+// FF::mul can't be used in a const context, so this is the same shift and
+// add multiplication
+const fn mul(a: u8, b: u8) -> u8 {
+	let mut a = a;
+	let mut res = 0;
+	let mut i = 0;
+
+	while i < 8 {
+		if b & (1 << i) != 0 {
+			res ^= a;
+		}
+		a = xtime(a);
+		i += 1;
+	}
+
+	res
+}
+
+const fn column(a: u8, b: u8, c: u8, d: u8) -> u32 {
+	(a as u32) << 24 | (b as u32) << 16 | (c as u32) << 8 | d as u32
+}
+
+const fn encryption_table() -> [u32; 256] {
+	let mut table = [0; 256];
+	let mut i = 0;
+
+	while i < 256 {
+		let s = S_BOX[i];
+		table[i] = column(mul(s, 2), s, s, mul(s, 3));
+		i += 1;
+	}
+
+	table
+}
+
+const fn decryption_table() -> [u32; 256] {
+	let mut table = [0; 256];
+	let mut i = 0;
+
+	while i < 256 {
+		let s = INV_S_BOX[i];
+		table[i] = column(mul(s, 0x0e), mul(s, 0x09), mul(s, 0x0d), mul(s, 0x0b));
+		i += 1;
+	}
+
+	table
+}
+
+const fn rotations(table: [u32; 256]) -> [[u32; 256]; 4] {
+	let mut tables = [[0; 256]; 4];
+	let mut i = 0;
+
+	while i < 256 {
+		tables[0][i] = table[i];
+		tables[1][i] = table[i].rotate_right(8);
+		tables[2][i] = table[i].rotate_right(16);
+		tables[3][i] = table[i].rotate_right(24);
+		i += 1;
+	}
+
+	tables
+}
+
+fn byte(word: u32, pos: usize) -> usize {
+	((word >> (24 - 8*pos)) & 0xff) as usize
+}
+
+fn to_words(input: &[u8]) -> [u32; 4] {
+	let mut words = [0; 4];
+
+	for c in 0..4 {
+		words[c] = u32::from_be_bytes([input[c*4], input[c*4+1], input[c*4+2], input[c*4+3]]);
+	}
+
+	words
+}
+
+fn to_bytes(words: &[u32; 4]) -> [u8; 16] {
+	let mut ret = [0; 16];
+
+	for c in 0..4 {
+		ret[c*4..(c+1)*4].copy_from_slice(&words[c].to_be_bytes());
+	}
+
+	ret
+}
+
+pub fn encrypt(schedule: &KeySchedule, input: &[u8]) -> [u8; 16] {
+	let nr = schedule.rounds();
+
+	let mut s = add_round_key(to_words(input), &schedule[0]);
+	for round in 1..nr {
+		s = round_enc(&s, &schedule[round]);
+	}
+
+	to_bytes(&last_round_enc(&s, &schedule[nr]))
+}
+
+// Runs several blocks through the rounds together, each under its own key
+// schedule with the same number of rounds.  The lookups for one block don't
+// depend on the others, so the CPU can overlap them instead of waiting on
+// each round of a single block in turn
+#[cfg(feature = "std")]
+pub fn encrypt_lanes(jobs: &[(&KeySchedule, &[u8])]) -> Vec<[u8; 16]> {
+	let nr = jobs[0].0.rounds();
+
+	let mut s: Vec<[u32; 4]> = jobs.iter()
+		.map(|&(schedule, input)| add_round_key(to_words(input), &schedule[0]))
+		.collect();
+	for round in 1..nr {
+		for (s, &(schedule, _)) in s.iter_mut().zip(jobs) {
+			*s = round_enc(s, &schedule[round]);
+		}
+	}
+
+	s.iter().zip(jobs).map(|(s, &(schedule, _))| to_bytes(&last_round_enc(s, &schedule[nr]))).collect()
+}
+
+fn add_round_key(mut s: [u32; 4], ks: &[u32]) -> [u32; 4] {
+	for c in 0..4 {
+		s[c] ^= ks[c];
+	}
+
+	s
+}
+
+#[inline(always)]
+fn round_enc(s: &[u32; 4], ks: &[u32]) -> [u32; 4] {
+	let mut t = [0; 4];
+
+	// Column c of the output takes row r from column c+r of the input,
+	// which is where ShiftRows went
+	for c in 0..4 {
+		t[c] = TE[0][byte(s[c], 0)]
+			^ TE[1][byte(s[(c+1) % 4], 1)]
+			^ TE[2][byte(s[(c+2) % 4], 2)]
+			^ TE[3][byte(s[(c+3) % 4], 3)]
+			^ ks[c];
+	}
+
+	t
+}
+
+// There's no MixColumns in the last round, so just the S-box is used
+fn last_round_enc(s: &[u32; 4], ks: &[u32]) -> [u32; 4] {
+	let mut t = [0; 4];
+
+	for c in 0..4 {
+		t[c] = column(
+			S_BOX[byte(s[c], 0)],
+			S_BOX[byte(s[(c+1) % 4], 1)],
+			S_BOX[byte(s[(c+2) % 4], 2)],
+			S_BOX[byte(s[(c+3) % 4], 3)]
+		) ^ ks[c];
+	}
+
+	t
+}
+
+// The T-table decryption uses the equivalent inverse cipher from section 5.3.5
+// of FIPS-197, which needs InvMixColumns applied to the middle round keys.
+// The keys are also stored in the order they are used
+pub fn inverse_schedule(schedule: &KeySchedule) -> Buffer<u32, 60> {
+	let nr = schedule.rounds();
+	let mut words = Buffer::new();
+
+	for round in (0..nr+1).rev() {
+		for &word in schedule[round].iter() {
+			words.push(if round == 0 || round == nr { word } else { inv_mix_column(word) });
+		}
+	}
+
+	words
+}
+
+// TD includes the inverse S-box, so the forward S-box cancels it out
+fn inv_mix_column(word: u32) -> u32 {
+	TD[0][S_BOX[byte(word, 0)] as usize]
+		^ TD[1][S_BOX[byte(word, 1)] as usize]
+		^ TD[2][S_BOX[byte(word, 2)] as usize]
+		^ TD[3][S_BOX[byte(word, 3)] as usize]
+}
+
+pub fn decrypt(inverse_schedule: &[u32], input: &[u8]) -> [u8; 16] {
+	let nr = inverse_schedule.len() / 4 - 1;
+	let ks = |round: usize| &inverse_schedule[round*4..(round+1)*4];
+
+	let mut s = add_round_key(to_words(input), ks(0));
+	for round in 1..nr {
+		s = round_dec(&s, ks(round));
+	}
+
+	to_bytes(&last_round_dec(&s, ks(nr)))
+}
+
+#[cfg(feature = "std")]
+pub fn decrypt_lanes(jobs: &[(&[u32], &[u8])]) -> Vec<[u8; 16]> {
+	let nr = jobs[0].0.len() / 4 - 1;
+
+	let mut s: Vec<[u32; 4]> = jobs.iter()
+		.map(|&(inverse_schedule, input)| add_round_key(to_words(input), &inverse_schedule[..4]))
+		.collect();
+	for round in 1..nr {
+		for (s, &(inverse_schedule, _)) in s.iter_mut().zip(jobs) {
+			*s = round_dec(s, &inverse_schedule[round*4..(round+1)*4]);
+		}
+	}
+
+	s.iter().zip(jobs)
+		.map(|(s, &(inverse_schedule, _))| to_bytes(&last_round_dec(s, &inverse_schedule[nr*4..])))
+		.collect()
+}
+
+#[inline(always)]
+fn round_dec(s: &[u32; 4], ks: &[u32]) -> [u32; 4] {
+	let mut t = [0; 4];
+
+	// InvShiftRows moves row r right, so it comes from column c-r
+	for c in 0..4 {
+		t[c] = TD[0][byte(s[c], 0)]
+			^ TD[1][byte(s[(c+3) % 4], 1)]
+			^ TD[2][byte(s[(c+2) % 4], 2)]
+			^ TD[3][byte(s[(c+1) % 4], 3)]
+			^ ks[c];
+	}
+
+	t
+}
+
+fn last_round_dec(s: &[u32; 4], ks: &[u32]) -> [u32; 4] {
+	let mut t = [0; 4];
+
+	for c in 0..4 {
+		t[c] = column(
+			INV_S_BOX[byte(s[c], 0)],
+			INV_S_BOX[byte(s[(c+3) % 4], 1)],
+			INV_S_BOX[byte(s[(c+2) % 4], 2)],
+			INV_S_BOX[byte(s[(c+1) % 4], 3)]
+		) ^ ks[c];
+	}
+
+	t
+}
+
+#[cfg(test)]
+mod tests {
+	use ttable::*;
+	use key::Key;
+	use state::State;
+
+	#[test]
+	fn test_tables() {
+		assert_eq!(0xc66363a5, TE[0][0x00]);
+		assert_eq!(0xa5c66363, TE[1][0x00]);
+		assert_eq!(0x51f4a750, TD[0][0x00]);
+		assert_eq!(0x5051f4a7, TD[1][0x00]);
+	}
+
+	#[test]
+	fn test_inv_mix_column() {
+		// The first column of the InvMixColumns example in state.rs
+		assert_eq!(0xe51c9502, inv_mix_column(0x627bceb9));
+	}
+
+	// Compares every round of the T-table implementation against the State
+	// operations, over a spread of keys and inputs
+	#[test]
+	fn test_matches_reference() {
+		let mut seed: u32 = 0x12345678;
+		let mut next = || {
+			seed ^= seed << 13;
+			seed ^= seed >> 17;
+			seed ^= seed << 5;
+			seed
+		};
+
+		for &nk in [4, 6, 8].iter() {
+			for _ in 0..20 {
+				let words: Vec<u32> = (0..nk).map(|_| next()).collect();
+				let schedule = Key::new(&words).create_schedule();
+				let nr = schedule.rounds();
+
+				let input: Vec<u8> = (0..16).map(|_| next() as u8).collect();
+
+				let mut state = State::from_slice(&input).add_round_key(&schedule[0]);
+				for round in 1..nr {
+					state = state.sub_bytes().shift_rows().mix_columns().add_round_key(&schedule[round]);
+				}
+				let expected = state.sub_bytes().shift_rows().add_round_key(&schedule[nr]).to_byte_array();
+
+				assert_eq!(expected, encrypt(&schedule, &input));
+				assert_eq!(&input[..], &decrypt(&inverse_schedule(&schedule), &expected)[..]);
+			}
+		}
+	}
+}
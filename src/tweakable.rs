@@ -0,0 +1,9 @@
+// A block cipher that takes an extra public input, the tweak, alongside the
+// key.  Different tweaks give what behave like independent permutations, which
+// is what disk encryption modes use to stop identical sectors or blocks from
+// encrypting identically
+pub trait TweakableBlockCipher {
+	fn encrypt_block(&self, tweak: &[u8; 16], input: &[u8]) -> [u8; 16];
+
+	fn decrypt_block(&self, tweak: &[u8; 16], input: &[u8]) -> [u8; 16];
+}
@@ -1,20 +1,20 @@
-pub fn word_to_bytes(word: u32) -> (u8, u8, u8, u8) {
+pub const fn word_to_bytes(word: u32) -> (u8, u8, u8, u8) {
     (
         ((word & 0xff000000) >> 24) as u8,
         ((word & 0x00ff0000) >> 16) as u8,
-        ((word & 0x0000ff00) >> 08) as u8,
-        ((word & 0x000000ff) >> 00) as u8
+        ((word & 0x0000ff00) >> 8) as u8,
+        (word & 0x000000ff) as u8
     )
 }
 
-pub fn bytes_to_word(bytes: (u8, u8, u8, u8)) -> u32 {
+pub const fn bytes_to_word(bytes: (u8, u8, u8, u8)) -> u32 {
     (bytes.0 as u32) << 24 ^
     (bytes.1 as u32) << 16 ^
-    (bytes.2 as u32) << 08 ^
+    (bytes.2 as u32) << 8 ^
     (bytes.3 as u32)
 }
 
-pub fn rot_word(word: u32) -> u32 {
+pub const fn rot_word(word: u32) -> u32 {
     let high: u8 = ((word & 0xff000000) >> 24) as u8;
     let word = word << 8;
 
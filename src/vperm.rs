@@ -0,0 +1,370 @@
+// Constant time AES with SSSE3's pshufb, after Hamburg's "Accelerating AES
+// with Vector Permute Instructions".  pshufb looks up 16 bytes at once in a 16
+// entry table, so any function of a nibble is one instruction, and a linear map
+// of a byte is two lookups xored together.  Bytes are mapped into the tower
+// field, where the inverse of k*t + i only needs inverses of nibbles.  With
+// j = i + k, the norm N = C*k^2 + i*j and A = 1/C:
+//
+//   io = j + 1/(1/i + A/k) = N/(C*k + i)
+//   jo = i + 1/(1/j + A/k) = N/(C*k + j)
+//   1/(k*t + i) = (k*t + j)/N = (t + C)/io + (t + C + 1)/jo
+//
+// so the output tables take io and jo and produce the S-box output, already
+// multiplied by whatever MixColumns needs.  1/0 is looked up as 0x80, which
+// pshufb turns back into 0 on the next lookup, so zero needs no special case.
+// Everything here must only be called once is_available() has returned true
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::*;
+
+use buffer::{Buffer, ROUND_KEYS};
+use key::KeySchedule;
+use tower::{self, C, gf16_mul, gf16_inv, to_tower, from_tower};
+
+const A: u8 = gf16_inv(C);
+
+static INPUT: [[u8; 16]; 2] = input_tables(false);
+static INV_INPUT: [[u8; 16]; 2] = input_tables(true);
+static INVERSE: [u8; 16] = nibble_inverses(1);
+static A_OVER: [u8; 16] = nibble_inverses(A);
+
+// The S-box output times 1 and 2 for MixColumns, and the inverse S-box output
+// times 1, 14, 11, 13 and 9 for InvMixColumns
+static ENC_OUTPUT: [[[u8; 16]; 2]; 2] = [output_tables(1, true), output_tables(2, true)];
+static DEC_OUTPUT: [[[u8; 16]; 2]; 5] = [
+	output_tables(1, false),
+	output_tables(0x0e, false),
+	output_tables(0x0b, false),
+	output_tables(0x0d, false),
+	output_tables(0x09, false)
+];
+
+// Byte c*4 + r of the state is row r of column c
+static SHIFT_ROWS: [u8; 16] = shuffle(1, 0);
+static INV_SHIFT_ROWS: [u8; 16] = shuffle(3, 0);
+static ROTATE: [[u8; 16]; 3] = [shuffle(0, 1), shuffle(0, 2), shuffle(0, 3)];
+
+// AES field multiplication, for the MixColumns factors
+const fn mul(a: u8, b: u8) -> u8 {
+	let mut a = a;
+	let mut res = 0;
+	let mut i = 0;
+
+	while i < 8 {
+		if b & (1 << i) != 0 {
+			res ^= a;
+		}
+		a = (a << 1) ^ if a & 0x80 != 0 { 0x1b } else { 0 };
+		i += 1;
+	}
+
+	res
+}
+
+// The linear part of the S-box's affine map, and its inverse
+const fn linear(b: u8) -> u8 {
+	b ^ b.rotate_left(1) ^ b.rotate_left(2) ^ b.rotate_left(3) ^ b.rotate_left(4)
+}
+
+const fn inv_linear(b: u8) -> u8 {
+	b.rotate_left(1) ^ b.rotate_left(3) ^ b.rotate_left(6)
+}
+
+// Maps the low and high nibbles of a byte into the tower field.  For the
+// inverse cipher this undoes the affine map first, with its constant folded
+// into the low nibble table
+const fn input_tables(inverse: bool) -> [[u8; 16]; 2] {
+	let mut tables = [[0; 16]; 2];
+	let mut n = 0;
+
+	while n < 16 {
+		let (lo, hi) = if inverse {
+			(inv_linear(n as u8) ^ inv_linear(0x63), inv_linear((n as u8) << 4))
+		} else {
+			(n as u8, (n as u8) << 4)
+		};
+		tables[0][n] = to_tower(lo);
+		tables[1][n] = to_tower(hi);
+		n += 1;
+	}
+
+	tables
+}
+
+const fn nibble_inverses(numerator: u8) -> [u8; 16] {
+	let mut table = [0x80; 16];
+	let mut n = 1;
+
+	while n < 16 {
+		table[n] = gf16_mul(numerator, gf16_inv(n as u8));
+		n += 1;
+	}
+
+	table
+}
+
+// Takes io and jo back to the inverse in the AES field, then applies the
+// S-box's linear map if needed and multiplies by factor
+const fn output_tables(factor: u8, sbox: bool) -> [[u8; 16]; 2] {
+	let mut tables = [[0; 16]; 2];
+	let mut n = 0;
+
+	while n < 16 {
+		let inv = gf16_inv(n as u8);
+		let io = from_tower(tower::mul(0x10 | C, inv));
+		let jo = from_tower(tower::mul(0x10 | (C ^ 1), inv));
+
+		tables[0][n] = mul(factor, if sbox { linear(io) } else { io });
+		tables[1][n] = mul(factor, if sbox { linear(jo) } else { jo });
+		n += 1;
+	}
+
+	tables
+}
+
+// Row r of column c comes from row r + rows of column c + r*columns
+const fn shuffle(columns: usize, rows: usize) -> [u8; 16] {
+	let mut table = [0; 16];
+	let mut i = 0;
+
+	while i < 16 {
+		let (c, r) = (i / 4, i % 4);
+		table[i] = (((c + r*columns) % 4) * 4 + (r + rows) % 4) as u8;
+		i += 1;
+	}
+
+	table
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+pub fn is_available() -> bool {
+	is_x86_feature_detected!("ssse3")
+}
+
+// Without std it has to be known when compiling
+#[cfg(all(target_arch = "x86_64", not(feature = "std")))]
+pub fn is_available() -> bool {
+	cfg!(target_feature = "ssse3")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn is_available() -> bool {
+	false
+}
+
+// The round keys as bytes, with the S-box's affine constant folded into all
+// but the first.  MixColumns takes a column of equal bytes to itself, so the
+// constant can be added after it
+pub fn encryption_keys(schedule: &KeySchedule) -> Buffer<[u8; 16], ROUND_KEYS> {
+	(0..schedule.rounds() + 1).map(|round| {
+		let mut key = round_key(schedule, round);
+		if round > 0 {
+			for byte in key.iter_mut() {
+				*byte ^= 0x63;
+			}
+		}
+		key
+	}).collect()
+}
+
+// Decryption uses the equivalent inverse cipher, so the middle keys get
+// InvMixColumns, done here without tables so the key doesn't leak either
+pub fn decryption_keys(schedule: &KeySchedule) -> Buffer<[u8; 16], ROUND_KEYS> {
+	let nr = schedule.rounds();
+
+	(0..nr+1).rev().map(|round| {
+		let key = round_key(schedule, round);
+		if round == 0 || round == nr { key } else { inv_mix_columns(&key) }
+	}).collect()
+}
+
+fn round_key(schedule: &KeySchedule, round: usize) -> [u8; 16] {
+	let mut key = [0; 16];
+	for c in 0..4 {
+		key[c*4..(c+1)*4].copy_from_slice(&schedule[round][c].to_be_bytes());
+	}
+	key
+}
+
+fn xtime(x: u8) -> u8 {
+	(x << 1) ^ ((x >> 7) * 0x1b)
+}
+
+fn inv_mix_columns(block: &[u8; 16]) -> [u8; 16] {
+	let mut ret = [0; 16];
+
+	for c in 0..4 {
+		let a = &block[c*4..(c+1)*4];
+		for r in 0..4 {
+			let (a0, a1, a2, a3) = (a[r], a[(r+1) % 4], a[(r+2) % 4], a[(r+3) % 4]);
+
+			// 14*a0 + 11*a1 + 13*a2 + 9*a3, from the doublings of each
+			let x2 = xtime(a0 ^ a1);
+			let x4 = xtime(xtime(a0 ^ a2));
+			let x8 = xtime(xtime(xtime(a0 ^ a1 ^ a2 ^ a3)));
+			ret[c*4 + r] = x8 ^ x4 ^ x2 ^ a1 ^ a2 ^ a3;
+		}
+	}
+
+	ret
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn encrypt(keys: &[[u8; 16]], input: &[u8]) -> [u8; 16] {
+	unsafe { encrypt_impl(keys, input) }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn decrypt(keys: &[[u8; 16]], input: &[u8]) -> [u8; 16] {
+	unsafe { decrypt_impl(keys, input) }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn encrypt(_keys: &[[u8; 16]], _input: &[u8]) -> [u8; 16] {
+	unreachable!("SSSE3 is not available on this architecture")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub fn decrypt(_keys: &[[u8; 16]], _input: &[u8]) -> [u8; 16] {
+	unreachable!("SSSE3 is not available on this architecture")
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn load(bytes: &[u8]) -> __m128i {
+	_mm_loadu_si128(bytes.as_ptr() as *const __m128i)
+}
+
+#[cfg(target_arch = "x86_64")]
+unsafe fn store(block: __m128i) -> [u8; 16] {
+	let mut ret = [0; 16];
+	_mm_storeu_si128(ret.as_mut_ptr() as *mut __m128i, block);
+	ret
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn lookup(table: &[u8; 16], index: __m128i) -> __m128i {
+	_mm_shuffle_epi8(load(table), index)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn permute(state: __m128i, order: &[u8; 16]) -> __m128i {
+	_mm_shuffle_epi8(state, load(order))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn nibbles(x: __m128i) -> (__m128i, __m128i) {
+	let mask = _mm_set1_epi8(0x0f);
+	(_mm_and_si128(x, mask), _mm_and_si128(_mm_srli_epi16(x, 4), mask))
+}
+
+// Inverts every byte of the state, giving io and jo
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn invert(state: __m128i, input: &[[u8; 16]; 2]) -> (__m128i, __m128i) {
+	let (lo, hi) = nibbles(state);
+	let tower = _mm_xor_si128(lookup(&input[0], lo), lookup(&input[1], hi));
+
+	let (i, k) = nibbles(tower);
+	let j = _mm_xor_si128(i, k);
+	let ak = lookup(&A_OVER, k);
+	let iak = _mm_xor_si128(lookup(&INVERSE, i), ak);
+	let jak = _mm_xor_si128(lookup(&INVERSE, j), ak);
+
+	(_mm_xor_si128(lookup(&INVERSE, iak), j), _mm_xor_si128(lookup(&INVERSE, jak), i))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn output(inverse: (__m128i, __m128i), tables: &[[u8; 16]; 2]) -> __m128i {
+	_mm_xor_si128(lookup(&tables[0], inverse.0), lookup(&tables[1], inverse.1))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn encrypt_impl(keys: &[[u8; 16]], input: &[u8]) -> [u8; 16] {
+	let nr = keys.len() - 1;
+
+	let mut state = _mm_xor_si128(load(input), load(&keys[0]));
+	for round in 1..nr+1 {
+		let inverse = invert(permute(state, &SHIFT_ROWS), &INPUT);
+		let a = output(inverse, &ENC_OUTPUT[0]);
+
+		// 2*a0 + 3*a1 + a2 + a3
+		if round < nr {
+			let b = output(inverse, &ENC_OUTPUT[1]);
+			state = _mm_xor_si128(
+				_mm_xor_si128(b, permute(_mm_xor_si128(a, b), &ROTATE[0])),
+				_mm_xor_si128(permute(a, &ROTATE[1]), permute(a, &ROTATE[2]))
+			);
+		} else {
+			state = a;
+		}
+
+		state = _mm_xor_si128(state, load(&keys[round]));
+	}
+
+	store(state)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn decrypt_impl(keys: &[[u8; 16]], input: &[u8]) -> [u8; 16] {
+	let nr = keys.len() - 1;
+
+	let mut state = _mm_xor_si128(load(input), load(&keys[0]));
+	for round in 1..nr+1 {
+		let inverse = invert(permute(state, &INV_SHIFT_ROWS), &INV_INPUT);
+
+		// 14*a0 + 11*a1 + 13*a2 + 9*a3
+		if round < nr {
+			state = _mm_xor_si128(
+				_mm_xor_si128(output(inverse, &DEC_OUTPUT[1]), permute(output(inverse, &DEC_OUTPUT[2]), &ROTATE[0])),
+				_mm_xor_si128(permute(output(inverse, &DEC_OUTPUT[3]), &ROTATE[1]), permute(output(inverse, &DEC_OUTPUT[4]), &ROTATE[2]))
+			);
+		} else {
+			state = output(inverse, &DEC_OUTPUT[0]);
+		}
+
+		state = _mm_xor_si128(state, load(&keys[round]));
+	}
+
+	store(state)
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+	use vperm::*;
+	use sbox::{S_BOX, INV_S_BOX};
+	use state::State;
+
+	#[test]
+	fn test_sub_bytes() {
+		if !is_available() { return; }
+
+		for chunk in 0..16 {
+			let input: Vec<u8> = (0..16).map(|i| (chunk * 16 + i) as u8).collect();
+
+			unsafe {
+				let forward = store(output(invert(load(&input), &INPUT), &ENC_OUTPUT[0]));
+				let inverse = store(output(invert(load(&input), &INV_INPUT), &DEC_OUTPUT[0]));
+
+				for i in 0..16 {
+					assert_eq!(S_BOX[input[i] as usize], forward[i] ^ 0x63);
+					assert_eq!(INV_S_BOX[input[i] as usize], inverse[i]);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn test_inv_mix_columns() {
+		let block = [
+			0x62, 0x7b, 0xce, 0xb9, 0x99, 0x9d, 0x5a, 0xaa,
+			0xc9, 0x45, 0xec, 0xf4, 0x23, 0xf5, 0x6d, 0xa5
+		];
+
+		assert_eq!(State::from_slice(&block).inv_mix_columns().to_byte_array(), inv_mix_columns(&block));
+	}
+}
@@ -0,0 +1,218 @@
+use aes::{Encryptor, Decryptor};
+use gf128;
+use key::Key;
+
+// XTS from IEEE 1619, the standard mode for disk sectors.  Block j of a data
+// unit is masked before and after encryption with T = E_K2(i) * x^j, where i
+// is the data unit (sector) number as a little endian block:
+//   C = E_K1(P ^ T) ^ T
+// A data unit that isn't a whole number of blocks uses ciphertext stealing
+// on its last two blocks, so the output is the same length as the input
+pub struct Xts {
+	encryptor: Encryptor,
+	decryptor: Decryptor,
+	tweak_encryptor: Encryptor
+}
+
+impl Xts {
+	pub fn using(key: Key, tweak_key: Key) -> Xts {
+		Xts{
+			encryptor: Encryptor::using(key.clone()),
+			decryptor: Decryptor::using(key),
+			tweak_encryptor: Encryptor::using(tweak_key)
+		}
+	}
+
+	#[cfg(feature = "std")]
+	pub fn encrypt(&self, sector: u128, input: &[u8]) -> Vec<u8> {
+		self.encrypt_from(sector, 0, input)
+	}
+
+	#[cfg(feature = "std")]
+	pub fn decrypt(&self, sector: u128, input: &[u8]) -> Vec<u8> {
+		self.decrypt_from(sector, 0, input)
+	}
+
+	// Starts at the given block of the data unit, so that a data unit can be
+	// processed in pieces.  Only the piece at the end can be a partial block
+	#[cfg(feature = "std")]
+	pub fn encrypt_from(&self, sector: u128, block: u64, input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.encrypt_in_place(sector, block, &mut output);
+		output
+	}
+
+	#[cfg(feature = "std")]
+	pub fn decrypt_from(&self, sector: u128, block: u64, input: &[u8]) -> Vec<u8> {
+		let mut output = input.to_vec();
+		self.decrypt_in_place(sector, block, &mut output);
+		output
+	}
+
+	pub fn encrypt_in_place(&self, sector: u128, block: u64, data: &mut [u8]) {
+		if data.len() < 16 { panic!("XTS can only encrypt at least one full block!"); }
+
+		self.transform(self.mask(sector, block), data, true)
+	}
+
+	pub fn decrypt_in_place(&self, sector: u128, block: u64, data: &mut [u8]) {
+		if data.len() < 16 { panic!("XTS can only decrypt at least one full block!"); }
+
+		self.transform(self.mask(sector, block), data, false)
+	}
+
+	fn mask(&self, sector: u128, block: u64) -> u128 {
+		let t = u128::from_le_bytes(self.tweak_encryptor.encrypt(&sector.to_le_bytes(), false));
+		gf128::mul(t, gf128::pow(2, block))
+	}
+
+	fn transform(&self, mask: u128, data: &mut [u8], encrypt: bool) {
+		let full = data.len() / 16;
+		let tail = data.len() % 16;
+
+		// With stealing, the last full block is done along with the tail
+		let bulk = if tail == 0 { full } else { full - 1 };
+
+		// Eight blocks at a time, with their masks kept on the stack
+		let mut t = mask;
+		let mut masks = [0; 8];
+		for chunk in data[..bulk*16].chunks_mut(128) {
+			for (block, mask) in chunk.chunks_mut(16).zip(masks.iter_mut()) {
+				*mask = t;
+				t = gf128::mul_alpha(t);
+				block.copy_from_slice(&(to_block(block) ^ *mask).to_le_bytes());
+			}
+
+			if encrypt {
+				self.encryptor.encrypt_blocks_in_place(chunk);
+			} else {
+				self.decryptor.decrypt_blocks_in_place(chunk);
+			}
+
+			for (block, mask) in chunk.chunks_mut(16).zip(masks.iter()) {
+				block.copy_from_slice(&(to_block(block) ^ mask).to_le_bytes());
+			}
+		}
+
+		if tail != 0 {
+			// Decryption needs the last mask first to recover the stolen bytes
+			let (first, second) = if encrypt {
+				(t, gf128::mul_alpha(t))
+			} else {
+				(gf128::mul_alpha(t), t)
+			};
+
+			let stolen = self.block(&data[bulk*16..(bulk+1)*16], first, encrypt);
+			let mut last = stolen;
+			last[..tail].copy_from_slice(&data[(bulk+1)*16..]);
+
+			data[bulk*16..(bulk+1)*16].copy_from_slice(&self.block(&last, second, encrypt));
+			data[(bulk+1)*16..].copy_from_slice(&stolen[..tail]);
+		}
+	}
+
+	fn block(&self, input: &[u8], mask: u128, encrypt: bool) -> [u8; 16] {
+		let masked = (to_block(input) ^ mask).to_le_bytes();
+		let block = if encrypt {
+			self.encryptor.encrypt(&masked, false)
+		} else {
+			self.decryptor.decrypt(&masked, false)
+		};
+
+		(u128::from_le_bytes(block) ^ mask).to_le_bytes()
+	}
+}
+
+fn to_block(bytes: &[u8]) -> u128 {
+	let mut block = [0; 16];
+	block.copy_from_slice(bytes);
+	u128::from_le_bytes(block)
+}
+
+#[cfg(test)]
+mod tests {
+	use xts::*;
+
+	// Vector 2 from IEEE 1619 Annex B
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_encrypt() {
+		let xts = Xts::using(Key::from_bytes(&[0x11; 16]), Key::from_bytes(&[0x22; 16]));
+
+		let expected = [
+			0xc4, 0x54, 0x18, 0x5e, 0x6a, 0x16, 0x93, 0x6e, 0x39, 0x33, 0x40, 0x38, 0xac, 0xef, 0x83, 0x8b,
+			0xfb, 0x18, 0x6f, 0xff, 0x74, 0x80, 0xad, 0xc4, 0x28, 0x93, 0x82, 0xec, 0xd6, 0xd3, 0x94, 0xf0
+		];
+		assert_eq!(&expected[..], &xts.encrypt(0x3333333333, &[0x44; 32])[..]);
+		assert_eq!(&[0x44; 32][..], &xts.decrypt(0x3333333333, &expected)[..]);
+	}
+
+	fn stealing_xts() -> Xts {
+		Xts::using(
+			Key::new(&[0xfffefdfc, 0xfbfaf9f8, 0xf7f6f5f4, 0xf3f2f1f0]),
+			Key::new(&[0xbfbebdbc, 0xbbbab9b8, 0xb7b6b5b4, 0xb3b2b1b0])
+		)
+	}
+
+	// Vectors 15 to 18 from IEEE 1619 Annex B, which use ciphertext stealing.
+	// The data unit is 9a 78 56 34 12 as little endian bytes, so unlike the
+	// 33 33 33 33 33 of vector 2 it checks the byte order of the tweak
+	const STEALING: [(usize, &[u8]); 4] = [
+		(17, &[
+			0x6c, 0x16, 0x25, 0xdb, 0x46, 0x71, 0x52, 0x2d, 0x3d, 0x75, 0x99, 0x60, 0x1d, 0xe7, 0xca, 0x09,
+			0xed
+		]),
+		(18, &[
+			0xd0, 0x69, 0x44, 0x4b, 0x7a, 0x7e, 0x0c, 0xab, 0x09, 0xe2, 0x44, 0x47, 0xd2, 0x4d, 0xeb, 0x1f,
+			0xed, 0xbf
+		]),
+		(19, &[
+			0xe5, 0xdf, 0x13, 0x51, 0xc0, 0x54, 0x4b, 0xa1, 0x35, 0x0b, 0x33, 0x63, 0xcd, 0x8e, 0xf4, 0xbe,
+			0xed, 0xbf, 0x9d
+		]),
+		(20, &[
+			0x9d, 0x84, 0xc8, 0x13, 0xf7, 0x19, 0xaa, 0x2c, 0x7b, 0xe3, 0xf6, 0x61, 0x71, 0xc7, 0xc5, 0xc2,
+			0xed, 0xbf, 0x9d, 0xac
+		])
+	];
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_stealing() {
+		let xts = stealing_xts();
+
+		for &(len, expected) in STEALING.iter() {
+			let input: Vec<u8> = (0..len as u8).collect();
+			assert_eq!(expected, &xts.encrypt(0x123456789a, &input)[..]);
+			assert_eq!(input, xts.decrypt(0x123456789a, expected));
+		}
+	}
+
+	#[test]
+	#[cfg(feature = "std")]
+	fn test_encrypt_from() {
+		let xts = stealing_xts();
+		let input: Vec<u8> = (0..100).map(|i| i as u8).collect();
+		let ciphertext = xts.encrypt(7, &input);
+
+		assert_eq!(&ciphertext[..48], &xts.encrypt_from(7, 0, &input[..48])[..]);
+		assert_eq!(&ciphertext[48..], &xts.encrypt_from(7, 3, &input[48..])[..]);
+		assert_eq!(&input[48..], &xts.decrypt_from(7, 3, &ciphertext[48..])[..]);
+	}
+
+	#[test]
+	fn test_in_place() {
+		let xts = stealing_xts();
+
+		for &(len, expected) in STEALING.iter() {
+			let mut data = [0; 20];
+			for i in 0..len { data[i] = i as u8; }
+			let plaintext = data;
+
+			xts.encrypt_in_place(0x123456789a, 0, &mut data[..len]);
+			assert_eq!(expected, &data[..len]);
+			xts.decrypt_in_place(0x123456789a, 0, &mut data[..len]);
+			assert_eq!(plaintext, data);
+		}
+	}
+}
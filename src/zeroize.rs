@@ -0,0 +1,31 @@
+use core::ptr;
+use core::sync::atomic::{self, Ordering};
+
+// Overwrites secrets before their memory is given back.  The writes are
+// volatile so that the compiler can't remove them as dead stores to memory
+// that is about to be freed
+pub fn zeroize<T: Copy + Default>(values: &mut [T]) {
+	for value in values.iter_mut() {
+		unsafe { ptr::write_volatile(value, T::default()); }
+	}
+
+	// Keep whatever comes next, like the deallocation, from being moved
+	// ahead of the writes
+	atomic::compiler_fence(Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+	use zeroize::*;
+
+	#[test]
+	fn test_zeroize() {
+		let mut words = vec![0xdeadbeef_u32; 12];
+		zeroize(&mut words);
+		assert_eq!(vec![0; 12], words);
+
+		let mut blocks = [[0xa5_u8; 16]; 3];
+		zeroize(&mut blocks);
+		assert_eq!([[0; 16]; 3], blocks);
+	}
+}